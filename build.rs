@@ -0,0 +1,11 @@
+// With the `embree` feature the binary links against libembree3. When Embree isn't installed
+// where the linker looks already, EMBREE_DIR can point at the directory it was installed to
+fn main() {
+    println!("cargo:rerun-if-env-changed=EMBREE_DIR");
+    if std::env::var_os("CARGO_FEATURE_EMBREE").is_none() {
+        return;
+    }
+    if let Some(dir) = std::env::var_os("EMBREE_DIR") {
+        println!("cargo:rustc-link-search=native={}/lib", dir.to_string_lossy());
+    }
+}
@@ -0,0 +1,237 @@
+// Acceleration structures sort the things a ray can hit by where they are, so a ray only tries
+// the few near it. There's more than one way to do it and which is quickest depends on the
+// scene, so the scene can pick one and they're all used the same way.
+
+use crate::bvh::{Aabb, Bvh, BvhOptions};
+use crate::grid::Grid;
+use crate::kdtree::KdTree;
+use glam::Vec3;
+
+// Which structure the objects of a scene are sorted into
+#[derive(Copy, Clone, Default)]
+pub enum Accelerator {
+    #[default]
+    Bvh,
+    // Cuts space in two again and again, putting things that straddle a cut on both sides.
+    // Tries fewer things than a BVH when they overlap a lot, but takes longer to build
+    KdTree,
+    // Quickest to build, for scenes sorted again every frame, but slow when things bunch up
+    Grid
+}
+
+// Shape of a tree, to see how well it's balanced
+pub struct Stats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    pub largest_leaf: usize
+}
+
+pub trait AccelStructure: Send + Sync {
+    // Calls `hit` for things the ray may hit between `min_t` and the closest hit so far, with
+    // the index of the thing and that closest hit. It returns where the ray hits the thing
+    // when that's closer, and the search stops once nothing left can be closer than that.
+    // A thing can come up more than once for the same ray
+    fn traverse(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, hit: &mut dyn FnMut(usize, f32) -> Option<f32>);
+
+    // Like `traverse` for a packet of rays going about the same way, each an origin and a
+    // direction, with the closest hit so far of each in `max_t`. `hit` is called with the ray
+    // as well as the thing. Structures that can walk the packet through all at once do,
+    // others go ray by ray
+    fn traverse_packet(&self, rays: &[(Vec3, Vec3)], min_t: f32, max_t: &mut [f32], hit: &mut dyn FnMut(usize, usize, f32) -> Option<f32>) {
+        for (k, &(origin, direction)) in rays.iter().enumerate() {
+            let mut closest_t = max_t[k];
+            self.traverse(origin, direction, min_t, max_t[k], &mut |thing, max_t| {
+                let t = hit(k, thing, max_t)?;
+                closest_t = closest_t.min(t);
+                Some(t)
+            });
+            max_t[k] = closest_t;
+        }
+    }
+
+    // Whether `hit` says yes to any of the things the ray may hit between `min_t` and `max_t`,
+    // for shadow rays, which only need to know if something's in the way. `hit` is called with
+    // the index of the thing and the search stops as soon as it says yes, however far along
+    // the ray that is
+    fn any_hit(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, hit: &mut dyn FnMut(usize) -> bool) -> bool {
+        let mut found = false;
+        // A closest hit before anything can be is where every structure gives up
+        self.traverse(origin, direction, min_t, max_t, &mut |thing, _| {
+            if found {
+                return None;
+            }
+            found = hit(thing);
+            found.then_some(f32::NEG_INFINITY)
+        });
+        found
+    }
+
+    // Like `any_hit` for a packet of rays, each with its own `max_t`, for the shadow rays from
+    // one point towards the samples of a light. `blocked` says which rays something's in the way
+    // of already, those are left out, and the others are set when `hit` says yes for them.
+    // `hit` is called with the ray as well as the thing
+    fn any_hit_packet(&self, rays: &[(Vec3, Vec3)], min_t: f32, max_t: &[f32], blocked: &mut [bool], hit: &mut dyn FnMut(usize, usize) -> bool) {
+        for (k, &(origin, direction)) in rays.iter().enumerate() {
+            if !blocked[k] {
+                blocked[k] = self.any_hit(origin, direction, min_t, max_t[k], &mut |thing| hit(k, thing));
+            }
+        }
+    }
+
+    fn stats(&self) -> Stats;
+}
+
+impl Accelerator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Accelerator::Bvh => "BVH",
+            Accelerator::KdTree => "kd-tree",
+            Accelerator::Grid => "grid"
+        }
+    }
+
+    // Structure over the boxes, which the things are known by the index of from then on. The
+    // options are only for BVHs
+    pub fn build(&self, boxes: &[Aabb], options: BvhOptions) -> Box<dyn AccelStructure> {
+        match self {
+            Accelerator::Bvh => Box::new(Bvh::new(boxes, options)),
+            Accelerator::KdTree => Box::new(KdTree::new(boxes)),
+            Accelerator::Grid => Box::new(Grid::new(boxes))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    const ACCELERATORS: [Accelerator; 3] = [Accelerator::Bvh, Accelerator::KdTree, Accelerator::Grid];
+
+    fn random_boxes(rng: &mut Rng, count: usize) -> Vec<Aabb> {
+        let mut point = |scale: f32| Vec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32()) * scale;
+        (0..count)
+            .map(|_| {
+                let corner = point(20.0) - Vec3::splat(10.0);
+                Aabb::around_points(&[corner, corner + point(2.0) + Vec3::splat(0.01)])
+            })
+            .collect()
+    }
+
+    // Where the ray first gets into the box, the boxes being the things
+    fn box_hit(b: &Aabb, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<f32> {
+        b.entry_exit(origin, direction.recip(), min_t, max_t).map(|(near, _)| near)
+    }
+
+    fn closest_by_brute_force(boxes: &[Aabb], origin: Vec3, direction: Vec3) -> Option<f32> {
+        boxes.iter().filter_map(|b| box_hit(b, origin, direction, 0.0, f32::MAX)).min_by(f32::total_cmp)
+    }
+
+    fn closest(structure: &dyn AccelStructure, boxes: &[Aabb], origin: Vec3, direction: Vec3) -> Option<f32> {
+        let mut closest = None;
+        structure.traverse(origin, direction, 0.0, f32::MAX, &mut |thing, max_t| {
+            let t = box_hit(&boxes[thing], origin, direction, 0.0, max_t)?;
+            closest = Some(closest.map_or(t, |closest: f32| closest.min(t)));
+            Some(t)
+        });
+        closest
+    }
+
+    // Rays from all around the boxes, in any direction, and along each axis at one of them, where
+    // the direction is 0 on the other axes
+    fn rays(rng: &mut Rng, boxes: &[Aabb]) -> Vec<(Vec3, Vec3)> {
+        let mut rays = vec![];
+        for _ in 0..200 {
+            let mut random = || Vec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32()) * 2.0 - Vec3::ONE;
+            let origin = random() * 15.0;
+            rays.push((origin, random().normalize()));
+        }
+        for (k, b) in boxes.iter().enumerate().take(60) {
+            let axis = k % 3;
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            let mut direction = Vec3::ZERO;
+            direction[axis] = sign;
+            rays.push((b.center() - 30.0 * direction, direction));
+        }
+        rays
+    }
+
+    #[test]
+    fn all_accelerators_find_the_closest_hit() {
+        let mut rng = Rng::new(7);
+        let boxes = random_boxes(&mut rng, 300);
+        let rays = rays(&mut rng, &boxes);
+        for accelerator in ACCELERATORS {
+            let structure = accelerator.build(&boxes, BvhOptions::default());
+            for &(origin, direction) in &rays {
+                assert_eq!(
+                    closest(structure.as_ref(), &boxes, origin, direction),
+                    closest_by_brute_force(&boxes, origin, direction),
+                    "{} from {} going {}",
+                    accelerator.name(),
+                    origin,
+                    direction
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn packets_find_the_same_hits_as_single_rays() {
+        let mut rng = Rng::new(11);
+        let boxes = random_boxes(&mut rng, 300);
+        let rays = rays(&mut rng, &boxes);
+        for accelerator in ACCELERATORS {
+            let structure = accelerator.build(&boxes, BvhOptions::default());
+            for packet in rays.chunks(8) {
+                let mut max_t = vec![f32::MAX; packet.len()];
+                structure.traverse_packet(packet, 0.0, &mut max_t, &mut |k, thing, max_t| box_hit(&boxes[thing], packet[k].0, packet[k].1, 0.0, max_t));
+                for (&(origin, direction), &t) in packet.iter().zip(&max_t) {
+                    let expected = closest_by_brute_force(&boxes, origin, direction).unwrap_or(f32::MAX);
+                    assert_eq!(t, expected, "{} from {} going {}", accelerator.name(), origin, direction);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn all_accelerators_agree_on_whether_anything_is_in_the_way() {
+        let mut rng = Rng::new(13);
+        let boxes = random_boxes(&mut rng, 300);
+        let rays = rays(&mut rng, &boxes);
+        for accelerator in ACCELERATORS {
+            let structure = accelerator.build(&boxes, BvhOptions::default());
+            for &(origin, direction) in &rays {
+                // Only up to 10 along the ray
+                let blocked = structure.any_hit(origin, direction, 0.0, 10.0, &mut |thing| box_hit(&boxes[thing], origin, direction, 0.0, 10.0).is_some());
+                let expected = boxes.iter().any(|b| box_hit(b, origin, direction, 0.0, 10.0).is_some());
+                assert_eq!(blocked, expected, "{} from {} going {}", accelerator.name(), origin, direction);
+            }
+        }
+    }
+
+    #[test]
+    fn packets_agree_on_whether_anything_is_in_the_way() {
+        let mut rng = Rng::new(17);
+        let boxes = random_boxes(&mut rng, 300);
+        let rays = rays(&mut rng, &boxes);
+        for accelerator in ACCELERATORS {
+            let structure = accelerator.build(&boxes, BvhOptions::default());
+            for packet in rays.chunks(8) {
+                // Each ray only goes so far, and the first is taken as blocked to begin with
+                let max_t: Vec<f32> = (0..packet.len()).map(|k| 5.0 + 5.0 * k as f32).collect();
+                let mut blocked = vec![false; packet.len()];
+                blocked[0] = true;
+                structure.any_hit_packet(packet, 0.0, &max_t, &mut blocked, &mut |k, thing| {
+                    assert!(k != 0, "{} tried a blocked ray", accelerator.name());
+                    box_hit(&boxes[thing], packet[k].0, packet[k].1, 0.0, max_t[k]).is_some()
+                });
+                for (k, &(origin, direction)) in packet.iter().enumerate().skip(1) {
+                    let expected = boxes.iter().any(|b| box_hit(b, origin, direction, 0.0, max_t[k]).is_some());
+                    assert_eq!(blocked[k], expected, "{} from {} going {}", accelerator.name(), origin, direction);
+                }
+            }
+        }
+    }
+}
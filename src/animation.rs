@@ -0,0 +1,133 @@
+// Camera fly-throughs: keyframes of where the camera is, what it looks at and its field of view,
+// and the camera in between them at any time, for rendering a sequence of frames. They're read
+// from JSON files like
+//
+//     {
+//         "interpolation": "catmull-rom",
+//         "fps": 24,
+//         "keyframes": [
+//             { "time": 0, "position": [0, 0, 0], "target": [0, 0, 3], "fov": 53 },
+//             { "time": 2.5, "position": [2, 1, 0], "target": [0, 0, 3] }
+//         ]
+//     }
+//
+// Times are in seconds. Keyframes without a field of view keep the one of the keyframe before,
+// and a path without any leaves the one of the camera alone. The interpolation can also be
+// "linear", which goes straight from one keyframe to the next, and `fps` defaults to 24.
+
+use crate::camera::{Camera, Projection};
+use crate::json::Json;
+use glam::Vec3;
+use std::fs;
+use std::ops::{Add, Mul, Sub};
+
+pub struct Keyframe {
+    pub time: f32,
+    pub position: Vec3,
+    pub target: Vec3,
+    pub fov: f32
+}
+
+#[derive(Copy, Clone)]
+pub enum Interpolation {
+    Linear,
+    // Smooth curves through the keyframes, each going the way from the one before to the one
+    // after. They're spaced as if the keyframes were evenly apart in time
+    CatmullRom
+}
+
+pub struct CameraPath {
+    // In order of time
+    pub keyframes: Vec<Keyframe>,
+    pub interpolation: Interpolation,
+    // Frames a second of the sequence
+    pub fps: f32,
+    // Whether the keyframes have a field of view
+    sets_fov: bool
+}
+
+pub fn load(path: &str) -> Result<CameraPath, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let document = Json::parse(&text)?;
+    let interpolation = match document.get("interpolation").and_then(Json::as_str) {
+        None | Some("linear") => Interpolation::Linear,
+        Some("catmull-rom") => Interpolation::CatmullRom,
+        Some(other) => return Err(format!("unknown interpolation {}", other))
+    };
+    let fps = document.get("fps").map_or(Some(24.0), Json::as_f32).filter(|&fps| fps > 0.0).ok_or("fps isn't a number over 0")?;
+
+    let vector = |keyframe: &Json, key: &str| {
+        let values = keyframe.get(key).and_then(Json::as_f32_array).filter(|values| values.len() == 3);
+        values.map(|values| Vec3::from_slice(&values)).ok_or(format!("keyframe without a {}", key))
+    };
+    let elements = document.get("keyframes").map_or(&[][..], Json::elements);
+    let fovs: Vec<Option<f32>> = elements.iter().map(|keyframe| keyframe.get("fov").and_then(Json::as_f32)).collect();
+    let mut fov = fovs.iter().find_map(|&fov| fov);
+    let sets_fov = fov.is_some();
+    let mut keyframes = vec![];
+    for (keyframe, &own_fov) in elements.iter().zip(&fovs) {
+        fov = own_fov.or(fov);
+        keyframes.push(Keyframe {
+            time: keyframe.get("time").and_then(Json::as_f32).ok_or("keyframe without a time")?,
+            position: vector(keyframe, "position")?,
+            target: vector(keyframe, "target")?,
+            fov: fov.unwrap_or(0.0)
+        });
+    }
+    if keyframes.is_empty() {
+        return Err(String::from("no keyframes"));
+    }
+    keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(CameraPath { keyframes, interpolation, fps, sets_fov })
+}
+
+impl CameraPath {
+    // Time of the last keyframe, where the sequence ends
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    // The camera moved along the path to where it is at `time`. Before the first keyframe and
+    // after the last it stays at them. The field of view only changes perspective cameras
+    pub fn camera_at(&self, camera: Camera, time: f32) -> Camera {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len() - 1;
+        // The keyframes either side of the time, and how far it is from the first to the second
+        let next = keyframes.iter().position(|keyframe| keyframe.time > time).unwrap_or(last + 1);
+        let (k, t) = match next {
+            0 => (0, 0.0),
+            next if next > last => (last, 0.0),
+            next => {
+                let (start, end) = (keyframes[next - 1].time, keyframes[next].time);
+                (next - 1, (time - start) / (end - start))
+            }
+        };
+        // The keyframe before those and the one after them too, for Catmull-Rom, the ones at the
+        // ends standing in for them there
+        let around = [k.saturating_sub(1), k, (k + 1).min(last), (k + 2).min(last)].map(|index| &keyframes[index]);
+        let mut camera = Camera {
+            position: interpolate(around.map(|keyframe| keyframe.position), t, self.interpolation),
+            target: interpolate(around.map(|keyframe| keyframe.target), t, self.interpolation),
+            ..camera
+        };
+        if let (true, Projection::Perspective { .. }) = (self.sets_fov, camera.projection) {
+            camera.projection = Projection::Perspective { fov: interpolate(around.map(|keyframe| keyframe.fov), t, self.interpolation) };
+        }
+        camera
+    }
+}
+
+// Value `t` of the way from the second of four values to the third
+fn interpolate<T>(values: [T; 4], t: f32, interpolation: Interpolation) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>
+{
+    let [p0, p1, p2, p3] = values;
+    match interpolation {
+        Interpolation::Linear => p1 + (p2 - p1) * t,
+        Interpolation::CatmullRom => {
+            let (t2, t3) = (t * t, t * t * t);
+            (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+        }
+    }
+}
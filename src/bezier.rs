@@ -0,0 +1,139 @@
+// Bicubic Bézier patches, like the ones the Utah teapot is made of, tessellated into a mesh.
+//
+// Patches are read from .bpt files: the number of patches, then for each one its degrees,
+// which have to be 3 3, and its 16 control points row by row. Each patch is cut into as many
+// triangles as it takes to follow its curves, so flat patches stay cheap.
+
+use crate::shapes::Mesh;
+use glam::{Vec2, Vec3};
+use std::fs;
+
+// Most steps across a patch, however much it bends
+const MAX_STEPS: usize = 64;
+
+pub struct Patch {
+    // Control points by row, going along `v`, and by column, going along `u`
+    pub points: [[Vec3; 4]; 4]
+}
+
+impl Patch {
+    pub fn point(&self, u: f32, v: f32) -> Vec3 {
+        self.evaluate(bernstein(u), bernstein(v))
+    }
+
+    // How fast the surface moves along `u` and along `v`
+    fn derivatives(&self, u: f32, v: f32) -> (Vec3, Vec3) {
+        (self.evaluate(bernstein_derivative(u), bernstein(v)), self.evaluate(bernstein(u), bernstein_derivative(v)))
+    }
+
+    fn evaluate(&self, along_u: [f32; 4], along_v: [f32; 4]) -> Vec3 {
+        let mut point = Vec3::ZERO;
+        for (row, weight_v) in self.points.iter().zip(along_v) {
+            for (control, weight_u) in row.iter().zip(along_u) {
+                point += *control * weight_u * weight_v;
+            }
+        }
+        point
+    }
+
+    // Facing the way `u` cross `v` points. Patches closing into a point, like the top of the
+    // teapot lid, have no tangent there, so the normal is taken from a little way off
+    fn normal(&self, u: f32, v: f32) -> Vec3 {
+        let (du, dv) = self.derivatives(u, v);
+        let normal = du.cross(dv);
+        if normal.length_squared() > 1e-12 {
+            return normal.normalize();
+        }
+        let (u, v) = (u.clamp(1e-3, 1.0 - 1e-3), v.clamp(1e-3, 1.0 - 1e-3));
+        let (du, dv) = self.derivatives(u, v);
+        du.cross(dv).normalize_or_zero()
+    }
+
+    // Steps along `u` and along `v` to keep the triangles within `tolerance` of the surface. A
+    // cubic cut into n straight pieces strays from them by at most 3/4 of the largest second
+    // difference of its control points over n²
+    fn steps(&self, tolerance: f32) -> (usize, usize) {
+        let second_difference = |a: Vec3, b: Vec3, c: Vec3| (a - 2.0 * b + c).length();
+        let mut bend_u: f32 = 0.0;
+        let mut bend_v: f32 = 0.0;
+        for i in 0..4 {
+            for j in 0..2 {
+                let p = &self.points;
+                bend_u = bend_u.max(second_difference(p[i][j], p[i][j + 1], p[i][j + 2]));
+                bend_v = bend_v.max(second_difference(p[j][i], p[j + 1][i], p[j + 2][i]));
+            }
+        }
+        let steps = |bend: f32| ((0.75 * bend / tolerance).sqrt().ceil() as usize).clamp(1, MAX_STEPS);
+        (steps(bend_u), steps(bend_v))
+    }
+}
+
+// Weights of the four control points along a curve at `t`
+fn bernstein(t: f32) -> [f32; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+fn bernstein_derivative(t: f32) -> [f32; 4] {
+    let s = 1.0 - t;
+    [-3.0 * s * s, 3.0 * s * s - 6.0 * t * s, 6.0 * t * s - 3.0 * t * t, 3.0 * t * t]
+}
+
+// Triangles following every patch to within `tolerance`, smooth shaded with the normals of
+// the surface. Texture coordinates go from 0 to 1 across each patch
+pub fn tessellate(patches: &[Patch], tolerance: f32) -> Mesh {
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut triangles = vec![];
+    for patch in patches {
+        let (steps_u, steps_v) = patch.steps(tolerance);
+        let first = vertices.len();
+        for j in 0..=steps_v {
+            for i in 0..=steps_u {
+                let uv = Vec2::new(i as f32 / steps_u as f32, j as f32 / steps_v as f32);
+                vertices.push(patch.point(uv.x, uv.y));
+                normals.push(patch.normal(uv.x, uv.y));
+                uvs.push(uv);
+            }
+        }
+        let index = |i: usize, j: usize| first + j * (steps_u + 1) + i;
+        for j in 0..steps_v {
+            for i in 0..steps_u {
+                triangles.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                triangles.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+    }
+    Mesh::new(vertices, triangles, vec![]).with_normals(normals).with_uvs(uvs)
+}
+
+pub fn load(path: &str) -> Result<Vec<Patch>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn parse(text: &str) -> Result<Vec<Patch>, String> {
+    let mut numbers = text.split_whitespace().map(|word| word.parse::<f32>().map_err(|_| format!("invalid number {}", word)));
+    let mut next = || numbers.next().unwrap_or_else(|| Err(String::from("unexpected end of file")));
+    let count = next()?;
+    if count < 0.0 || count.fract() != 0.0 {
+        return Err(String::from("invalid number of patches"));
+    }
+
+    let mut patches = Vec::with_capacity(count as usize);
+    for _ in 0..count as usize {
+        let degrees = (next()?, next()?);
+        if degrees != (3.0, 3.0) {
+            return Err(format!("patch of degrees {} {}, only bicubic ones are supported", degrees.0, degrees.1));
+        }
+        let mut points = [[Vec3::ZERO; 4]; 4];
+        for row in &mut points {
+            for point in row {
+                *point = Vec3::new(next()?, next()?, next()?);
+            }
+        }
+        patches.push(Patch { points });
+    }
+    Ok(patches)
+}
@@ -0,0 +1,378 @@
+// Bounding volume hierarchy, a tree of boxes around things a ray can hit, so a ray only tries
+// the things whose boxes it goes through instead of all of them.
+//
+// The tree doesn't know what the things are, only their boxes, and refers to them by index.
+// It's used for the objects of the scene and for the triangles of each mesh.
+
+use crate::accel::{AccelStructure, Stats};
+use glam::Vec3;
+
+// Buckets the centers are sorted into along each axis to look for the best split
+const BINS: usize = 12;
+
+// How the things are split between the two children of a node
+#[derive(Copy, Clone)]
+pub enum Split {
+    // In half, at the middle one along the axis their centers spread the most. Quick to build
+    // but rays end up going through a lot of overlapping boxes
+    Median,
+    // Where the surface area heuristic expects rays to try the fewest things, estimated at the
+    // borders between a few bins along each axis (Wald). Slower to build and much quicker to
+    // trace, specially for big meshes
+    Sah
+}
+
+#[derive(Copy, Clone)]
+pub struct BvhOptions {
+    pub split: Split,
+    // Nodes with at most this many things are leaves
+    pub leaf_size: usize
+}
+
+impl Default for BvhOptions {
+    fn default() -> Self {
+        BvhOptions { split: Split::Sah, leaf_size: 4 }
+    }
+}
+
+// Axis aligned box
+#[derive(Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Aabb {
+    // Box around nothing, which grows to fit whatever is added to it
+    pub const EMPTY: Aabb = Aabb { min: Vec3::splat(f32::MAX), max: Vec3::splat(f32::MIN) };
+
+    pub fn around_sphere(center: Vec3, radius: f32) -> Aabb {
+        Aabb { min: center - radius, max: center + radius }
+    }
+
+    pub fn around_points(points: &[Vec3]) -> Aabb {
+        points.iter().fold(Aabb::EMPTY, |bounds, &point| bounds.union(Aabb { min: point, max: point }))
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    // Rays cross a box about in proportion to its surface
+    pub fn surface_area(&self) -> f32 {
+        let size = (self.max - self.min).max(Vec3::ZERO);
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // Where the ray `origin + t * direction` goes into and comes out of the box, if it does
+    // between `min_t` and `max_t`. Takes 1 / direction, which is the same for every box along
+    // the ray
+    pub fn entry_exit(&self, origin: Vec3, inverse_direction: Vec3, min_t: f32, max_t: f32) -> Option<(f32, f32)> {
+        let (mut near, mut far) = (min_t, max_t);
+        for axis in 0..3 {
+            // Running along the sides, where 0 * infinity would make a mess of the slab
+            if inverse_direction[axis].is_infinite() {
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (self.min[axis] - origin[axis]) * inverse_direction[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inverse_direction[axis];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        (near <= far).then_some((near, far))
+    }
+
+    fn entry(&self, origin: Vec3, inverse_direction: Vec3, min_t: f32, max_t: f32) -> Option<f32> {
+        self.entry_exit(origin, inverse_direction, min_t, max_t).map(|(near, _)| near)
+    }
+}
+
+// A node is a leaf holding `count` things, from `first` on in the indices, or has two
+// children when `count` is 0: the next node, and the one at `first`
+#[derive(Copy, Clone)]
+struct Node {
+    bounds: Aabb,
+    first: usize,
+    count: usize
+}
+
+#[derive(Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    // What's in the leaves, as indices into the boxes the tree was built from
+    indices: Vec<usize>
+}
+
+impl Bvh {
+    // Tree over the boxes, which the things are known by the index of from then on
+    pub fn new(boxes: &[Aabb], options: BvhOptions) -> Bvh {
+        let mut bvh = Bvh { nodes: vec![], indices: (0..boxes.len()).collect() };
+        if !boxes.is_empty() {
+            bvh.build(boxes, 0, boxes.len(), options);
+        }
+        bvh
+    }
+
+    // Adds the node for the things at `indices[start..end]`, returning where it is
+    fn build(&mut self, boxes: &[Aabb], start: usize, end: usize, options: BvhOptions) -> usize {
+        let node = self.nodes.len();
+        let bounds = self.indices[start..end].iter().fold(Aabb::EMPTY, |bounds, &index| bounds.union(boxes[index]));
+        self.nodes.push(Node { bounds, first: start, count: end - start });
+        if end - start <= options.leaf_size.max(1) {
+            return node;
+        }
+
+        let centers = self.indices[start..end].iter().fold(Aabb::EMPTY, |centers, &index| {
+            let center = boxes[index].center();
+            centers.union(Aabb { min: center, max: center })
+        });
+        let middle = match options.split {
+            Split::Median => self.median_split(boxes, start, end, centers),
+            Split::Sah => self.sah_split(boxes, start, end, centers).unwrap_or_else(|| self.median_split(boxes, start, end, centers))
+        };
+
+        self.build(boxes, start, middle, options);
+        let right = self.build(boxes, middle, end, options);
+        self.nodes[node] = Node { bounds, first: right, count: 0 };
+        node
+    }
+
+    // Puts the first half of the things along the axis their centers spread the most before
+    // the others, returning where the second half starts
+    fn median_split(&mut self, boxes: &[Aabb], start: usize, end: usize, centers: Aabb) -> usize {
+        let spread = centers.max - centers.min;
+        let axis = if spread.x >= spread.y && spread.x >= spread.z { 0 } else if spread.y >= spread.z { 1 } else { 2 };
+        let middle = (start + end) / 2;
+        self.indices[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            boxes[a].center()[axis].total_cmp(&boxes[b].center()[axis])
+        });
+        middle
+    }
+
+    // Sorts the centers into bins along each axis and tries a split between every two bins,
+    // costing each side as the number of things in it times how likely a ray through the
+    // node is to go through it. Puts the things on the cheapest side of the cheapest split
+    // first, returning where the others start. None when the centers are all in one place
+    fn sah_split(&mut self, boxes: &[Aabb], start: usize, end: usize, centers: Aabb) -> Option<usize> {
+        let spread = centers.max - centers.min;
+        let bin_of = |index: usize, axis: usize| {
+            let offset = (boxes[index].center()[axis] - centers.min[axis]) / spread[axis];
+            ((offset * BINS as f32) as usize).min(BINS - 1)
+        };
+
+        // Axis, last bin on the left and cost of the best split so far
+        let mut best: Option<(usize, usize, f32)> = None;
+        for axis in 0..3 {
+            if spread[axis] <= 0.0 {
+                continue;
+            }
+            let mut counts = [0usize; BINS];
+            let mut bin_bounds = [Aabb::EMPTY; BINS];
+            for &index in &self.indices[start..end] {
+                let bin = bin_of(index, axis);
+                counts[bin] += 1;
+                bin_bounds[bin] = bin_bounds[bin].union(boxes[index]);
+            }
+            // Cost of everything up to each bin from the left, then adding up from the right
+            let mut left_costs = [0.0; BINS];
+            let (mut left, mut left_count) = (Aabb::EMPTY, 0);
+            for bin in 0..BINS - 1 {
+                left = left.union(bin_bounds[bin]);
+                left_count += counts[bin];
+                left_costs[bin] = left.surface_area() * left_count as f32;
+            }
+            let (mut right, mut right_count) = (Aabb::EMPTY, 0);
+            for bin in (1..BINS).rev() {
+                right = right.union(bin_bounds[bin]);
+                right_count += counts[bin];
+                let cost = left_costs[bin - 1] + right.surface_area() * right_count as f32;
+                if right_count < end - start && right_count > 0 && best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, bin - 1, cost));
+                }
+            }
+        }
+
+        let (axis, last_left, _) = best?;
+        let (mut i, mut j) = (start, end);
+        while i < j {
+            if bin_of(self.indices[i], axis) <= last_left {
+                i += 1;
+            } else {
+                j -= 1;
+                self.indices.swap(i, j);
+            }
+        }
+        Some(i)
+    }
+
+    // Calls `hit` for each thing whose box the ray goes through before the closest hit so far,
+    // with the index of the thing and that closest hit. It returns where the ray hits the
+    // thing when that's closer, and boxes past that point are skipped. Nearer children are
+    // visited first, so far away things are often never tried
+    pub fn traverse(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, mut hit: impl FnMut(usize, f32) -> Option<f32>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse_direction = direction.recip();
+        let mut max_t = max_t;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = self.nodes[index];
+            if node.bounds.entry(origin, inverse_direction, min_t, max_t).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for &thing in &self.indices[node.first..node.first + node.count] {
+                    if let Some(t) = hit(thing, max_t) {
+                        max_t = max_t.min(t);
+                    }
+                }
+                continue;
+            }
+            let (left, right) = (index + 1, node.first);
+            let entry = |child: usize| self.nodes[child].bounds.entry(origin, inverse_direction, min_t, max_t).unwrap_or(f32::MAX);
+            // The one visited first goes on the stack last
+            if entry(left) <= entry(right) {
+                stack.extend([right, left]);
+            } else {
+                stack.extend([left, right]);
+            }
+        }
+    }
+
+    // Whether `hit` says yes to any of the things the ray may hit between `min_t` and `max_t`.
+    // Any one will do, so children are taken in whatever order and the walk stops at the first
+    pub fn any_hit(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, mut hit: impl FnMut(usize) -> bool) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inverse_direction = direction.recip();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = self.nodes[index];
+            if node.bounds.entry(origin, inverse_direction, min_t, max_t).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first, index + 1]);
+            } else if self.indices[node.first..node.first + node.count].iter().any(|&thing| hit(thing)) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Walks the tree once for the whole packet, going into a node when any of the rays goes
+    // through its box before its closest hit so far. Children are visited in the order the
+    // rays go on average, which is the same for all of them when they're coherent (Wald)
+    fn traverse_all(&self, rays: &[(Vec3, Vec3)], min_t: f32, max_t: &mut [f32], hit: &mut dyn FnMut(usize, usize, f32) -> Option<f32>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse_directions: Vec<Vec3> = rays.iter().map(|&(_, direction)| direction.recip()).collect();
+        let direction = rays.iter().map(|&(_, direction)| direction).sum::<Vec3>();
+        let crosses = |bounds: &Aabb, k: usize, max_t: f32| bounds.entry(rays[k].0, inverse_directions[k], min_t, max_t).is_some();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = self.nodes[index];
+            if !(0..rays.len()).any(|k| crosses(&node.bounds, k, max_t[k])) {
+                continue;
+            }
+            if node.count > 0 {
+                for (k, closest_t) in max_t.iter_mut().enumerate() {
+                    if !crosses(&node.bounds, k, *closest_t) {
+                        continue;
+                    }
+                    for &thing in &self.indices[node.first..node.first + node.count] {
+                        if let Some(t) = hit(k, thing, *closest_t) {
+                            *closest_t = closest_t.min(t);
+                        }
+                    }
+                }
+                continue;
+            }
+            let (left, right) = (index + 1, node.first);
+            // Left first when the rays go from its side of the split towards the right one
+            let apart = self.nodes[right].bounds.center() - self.nodes[left].bounds.center();
+            if direction.dot(apart) >= 0.0 {
+                stack.extend([right, left]);
+            } else {
+                stack.extend([left, right]);
+            }
+        }
+    }
+
+    // `any_hit` for the whole packet in one walk, going into a node when any of the rays not
+    // blocked yet goes through its box. It stops once they all are
+    fn any_hit_all(&self, rays: &[(Vec3, Vec3)], min_t: f32, max_t: &[f32], blocked: &mut [bool], hit: &mut dyn FnMut(usize, usize) -> bool) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse_directions: Vec<Vec3> = rays.iter().map(|&(_, direction)| direction.recip()).collect();
+        let crosses = |bounds: &Aabb, k: usize| bounds.entry(rays[k].0, inverse_directions[k], min_t, max_t[k]).is_some();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = self.nodes[index];
+            if !(0..rays.len()).any(|k| !blocked[k] && crosses(&node.bounds, k)) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.extend([node.first, index + 1]);
+                continue;
+            }
+            for (k, blocked) in blocked.iter_mut().enumerate() {
+                if !*blocked && crosses(&node.bounds, k) {
+                    *blocked = self.indices[node.first..node.first + node.count].iter().any(|&thing| hit(k, thing));
+                }
+            }
+            if blocked.iter().all(|&blocked| blocked) {
+                return;
+            }
+        }
+    }
+
+    fn tree_stats(&self) -> Stats {
+        let mut stats = Stats { nodes: self.nodes.len(), leaves: 0, depth: 0, largest_leaf: 0 };
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![(0, 1)] };
+        while let Some((index, depth)) = stack.pop() {
+            let node = self.nodes[index];
+            stats.depth = stats.depth.max(depth);
+            if node.count > 0 {
+                stats.leaves += 1;
+                stats.largest_leaf = stats.largest_leaf.max(node.count);
+            } else {
+                stack.extend([(index + 1, depth + 1), (node.first, depth + 1)]);
+            }
+        }
+        stats
+    }
+}
+
+impl AccelStructure for Bvh {
+    fn traverse(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, hit: &mut dyn FnMut(usize, f32) -> Option<f32>) {
+        Bvh::traverse(self, origin, direction, min_t, max_t, hit);
+    }
+
+    fn traverse_packet(&self, rays: &[(Vec3, Vec3)], min_t: f32, max_t: &mut [f32], hit: &mut dyn FnMut(usize, usize, f32) -> Option<f32>) {
+        self.traverse_all(rays, min_t, max_t, hit);
+    }
+
+    fn any_hit(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, hit: &mut dyn FnMut(usize) -> bool) -> bool {
+        Bvh::any_hit(self, origin, direction, min_t, max_t, hit)
+    }
+
+    fn any_hit_packet(&self, rays: &[(Vec3, Vec3)], min_t: f32, max_t: &[f32], blocked: &mut [bool], hit: &mut dyn FnMut(usize, usize) -> bool) {
+        self.any_hit_all(rays, min_t, max_t, blocked, hit);
+    }
+
+    fn stats(&self) -> Stats {
+        self.tree_stats()
+    }
+}
@@ -0,0 +1,417 @@
+// The camera the picture is taken with: where the rays start from and which way they go
+// through each point of the canvas.
+//
+// With a perspective projection the viewport is a rectangle one unit in front of the camera,
+// square to the way it looks, and the canvas is spread over it, its middle where the camera
+// looks. It's as tall as the field of view takes in, and as wide as it takes for the pixels to
+// be square, so a wider canvas sees further to the sides rather than stretching the picture.
+// Ray directions go from the camera to points of the viewport, so a ray gets there at t = 1.
+//
+// An orthographic projection has the canvas spread over a rectangle around the camera instead,
+// and the rays all start from it going the same way, one unit long.
+//
+// A fisheye lens doesn't have a viewport. It sees a circle as tall as the canvas, and how far a
+// point is from the middle of it gives the angle of its ray to the way the camera looks, up to
+// half the field of view at the edge of the circle. The points outside the circle don't see
+// anything. Its rays are one unit long too.
+//
+// A panorama sees all the way around. Across the canvas is the angle around the camera, from
+// behind it on the left, through where it looks in the middle, to behind it again on the right,
+// and up the canvas the angle above or below the horizon, which is square to `up` here. That's
+// the equirectangular pictures panorama viewers and photo spheres take, when the canvas is
+// twice as wide as it's tall.
+//
+// Any of them can have a lens with an aperture, which only keeps things at the focus distance
+// sharp. The rays through a point of the canvas start from all over the aperture instead of a
+// single point, and meet again where the ray from the middle of it is the focus distance along.
+// Tilting the lens, like on a tilt-shift lens, turns the plane they meet on away from square to
+// the camera, which only keeps a slanted strip of the scene sharp, for the look of a miniature.
+// Shifting it moves the picture across instead of turning the camera, so a perspective camera
+// can take in the top of a building while keeping its sides upright.
+//
+// A perspective camera can also have the distortion of a real lens, so renders line up with
+// footage shot through it. Brown-Conrady coefficients, like calibration tools find, move a
+// point of the viewport of a perfect lens to where the real one shows it. Rays go the other
+// way, from where the picture shows a point to where it is, so the distortion is undone for
+// them a step at a time until it comes out where the picture shows it.
+//
+// The shutter stays open for a while around the time of the picture, and each ray is taken at
+// some time while it is. A camera moving meanwhile blurs the picture along the way it goes.
+// Nothing else in a scene moves yet, so the time of a ray doesn't go further than where the
+// camera was then.
+//
+// Only what's between the near and far distances shows up, and reflections and refractions
+// don't go further than the far one either. They're measured in the t of the rays, which is
+// how far in front of the camera for perspective and orthographic cameras, and how far along
+// the ray for the others.
+
+use crate::Canvas;
+use glam::{Quat, Vec2, Vec3};
+use std::f32::consts::PI;
+
+// Field of view of the camera unless it's given another one, as much as the scenes were made
+// to be seen with
+pub const DEFAULT_FOV: f32 = 53.13;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Projection {
+    // Rays spreading out from the camera, seeing `fov` degrees from the bottom of the picture
+    // to the top
+    Perspective { fov: f32 },
+    // Parallel rays from a rectangle `width` units wide, so things look as big however far away
+    // they are. For technical drawings, and for seeing exactly what a shape's intersection code
+    // finds
+    Orthographic { width: f32 },
+    // Rays in a circle of `fov` degrees around the way the camera looks, all the way to the sides
+    // at 180
+    Fisheye { fov: f32, lens: Lens },
+    // Rays all the way around, for 360 degree pictures
+    Panorama
+}
+
+// How the angle of a ray to the middle of a fisheye picture maps to how far from the middle it
+// is. Equidistant keeps angles the same all over it, equisolid keeps how big things look
+#[derive(Copy, Clone, PartialEq)]
+pub enum Lens {
+    Equidistant,
+    Equisolid
+}
+
+// Shape of the aperture, which out of focus highlights take
+#[derive(Copy, Clone, PartialEq)]
+pub enum Bokeh {
+    Disc,
+    // The aperture of a lens with that many blades, a corner at the top
+    Polygon { sides: u32 }
+}
+
+// Radial distortion `k1`, `k2` and `k3`, bulging out the middle of the picture (barrel) when
+// they're under 0 and pinching it in (pincushion) over 0, and tangential distortion `p1` and
+// `p2`, from the lens not being quite square to the sensor
+#[derive(Copy, Clone, PartialEq, Default)]
+pub struct Distortion {
+    pub k1: f32,
+    pub k2: f32,
+    pub k3: f32,
+    pub p1: f32,
+    pub p2: f32
+}
+
+impl Distortion {
+    // Where the lens shows a point of the viewport
+    pub fn distort(&self, p: Vec2) -> Vec2 {
+        let r2 = p.length_squared();
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        let tangential = Vec2::new(
+            2.0 * self.p1 * p.x * p.y + self.p2 * (r2 + 2.0 * p.x * p.x),
+            self.p1 * (r2 + 2.0 * p.y * p.y) + 2.0 * self.p2 * p.x * p.y
+        );
+        p * radial + tangential
+    }
+
+    // The point of the viewport the lens shows at `distorted`. None where the distortion is so
+    // strong it folds over and nothing is shown there
+    pub fn undistort(&self, distorted: Vec2) -> Option<Vec2> {
+        if *self == Distortion::default() {
+            return Some(distorted);
+        }
+        let mut p = distorted;
+        for _ in 0..20 {
+            p += distorted - self.distort(p);
+        }
+        (self.distort(p).distance(distorted) < 1e-4 * (1.0 + distorted.length())).then_some(p)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    // Point in the middle of the picture
+    pub target: Vec3,
+    // Which way is up in the picture. It doesn't have to be square to the way the camera looks,
+    // only not along it
+    pub up: Vec3,
+    pub projection: Projection,
+    // Only for perspective cameras
+    pub distortion: Distortion,
+    // How far the picture is moved right and up, as a share of its height, for perspective and
+    // orthographic cameras
+    pub shift: Vec2,
+    // Radius of the aperture, 0 for a pinhole that has everything in focus
+    pub aperture: f32,
+    pub focus_distance: f32,
+    // Degrees the plane of focus is turned away from the camera on the right and at the top
+    pub tilt: Vec2,
+    pub bokeh: Bokeh,
+    // When the shutter opens and closes, from the time of the picture, and how far the camera
+    // and what it looks at move in a unit of time meanwhile
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+    pub velocity: Vec3,
+    pub near: f32,
+    pub far: f32
+}
+
+impl Camera {
+    // At the origin looking down +Z, with Y up
+    pub fn new(projection: Projection) -> Camera {
+        Camera {
+            position: Vec3::ZERO,
+            target: Vec3::Z,
+            up: Vec3::Y,
+            projection,
+            distortion: Distortion::default(),
+            shift: Vec2::ZERO,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            tilt: Vec2::ZERO,
+            bokeh: Bokeh::Disc,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            velocity: Vec3::ZERO,
+            // Right in front of the lens, about as close as shadow rays start from surfaces
+            near: 0.001,
+            far: f32::MAX
+        }
+    }
+
+    // Directions of length 1 to the right, up and forward in the picture
+    pub fn axes(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = (self.target - self.position).normalize();
+        let right = self.up.cross(forward).normalize();
+        (right, forward.cross(right), forward)
+    }
+
+    // The left and right cameras of a stereo pair, `separation` apart across the picture, both
+    // turned to look at the point `convergence` in front of this one. Things that far away are
+    // seen at the depth of the screen, nearer ones come out of it. Turning the eyes in rather
+    // than shifting their pictures is a little off at the sides, but not so it shows while the
+    // convergence is a lot further than the separation
+    pub fn eyes(&self, separation: f32, convergence: f32) -> (Camera, Camera) {
+        let (right, _, forward) = self.axes();
+        let target = self.position + convergence * forward;
+        let eye = |side: f32| Camera { position: self.position + side * separation / 2.0 * right, target, ..*self };
+        (eye(-1.0), eye(1.0))
+    }
+
+    // Side of a pixel of the canvas on the viewport, or on the rectangle the rays of an
+    // orthographic camera start from, or the radius of the circle of a fisheye lens as 1, or
+    // the angle it takes up in a panorama
+    fn pixel_size(&self, canvas: Canvas) -> f32 {
+        match self.projection {
+            Projection::Perspective { fov } => 2.0 * (fov.to_radians() / 2.0).tan() / canvas.height as f32,
+            Projection::Orthographic { width } => width / canvas.width as f32,
+            Projection::Fisheye { .. } => 2.0 / canvas.height as f32,
+            Projection::Panorama => 2.0 * PI / canvas.width as f32
+        }
+    }
+
+    // Where the ray through a point of the canvas starts and which way it goes, None if the
+    // camera doesn't see through it. The point can be anywhere in a pixel, not just at its
+    // corner
+    pub fn ray(&self, canvas: Canvas, point: Vec2) -> Option<(Vec3, Vec3)> {
+        let (right, up, forward) = self.axes();
+        let point = match self.projection {
+            Projection::Perspective { .. } | Projection::Orthographic { .. } => point + self.shift * canvas.height as f32,
+            _ => point
+        };
+        let point = point * self.pixel_size(canvas);
+        let across = point.x * right + point.y * up;
+        match self.projection {
+            Projection::Perspective { .. } => {
+                let point = self.distortion.undistort(point)?;
+                Some((self.position, point.x * right + point.y * up + forward))
+            },
+            Projection::Orthographic { .. } => Some((self.position + across, forward)),
+            Projection::Fisheye { fov, lens } => {
+                let radius = point.length();
+                if radius > 1.0 {
+                    return None;
+                }
+                let half = fov.to_radians() / 2.0;
+                let angle = match lens {
+                    Lens::Equidistant => radius * half,
+                    Lens::Equisolid => 2.0 * (radius * (half / 2.0).sin()).asin()
+                };
+                let sideways = if radius > 0.0 { across / radius } else { Vec3::ZERO };
+                Some((self.position, angle.cos() * forward + angle.sin() * sideways))
+            },
+            Projection::Panorama => {
+                // Up is along the axis the panorama goes around, rather than square to where
+                // the camera looks
+                let up = self.up.normalize();
+                let forward = right.cross(up);
+                let (around, above) = (point.x, point.y);
+                if above.abs() > PI / 2.0 {
+                    return None;
+                }
+                let level = around.sin() * right + around.cos() * forward;
+                Some((self.position, above.cos() * level + above.sin() * up))
+            }
+        }
+    }
+
+    // Whether the rays through a point of the canvas go different ways, through different
+    // points of the lens or while the camera moves with the shutter open, which blurs the
+    // picture. Otherwise `ray` is all of them
+    pub fn blurs(&self) -> bool {
+        self.aperture > 0.0 || (self.shutter_close > self.shutter_open && self.velocity != Vec3::ZERO)
+    }
+
+    // Like `ray`, but through a point of the aperture and at a time while the shutter is open,
+    // which `sample`, a point of the unit cube, picks
+    pub fn sample_ray(&self, canvas: Canvas, point: Vec2, sample: Vec3) -> Option<(Vec3, Vec3)> {
+        let camera = self.at(self.shutter_open + (self.shutter_close - self.shutter_open) * sample.z);
+        let (origin, direction) = camera.ray(canvas, point)?;
+        if self.aperture == 0.0 {
+            return Some((origin, direction));
+        }
+        let (right, up, forward) = camera.axes();
+        // How far along the ray from the middle of the lens the plane of focus is. Rays that
+        // don't get to a tilted one are focused as if it weren't
+        let mut distance = self.focus_distance;
+        if self.tilt != Vec2::ZERO {
+            let tilt = Vec2::new(self.tilt.x.to_radians(), self.tilt.y.to_radians());
+            let normal = Quat::from_axis_angle(up, -tilt.x) * Quat::from_axis_angle(right, tilt.y) * forward;
+            let t = self.focus_distance * forward.dot(normal) / direction.dot(normal);
+            if t > 0.0 && t.is_finite() {
+                distance = t;
+            }
+        }
+        let focus = origin + distance * direction;
+        let offset = self.aperture * self.aperture_point(sample.truncate());
+        let origin = origin + offset.x * right + offset.y * up;
+        // As long as the ray from the middle, so they get as far for the same t
+        Some((origin, (focus - origin) / distance))
+    }
+
+    // Where the camera is `time` after the time of the picture
+    fn at(&self, time: f32) -> Camera {
+        let moved = time * self.velocity;
+        Camera { position: self.position + moved, target: self.target + moved, ..*self }
+    }
+
+    // Point of the aperture, as if it had a radius of 1, for a point of the unit square. They're
+    // spread evenly over it, since every part of it lets as much light through
+    fn aperture_point(&self, sample: Vec2) -> Vec2 {
+        match self.bokeh {
+            Bokeh::Disc => {
+                let angle = 2.0 * PI * sample.y;
+                sample.x.sqrt() * Vec2::new(angle.cos(), angle.sin())
+            },
+            // A point of one of the triangles from the middle to each side
+            Bokeh::Polygon { sides } => {
+                let sides = sides.max(3) as f32;
+                let side = (sample.x * sides).floor().min(sides - 1.0);
+                let along = sample.x * sides - side;
+                let corner = |k: f32| {
+                    let angle = PI / 2.0 + 2.0 * PI * k / sides;
+                    Vec2::new(angle.cos(), angle.sin())
+                };
+                along.sqrt() * corner(side).lerp(corner(side + 1.0), sample.y)
+            }
+        }
+    }
+
+    // The other way around, the canvas point a point in front of the camera is seen at. None
+    // for points behind it. Only the window draws things over the picture
+    #[cfg(any(feature = "window", test))]
+    pub fn project(&self, canvas: Canvas, p: Vec3) -> Option<(i32, i32)> {
+        let (right, up, forward) = self.axes();
+        let p = p - self.position;
+        let depth = p.dot(forward);
+        let across = Vec2::new(p.dot(right), p.dot(up));
+        let point = match self.projection {
+            Projection::Perspective { .. } | Projection::Orthographic { .. } if depth <= 0.0 => return None,
+            Projection::Perspective { .. } => self.distortion.distort(across / depth) / self.pixel_size(canvas) - self.shift * canvas.height as f32,
+            Projection::Orthographic { .. } => across / self.pixel_size(canvas) - self.shift * canvas.height as f32,
+            Projection::Fisheye { fov, lens } => {
+                let angle = across.length().atan2(depth);
+                let half = fov.to_radians() / 2.0;
+                if angle > half {
+                    return None;
+                }
+                let radius = match lens {
+                    Lens::Equidistant => angle / half,
+                    Lens::Equisolid => (angle / 2.0).sin() / (half / 2.0).sin()
+                };
+                across.normalize_or_zero() * radius / self.pixel_size(canvas)
+            },
+            Projection::Panorama => {
+                let up = self.up.normalize();
+                let forward = right.cross(up);
+                let around = p.dot(right).atan2(p.dot(forward));
+                let above = p.normalize_or_zero().dot(up).clamp(-1.0, 1.0).asin();
+                Vec2::new(around, above) / self.pixel_size(canvas)
+            }
+        };
+        Some((point.x.round() as i32, point.y.round() as i32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANVAS: Canvas = Canvas { width: 80, height: 60 };
+
+    // A camera somewhere other than the origin, looking somewhere other than down +Z
+    fn placed(projection: Projection) -> Camera {
+        let mut camera = Camera::new(projection);
+        camera.position = Vec3::new(1.0, 2.0, -3.0);
+        camera.target = Vec3::new(2.0, 1.5, 1.0);
+        camera
+    }
+
+    // Points along the rays through points of the canvas are seen back at those points. Returns
+    // how many of the points the camera saw through
+    fn round_trip(camera: &Camera) -> usize {
+        let mut seen = 0;
+        for x in (-40..=40).step_by(8) {
+            for y in (-30..=30).step_by(6) {
+                let Some((origin, direction)) = camera.ray(CANVAS, Vec2::new(x as f32, y as f32)) else {
+                    continue;
+                };
+                assert_eq!(camera.project(CANVAS, origin + 3.0 * direction), Some((x, y)), "through ({}, {})", x, y);
+                seen += 1;
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn perspective_round_trip() {
+        let camera = placed(Projection::Perspective { fov: 60.0 });
+        assert_eq!(round_trip(&camera), 11 * 11);
+        // The top of the canvas is half the field of view up from where the camera looks
+        let (_, direction) = camera.ray(CANVAS, Vec2::new(0.0, 30.0)).unwrap();
+        let (_, _, forward) = camera.axes();
+        assert!((direction.angle_between(forward).to_degrees() - 30.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn orthographic_round_trip() {
+        let camera = placed(Projection::Orthographic { width: 8.0 });
+        assert_eq!(round_trip(&camera), 11 * 11);
+        // Every ray goes the same way, from a rectangle as wide as asked for
+        let (left, direction) = camera.ray(CANVAS, Vec2::new(-40.0, 0.0)).unwrap();
+        let (right, other) = camera.ray(CANVAS, Vec2::new(40.0, 0.0)).unwrap();
+        assert_eq!(direction, other);
+        assert!((left.distance(right) - 8.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fisheye_round_trip() {
+        for lens in [Lens::Equidistant, Lens::Equisolid] {
+            let camera = placed(Projection::Fisheye { fov: 180.0, lens });
+            // Only the circle as tall as the canvas is seen through, not its corners
+            let seen = round_trip(&camera);
+            assert!(seen > 60 && seen < 11 * 11, "{}", seen);
+            assert!(camera.ray(CANVAS, Vec2::new(40.0, 30.0)).is_none());
+            // Its edge is half the field of view from where the camera looks
+            let (_, direction) = camera.ray(CANVAS, Vec2::new(0.0, 30.0)).unwrap();
+            let (_, _, forward) = camera.axes();
+            assert!((direction.angle_between(forward).to_degrees() - 90.0).abs() < 1e-3);
+        }
+    }
+}
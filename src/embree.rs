@@ -0,0 +1,177 @@
+// Ray queries on triangle meshes through Intel's Embree, for big meshes where its hand tuned
+// BVHs are quicker than ours. Only built with the `embree` feature, which links against
+// libembree3; without it meshes use the BVH in bvh.rs.
+//
+// These are bindings to the few functions of the C API that are needed, following
+// rtcore.h of Embree 3.
+
+use glam::Vec3;
+use std::ffi::{c_char, c_void};
+use std::ptr;
+use std::sync::OnceLock;
+
+type RtcDevice = *mut c_void;
+type RtcScene = *mut c_void;
+type RtcGeometry = *mut c_void;
+
+const RTC_GEOMETRY_TYPE_TRIANGLE: u32 = 0;
+const RTC_BUFFER_TYPE_INDEX: u32 = 0;
+const RTC_BUFFER_TYPE_VERTEX: u32 = 1;
+const RTC_FORMAT_UINT3: u32 = 0x5003;
+const RTC_FORMAT_FLOAT3: u32 = 0x9003;
+const RTC_INVALID_GEOMETRY_ID: u32 = u32::MAX;
+
+#[repr(C)]
+struct RtcIntersectContext {
+    flags: u32,
+    filter: *const c_void,
+    inst_id: [u32; 1]
+}
+
+// The ray alone, for occlusion queries
+#[repr(C, align(16))]
+struct RtcRay {
+    org: [f32; 3],
+    tnear: f32,
+    dir: [f32; 3],
+    time: f32,
+    tfar: f32,
+    mask: u32,
+    id: u32,
+    flags: u32
+}
+
+#[repr(C, align(16))]
+struct RtcRayHit {
+    org: [f32; 3],
+    tnear: f32,
+    dir: [f32; 3],
+    time: f32,
+    tfar: f32,
+    mask: u32,
+    id: u32,
+    flags: u32,
+    ng: [f32; 3],
+    u: f32,
+    v: f32,
+    prim_id: u32,
+    geom_id: u32,
+    inst_id: [u32; 1]
+}
+
+#[link(name = "embree3")]
+extern "C" {
+    fn rtcNewDevice(config: *const c_char) -> RtcDevice;
+    fn rtcNewScene(device: RtcDevice) -> RtcScene;
+    fn rtcCommitScene(scene: RtcScene);
+    fn rtcReleaseScene(scene: RtcScene);
+    fn rtcNewGeometry(device: RtcDevice, kind: u32) -> RtcGeometry;
+    fn rtcSetNewGeometryBuffer(geometry: RtcGeometry, kind: u32, slot: u32, format: u32, stride: usize, count: usize) -> *mut c_void;
+    fn rtcCommitGeometry(geometry: RtcGeometry);
+    fn rtcAttachGeometry(scene: RtcScene, geometry: RtcGeometry) -> u32;
+    fn rtcReleaseGeometry(geometry: RtcGeometry);
+    fn rtcIntersect1(scene: RtcScene, context: *mut RtcIntersectContext, rayhit: *mut RtcRayHit);
+    fn rtcOccluded1(scene: RtcScene, context: *mut RtcIntersectContext, ray: *mut RtcRay);
+}
+
+// The device holds Embree's threads and memory, and is shared by all the meshes for as long as
+// the program runs
+struct Device(RtcDevice);
+
+static DEVICE: OnceLock<Device> = OnceLock::new();
+
+// A triangle mesh built into an Embree scene of its own
+pub struct TriangleScene {
+    scene: RtcScene
+}
+
+// Embree devices and scenes can be used from any number of threads, scenes once they're
+// committed
+unsafe impl Send for Device {}
+unsafe impl Sync for Device {}
+unsafe impl Send for TriangleScene {}
+unsafe impl Sync for TriangleScene {}
+
+impl TriangleScene {
+    pub fn new(vertices: &[Vec3], triangles: &[[usize; 3]]) -> TriangleScene {
+        let device = DEVICE.get_or_init(|| {
+            let device = unsafe { rtcNewDevice(ptr::null()) };
+            assert!(!device.is_null(), "couldn't create an Embree device");
+            Device(device)
+        }).0;
+        unsafe {
+            let scene = rtcNewScene(device);
+            // Embree won't make buffers with nothing in them, a mesh without triangles is left
+            // an empty scene that no ray hits
+            if triangles.is_empty() {
+                rtcCommitScene(scene);
+                return TriangleScene { scene };
+            }
+            let geometry = rtcNewGeometry(device, RTC_GEOMETRY_TYPE_TRIANGLE);
+
+            // Embree reads past the last vertex with SIMD loads, so it's given room for one more
+            let vertex_buffer = rtcSetNewGeometryBuffer(geometry, RTC_BUFFER_TYPE_VERTEX, 0, RTC_FORMAT_FLOAT3, 12, vertices.len() + 1) as *mut [f32; 3];
+            for (k, vertex) in vertices.iter().enumerate() {
+                *vertex_buffer.add(k) = vertex.to_array();
+            }
+            let index_buffer = rtcSetNewGeometryBuffer(geometry, RTC_BUFFER_TYPE_INDEX, 0, RTC_FORMAT_UINT3, 12, triangles.len()) as *mut [u32; 3];
+            for (k, triangle) in triangles.iter().enumerate() {
+                *index_buffer.add(k) = triangle.map(|index| index as u32);
+            }
+
+            rtcCommitGeometry(geometry);
+            rtcAttachGeometry(scene, geometry);
+            rtcReleaseGeometry(geometry);
+            rtcCommitScene(scene);
+            TriangleScene { scene }
+        }
+    }
+
+    // Closest triangle the ray hits between `min_t` and `max_t`, and where along the ray.
+    // Embree only looks ahead of the origin, so `min_t` can't be negative
+    pub fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<(usize, f32)> {
+        let mut context = RtcIntersectContext { flags: 0, filter: ptr::null(), inst_id: [RTC_INVALID_GEOMETRY_ID] };
+        let mut ray = RtcRayHit {
+            org: origin.to_array(),
+            tnear: min_t,
+            dir: direction.to_array(),
+            time: 0.0,
+            tfar: max_t,
+            mask: u32::MAX,
+            id: 0,
+            flags: 0,
+            ng: [0.0; 3],
+            u: 0.0,
+            v: 0.0,
+            prim_id: RTC_INVALID_GEOMETRY_ID,
+            geom_id: RTC_INVALID_GEOMETRY_ID,
+            inst_id: [RTC_INVALID_GEOMETRY_ID]
+        };
+        unsafe { rtcIntersect1(self.scene, &mut context, &mut ray) };
+        (ray.geom_id != RTC_INVALID_GEOMETRY_ID).then_some((ray.prim_id as usize, ray.tfar))
+    }
+
+    // Whether any triangle is in the way between `min_t` and `max_t`, which Embree answers
+    // quicker than the closest hit. It marks a blocked ray by setting its `tfar` to -infinity
+    pub fn occluded(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> bool {
+        let mut context = RtcIntersectContext { flags: 0, filter: ptr::null(), inst_id: [RTC_INVALID_GEOMETRY_ID] };
+        let mut ray = RtcRay {
+            org: origin.to_array(),
+            tnear: min_t,
+            dir: direction.to_array(),
+            time: 0.0,
+            tfar: max_t,
+            mask: u32::MAX,
+            id: 0,
+            flags: 0
+        };
+        unsafe { rtcOccluded1(self.scene, &mut context, &mut ray) };
+        ray.tfar == f32::NEG_INFINITY
+    }
+}
+
+impl Drop for TriangleScene {
+    fn drop(&mut self) {
+        unsafe { rtcReleaseScene(self.scene) };
+    }
+}
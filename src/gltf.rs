@@ -0,0 +1,489 @@
+// Importer for glTF 2.0 scenes, either .gltf files (JSON with the binary data in other files or
+// embedded as base64) or single binary .glb files.
+//
+// The meshes of the default scene come in with the transforms of the nodes above them baked in,
+// metallic-roughness materials become Pbr materials, and perspective cameras are kept so the
+// scene can be seen through them. glTF is right handed with cameras looking down -Z, so Z is
+// flipped to match the camera of the raytracer, which looks down +Z. Textures, skins, morph
+// targets, animations and lights are left out.
+
+use crate::json::Json;
+use crate::materials::{Material, MaterialId, Pbr};
+use crate::shapes::Mesh;
+use glam::{Mat4, Quat, Vec2, Vec3};
+use std::fs;
+use std::path::Path;
+
+pub struct Model {
+    // Name, geometry and material of each primitive of the meshes in the scene
+    pub meshes: Vec<(String, Mesh, MaterialId)>,
+    pub cameras: Vec<Camera>
+}
+
+pub struct Camera {
+    pub name: String,
+    // From the space of the camera, looking down +Z with +Y up, to the world
+    pub transform: Mat4,
+    // Vertical field of view, in radians
+    pub yfov: f32,
+    // Width over height of the picture, None when it's up to the window
+    pub aspect_ratio: Option<f32>
+}
+
+// Reads the scene at `path`, adding its materials to `materials`
+pub fn load(path: &str, materials: &mut Vec<Box<dyn Material>>) -> Result<Model, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(bytes, Path::new(path).parent().unwrap_or(Path::new("")), materials)
+}
+
+// The scene in `bytes`, either a .gltf or a .glb file, whose other files are in `directory`
+fn parse(bytes: Vec<u8>, directory: &Path, materials: &mut Vec<Box<dyn Material>>) -> Result<Model, String> {
+    let (text, binary) = if bytes.starts_with(b"glTF") {
+        parse_glb(&bytes)?
+    } else {
+        (String::from_utf8(bytes).map_err(|_| "not a glTF file")?, None)
+    };
+    let document = Json::parse(&text)?;
+    let version = document.get("asset").and_then(|asset| asset.get("version")).and_then(Json::as_str);
+    if !version.is_some_and(|version| version.starts_with("2.")) {
+        return Err(String::from("only glTF 2.0 is supported"));
+    }
+    let reader = Reader {
+        document: &document,
+        buffers: load_buffers(&document, binary, directory)?
+    };
+
+    let ids: Vec<MaterialId> = list(&document, "materials")
+        .iter()
+        .map(|material| {
+            materials.push(Box::new(pbr_material(material)));
+            materials.len() - 1
+        })
+        .collect();
+    // Primitives without a material get the default one of the spec, only added if needed
+    let mut default_material = None;
+
+    // Nodes of the default scene, or the ones no other node has as a child without scenes
+    let nodes = list(&document, "nodes");
+    let scenes = list(&document, "scenes");
+    let roots: Vec<usize> = if scenes.is_empty() {
+        let children: Vec<usize> = nodes.iter().flat_map(|node| list(node, "children")).filter_map(Json::as_usize).collect();
+        (0..nodes.len()).filter(|index| !children.contains(index)).collect()
+    } else {
+        let scene = document.get("scene").and_then(Json::as_usize).unwrap_or(0);
+        let scene = scenes.get(scene).ok_or("invalid default scene")?;
+        list(scene, "nodes").iter().filter_map(Json::as_usize).collect()
+    };
+
+    // Flips Z, from the right handed space of glTF to the one of the raytracer
+    let flip = Mat4::from_scale(Vec3::new(1.0, 1.0, -1.0));
+    let mut model = Model { meshes: vec![], cameras: vec![] };
+    // Backwards, so they come off it in the order of the file and the cameras are numbered by it
+    let mut stack: Vec<(usize, Mat4)> = roots.into_iter().rev().map(|root| (root, Mat4::IDENTITY)).collect();
+    let mut visited = 0;
+    while let Some((index, parent)) = stack.pop() {
+        // Nodes form a tree, a broken file could make us go around in circles
+        visited += 1;
+        if visited > nodes.len() {
+            return Err(String::from("the nodes don't form a tree"));
+        }
+        let node = nodes.get(index).ok_or_else(|| format!("invalid node {}", index))?;
+        let transform = parent * local_transform(node)?;
+        let name = node.get("name").and_then(Json::as_str).map(String::from);
+
+        if let Some(mesh) = node.get("mesh").and_then(Json::as_usize) {
+            let mesh = list(&document, "meshes").get(mesh).ok_or("invalid mesh")?;
+            let name = name.clone().or(mesh.get("name").and_then(Json::as_str).map(String::from)).unwrap_or(format!("node {}", index));
+            let primitives = list(mesh, "primitives");
+            for (k, primitive) in primitives.iter().enumerate() {
+                let mesh = match reader.primitive(primitive)? {
+                    Some(mesh) => mesh.transformed(flip * transform),
+                    None => continue
+                };
+                let material = match primitive.get("material").and_then(Json::as_usize) {
+                    Some(material) => *ids.get(material).ok_or("invalid material")?,
+                    None => *default_material.get_or_insert_with(|| {
+                        materials.push(Box::new(pbr_material(&Json::Object(vec![]))));
+                        materials.len() - 1
+                    })
+                };
+                let name = if primitives.len() > 1 { format!("{} {}", name, k + 1) } else { name.clone() };
+                model.meshes.push((name, mesh, material));
+            }
+        }
+
+        if let Some(camera) = node.get("camera").and_then(Json::as_usize) {
+            let camera = list(&document, "cameras").get(camera).ok_or("invalid camera")?;
+            // Orthographic cameras aren't supported
+            if let Some(perspective) = camera.get("perspective") {
+                model.cameras.push(Camera {
+                    name: name.clone().unwrap_or(format!("camera {}", model.cameras.len() + 1)),
+                    transform: flip * transform * flip,
+                    yfov: perspective.get("yfov").and_then(Json::as_f32).ok_or("camera without a field of view")?,
+                    aspect_ratio: perspective.get("aspectRatio").and_then(Json::as_f32)
+                });
+            }
+        }
+
+        for child in list(node, "children").iter().rev() {
+            stack.push((child.as_usize().ok_or("invalid child")?, transform));
+        }
+    }
+    Ok(model)
+}
+
+// Elements of the array `key` of an object, empty when it doesn't have one
+fn list<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key).map(Json::elements).unwrap_or(&[])
+}
+
+// The JSON and binary chunks of a .glb file
+fn parse_glb(bytes: &[u8]) -> Result<(String, Option<Vec<u8>>), String> {
+    let word = |offset: usize| -> Result<u32, String> {
+        let word = bytes.get(offset..offset + 4).ok_or("unexpected end of file")?;
+        Ok(u32::from_le_bytes(word.try_into().unwrap()))
+    };
+    if word(4)? != 2 {
+        return Err(String::from("only glTF 2.0 is supported"));
+    }
+    let length = (word(8)? as usize).min(bytes.len());
+    let mut text = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let size = word(offset)? as usize;
+        let kind = word(offset + 4)?;
+        let data = bytes.get(offset + 8..offset + 8 + size).ok_or("unexpected end of file")?;
+        match kind {
+            // "JSON"
+            0x4E4F534A => text = Some(String::from_utf8_lossy(data).to_string()),
+            // "BIN\0"
+            0x004E4942 => binary = Some(data.to_vec()),
+            _ => {}
+        }
+        offset += 8 + size;
+    }
+    Ok((text.ok_or("missing JSON chunk")?, binary))
+}
+
+// The contents of every buffer, from data URIs, files next to the scene or the binary chunk
+fn load_buffers(document: &Json, mut binary: Option<Vec<u8>>, directory: &Path) -> Result<Vec<Vec<u8>>, String> {
+    list(document, "buffers")
+        .iter()
+        .map(|buffer| match buffer.get("uri").and_then(Json::as_str) {
+            Some(uri) if uri.starts_with("data:") => {
+                let data = uri.split_once(";base64,").ok_or("only base64 data URIs are supported")?.1;
+                decode_base64(data)
+            },
+            Some(uri) => {
+                let path = directory.join(uri);
+                fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+            },
+            None => binary.take().ok_or_else(|| String::from("buffer without data"))
+        })
+        .collect()
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let value = |byte: u8| match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a' + 26),
+        b'0'..=b'9' => Some(byte - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None
+    };
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for byte in text.bytes().filter(|&byte| byte != b'=' && !byte.is_ascii_whitespace()) {
+        bits = bits << 6 | value(byte).ok_or("invalid base64 data")? as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+// A node places its contents with either a whole matrix or a translation, rotation and scale
+fn local_transform(node: &Json) -> Result<Mat4, String> {
+    if let Some(matrix) = node.get("matrix") {
+        let values = matrix.as_f32_array().filter(|values| values.len() == 16).ok_or("invalid node matrix")?;
+        return Ok(Mat4::from_cols_slice(&values));
+    }
+    let vector = |key: &str, length: usize| -> Result<Option<Vec<f32>>, String> {
+        match node.get(key) {
+            Some(value) => Ok(Some(value.as_f32_array().filter(|values| values.len() == length).ok_or_else(|| format!("invalid node {}", key))?)),
+            None => Ok(None)
+        }
+    };
+    let translation = vector("translation", 3)?.map_or(Vec3::ZERO, |t| Vec3::from_slice(&t));
+    let rotation = vector("rotation", 4)?.map_or(Quat::IDENTITY, |r| Quat::from_xyzw(r[0], r[1], r[2], r[3]).normalize());
+    let scale = vector("scale", 3)?.map_or(Vec3::ONE, |s| Vec3::from_slice(&s));
+    Ok(Mat4::from_scale_rotation_translation(scale, rotation, translation))
+}
+
+// Metallic-roughness material, with the defaults of the spec for what's left out
+fn pbr_material(material: &Json) -> Pbr {
+    let factors = material.get("pbrMetallicRoughness");
+    let factor = |key: &str| factors.and_then(|factors| factors.get(key)).and_then(Json::as_f32).unwrap_or(1.0);
+    let base_color = factors
+        .and_then(|factors| factors.get("baseColorFactor"))
+        .and_then(Json::as_f32_array)
+        .filter(|values| values.len() == 4)
+        .map_or(Vec3::ONE, |values| Vec3::new(values[0], values[1], values[2]));
+    let emissive = material.get("emissiveFactor").and_then(Json::as_f32_array).filter(|values| values.len() == 3);
+    let strength = material
+        .get("extensions")
+        .and_then(|extensions| extensions.get("KHR_materials_emissive_strength"))
+        .and_then(|extension| extension.get("emissiveStrength"))
+        .and_then(Json::as_f32)
+        .unwrap_or(1.0);
+    Pbr {
+        base_color: base_color.into(),
+        metallic: factor("metallicFactor").into(),
+        roughness: factor("roughnessFactor").into(),
+        emission: emissive.map_or(Vec3::ZERO, |values| Vec3::from_slice(&values)) * strength,
+        ..Pbr::default()
+    }
+}
+
+struct Reader<'a> {
+    document: &'a Json,
+    buffers: Vec<Vec<u8>>
+}
+
+impl<'a> Reader<'a> {
+    // Mesh of a primitive made of triangles, None for points, lines and strips
+    fn primitive(&self, primitive: &Json) -> Result<Option<Mesh>, String> {
+        if primitive.get("mode").and_then(Json::as_usize).unwrap_or(4) != 4 {
+            return Ok(None);
+        }
+        let attribute = |name: &str| primitive.get("attributes").and_then(|attributes| attributes.get(name)).and_then(Json::as_usize);
+        let (positions, components) = self.accessor(attribute("POSITION").ok_or("primitive without positions")?)?;
+        if components != 3 {
+            return Err(String::from("positions must be 3D vectors"));
+        }
+        let vertices: Vec<Vec3> = positions.chunks(3).map(|p| Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32)).collect();
+
+        // Without indices every three vertices make a triangle
+        let indices: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+            Some(accessor) => self.accessor(accessor)?.0.into_iter().map(|index| index as usize).collect(),
+            None => (0..vertices.len()).collect()
+        };
+        if indices.iter().any(|&index| index >= vertices.len()) {
+            return Err(String::from("vertex index out of range"));
+        }
+        let triangles = indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect();
+
+        // Values of an attribute with one element of `size` components per vertex, if it's there
+        let vertex_count = vertices.len();
+        let per_vertex = |name: &str, size: usize| -> Result<Option<Vec<f64>>, String> {
+            let Some(accessor) = attribute(name) else {
+                return Ok(None);
+            };
+            let (values, components) = self.accessor(accessor)?;
+            if components != size || values.len() != vertex_count * size {
+                return Err(format!("{} must have a {}D vector per vertex", name, size));
+            }
+            Ok(Some(values))
+        };
+        let mut mesh = Mesh::new(vertices, triangles, vec![]);
+        if let Some(normals) = per_vertex("NORMAL", 3)? {
+            mesh = mesh.with_normals(normals.chunks(3).map(|n| Vec3::new(n[0] as f32, n[1] as f32, n[2] as f32)).collect());
+        }
+        // Texture coordinates start at the top left corner of the image in glTF
+        if let Some(uvs) = per_vertex("TEXCOORD_0", 2)? {
+            mesh = mesh.with_uvs(uvs.chunks(2).map(|uv| Vec2::new(uv[0] as f32, 1.0 - uv[1] as f32)).collect());
+        }
+        Ok(Some(mesh))
+    }
+
+    // The values of an accessor, one after the other, and how many there are per element
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize), String> {
+        let accessor = list(self.document, "accessors").get(index).ok_or_else(|| format!("invalid accessor {}", index))?;
+        let count = accessor.get("count").and_then(Json::as_usize).ok_or("accessor without a count")?;
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(String::from("invalid accessor type"))
+        };
+        if accessor.get("sparse").is_some() {
+            return Err(String::from("sparse accessors are not supported"));
+        }
+        let component_type = accessor.get("componentType").and_then(Json::as_usize).ok_or("accessor without a component type")?;
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(format!("invalid component type {}", component_type))
+        };
+        // Integers stand for values in [0, 1], or [-1, 1] when signed
+        let normalized = accessor.get("normalized").and_then(Json::as_bool).unwrap_or(false);
+
+        // Accessors without a buffer view are all zeros
+        let view = match accessor.get("bufferView").and_then(Json::as_usize) {
+            Some(view) => list(self.document, "bufferViews").get(view).ok_or("invalid buffer view")?,
+            None => return Ok((vec![0.0; count * components], components))
+        };
+        let buffer = view.get("buffer").and_then(Json::as_usize).and_then(|buffer| self.buffers.get(buffer)).ok_or("invalid buffer")?;
+        let view_offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let view_length = view.get("byteLength").and_then(Json::as_usize).ok_or("buffer view without a length")?;
+        let data = buffer.get(view_offset..view_offset + view_length).ok_or("buffer view out of range")?;
+        let offset = accessor.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let stride = view.get("byteStride").and_then(Json::as_usize).unwrap_or(components * size);
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let start = offset + element * stride + component * size;
+                let bytes = data.get(start..start + size).ok_or("accessor out of range")?;
+                let value = match component_type {
+                    5120 => bytes[0] as i8 as f64,
+                    5121 => bytes[0] as f64,
+                    5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64
+                };
+                let value = match (normalized, component_type) {
+                    (true, 5120) => (value / 127.0).max(-1.0),
+                    (true, 5121) => value / 255.0,
+                    (true, 5122) => (value / 32767.0).max(-1.0),
+                    (true, 5123) => value / 65535.0,
+                    _ => value
+                };
+                values.push(value);
+            }
+        }
+        Ok((values, components))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A triangle in the XY plane, as three VEC3 floats
+    fn triangle_bytes() -> Vec<u8> {
+        [0.0f32, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0, 1.0].iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    // A document with one mesh whose positions are accessor 0, over the 36 bytes of buffer 0,
+    // plus `extra` accessors and attributes
+    fn document(buffer: &str, accessors: &str, attributes: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "nodes": [{{ "mesh": 0 }}],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 {} }} }}] }}],
+                "buffers": [{}],
+                "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+                "accessors": [{{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }} {}]
+            }}"#,
+            attributes, buffer, accessors
+        )
+    }
+
+    fn glb(json: &str, binary: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        // Chunks are padded to four bytes, JSON with spaces
+        while json.len() % 4 != 0 {
+            json.push(b' ');
+        }
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend((12 + 8 + json.len() as u32 + 8 + binary.len() as u32).to_le_bytes());
+        bytes.extend((json.len() as u32).to_le_bytes());
+        bytes.extend(b"JSON");
+        bytes.extend(json);
+        bytes.extend((binary.len() as u32).to_le_bytes());
+        bytes.extend(b"BIN\0");
+        bytes.extend(binary);
+        bytes
+    }
+
+    fn parse_bytes(bytes: Vec<u8>) -> Result<Model, String> {
+        parse(bytes, Path::new(""), &mut vec![])
+    }
+
+    // Z is flipped on the way in, which turns the triangle around too
+    fn expected_triangle() -> [Vec3; 3] {
+        [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, -1.0), Vec3::new(1.0, 0.0, -1.0)]
+    }
+
+    fn data_uri(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (k, &byte)| bits | (byte as u32) << (16 - 8 * k));
+            for k in 0..=chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * k) & 63) as usize] as char);
+            }
+        }
+        while text.len() % 4 != 0 {
+            text.push('=');
+        }
+        format!(r#"{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}"#, bytes.len(), text)
+    }
+
+    #[test]
+    fn decodes_base64_with_and_without_padding() {
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        assert_eq!(decode_base64("TW\nFu").unwrap(), b"Man");
+        assert!(decode_base64("TW*u").is_err());
+    }
+
+    #[test]
+    fn reads_buffers_from_base64_data_uris() {
+        let text = document(&data_uri(&triangle_bytes()), "", "");
+        let model = parse_bytes(text.into_bytes()).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].1.triangle_corners(), vec![expected_triangle()]);
+    }
+
+    #[test]
+    fn reads_the_json_and_binary_chunks_of_glb_files() {
+        let bytes = glb(&document(r#"{ "byteLength": 36 }"#, "", ""), &triangle_bytes());
+        let (text, binary) = parse_glb(&bytes).unwrap();
+        assert!(text.trim_end().ends_with('}'));
+        assert_eq!(binary.unwrap(), triangle_bytes());
+        let model = parse_bytes(bytes).unwrap();
+        assert_eq!(model.meshes[0].1.triangle_corners(), vec![expected_triangle()]);
+    }
+
+    #[test]
+    fn truncated_glb_files_are_errors() {
+        let bytes = glb(&document(r#"{ "byteLength": 36 }"#, "", ""), &triangle_bytes());
+        assert!(parse_glb(&bytes[..bytes.len() - 10]).is_err());
+        assert!(parse_glb(&bytes[..6]).is_err());
+    }
+
+    #[test]
+    fn normals_have_to_be_one_vec3_per_vertex() {
+        let buffer = data_uri(&triangle_bytes());
+        let valid = document(&buffer, r#", { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }"#, r#", "NORMAL": 1"#);
+        assert_eq!(parse_bytes(valid.into_bytes()).unwrap().meshes[0].1.vertex_normals().len(), 3);
+        let too_few = document(&buffer, r#", { "bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3" }"#, r#", "NORMAL": 1"#);
+        assert!(parse_bytes(too_few.into_bytes()).is_err());
+        let not_vec3 = document(&buffer, r#", { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC2" }"#, r#", "NORMAL": 1"#);
+        assert!(parse_bytes(not_vec3.into_bytes()).is_err());
+    }
+
+    #[test]
+    fn texture_coordinates_have_to_be_one_vec2_per_vertex() {
+        let buffer = data_uri(&triangle_bytes());
+        let valid = document(&buffer, r#", { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC2" }"#, r#", "TEXCOORD_0": 1"#);
+        assert_eq!(parse_bytes(valid.into_bytes()).unwrap().meshes[0].1.vertex_uvs().len(), 3);
+        let too_many = document(&buffer, r#", { "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC2" }"#, r#", "TEXCOORD_0": 1"#);
+        assert!(parse_bytes(too_many.into_bytes()).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+// Uniform grid, the box around the things a ray can hit cut into equal cells, each with a list
+// of the things whose boxes overlap it. It's built in a single pass over the things, much
+// quicker than a tree, so it suits scenes that change from frame to frame and are sorted
+// again every time. Trees do better when things are spread unevenly, with most cells empty.
+//
+// Like the trees it only knows the boxes of the things and refers to them by index.
+
+use crate::accel::{AccelStructure, Stats};
+use crate::bvh::Aabb;
+use glam::Vec3;
+
+// Cells per thing, more means fewer things to try per cell but more cells to walk through
+// (Cleary and Wyvill)
+const CELLS_PER_THING: f32 = 3.0;
+// Most cells along each side of the grid
+const MAX_RESOLUTION: usize = 128;
+
+// The things in cell k are the ones at `cell_things[cell_starts[k]..cell_starts[k + 1]]`
+pub struct Grid {
+    bounds: Aabb,
+    resolution: [usize; 3],
+    cell_starts: Vec<usize>,
+    cell_things: Vec<usize>
+}
+
+impl Grid {
+    pub fn new(boxes: &[Aabb]) -> Grid {
+        Grid::with_cells(boxes, CELLS_PER_THING, MAX_RESOLUTION)
+    }
+
+    // With about `cells_per_thing` cells for each thing, and at most `max_resolution` along each
+    // side, for things that are small and many, like the points of a scan
+    pub fn with_cells(boxes: &[Aabb], cells_per_thing: f32, max_resolution: usize) -> Grid {
+        let bounds = boxes.iter().fold(Aabb::EMPTY, |bounds, &b| bounds.union(b));
+        if boxes.is_empty() {
+            return Grid { bounds, resolution: [1; 3], cell_starts: vec![0, 0], cell_things: vec![] };
+        }
+        // Cells about cube shaped, flat scenes given some thickness so they don't end up
+        // with tiny ones
+        let extent = (bounds.max - bounds.min).max(Vec3::splat((bounds.max - bounds.min).max_element() / max_resolution as f32)).max(Vec3::splat(1e-6));
+        let cell = (extent.x * extent.y * extent.z / (cells_per_thing * boxes.len() as f32)).cbrt();
+        let resolution = [0, 1, 2].map(|axis| ((extent[axis] / cell).ceil() as usize).clamp(1, max_resolution));
+        let mut grid = Grid { bounds, resolution, cell_starts: vec![], cell_things: vec![] };
+
+        // Count the things of each cell first, so they can be laid out one cell after the other
+        // without a list per cell
+        let cells = resolution[0] * resolution[1] * resolution[2];
+        let mut counts = vec![0; cells + 1];
+        grid.for_each_cell(boxes, |_, cell| counts[cell + 1] += 1);
+        for cell in 0..cells {
+            counts[cell + 1] += counts[cell];
+        }
+        let mut cell_things = vec![0; counts[cells]];
+        grid.for_each_cell(boxes, |thing, cell| {
+            cell_things[counts[cell]] = thing;
+            counts[cell] += 1;
+        });
+        // Each count has moved up to where the next cell starts
+        counts.rotate_right(1);
+        counts[0] = 0;
+        grid.cell_starts = counts;
+        grid.cell_things = cell_things;
+        grid
+    }
+
+    // Calls `f` with every thing and each cell its box overlaps
+    fn for_each_cell(&self, boxes: &[Aabb], mut f: impl FnMut(usize, usize)) {
+        for (thing, b) in boxes.iter().enumerate() {
+            let (low, high) = (self.cell_of(b.min), self.cell_of(b.max));
+            for z in low[2]..=high[2] {
+                for y in low[1]..=high[1] {
+                    for x in low[0]..=high[0] {
+                        f(thing, self.index([x, y, z]));
+                    }
+                }
+            }
+        }
+    }
+
+    // Box around all the things
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    fn cell_size(&self) -> Vec3 {
+        (self.bounds.max - self.bounds.min) / Vec3::new(self.resolution[0] as f32, self.resolution[1] as f32, self.resolution[2] as f32)
+    }
+
+    // Cell the point is in, the nearest one for points outside the grid
+    fn cell_of(&self, p: Vec3) -> [usize; 3] {
+        let cell = (p - self.bounds.min) / self.cell_size().max(Vec3::splat(1e-12));
+        [0, 1, 2].map(|axis| (cell[axis].max(0.0) as usize).min(self.resolution[axis] - 1))
+    }
+
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.resolution[1] + y) * self.resolution[0] + x
+    }
+
+    fn things(&self, cell: [usize; 3]) -> &[usize] {
+        let index = self.index(cell);
+        &self.cell_things[self.cell_starts[index]..self.cell_starts[index + 1]]
+    }
+}
+
+impl AccelStructure for Grid {
+    // Walks the cells along the ray from where it enters the grid, in the order it crosses
+    // them (Amanatides and Woo). A thing can stick out of the cell it was found in, so the
+    // walk only stops once the closest hit so far is inside the cells already walked
+    fn traverse(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, hit: &mut dyn FnMut(usize, f32) -> Option<f32>) {
+        if self.cell_things.is_empty() {
+            return;
+        }
+        let inverse = direction.recip();
+        let Some((near, far)) = self.bounds.entry_exit(origin, inverse, min_t, max_t) else {
+            return;
+        };
+
+        let size = self.cell_size();
+        let mut cell = self.cell_of(origin + near * direction);
+        let mut next = Vec3::splat(f32::MAX);
+        let mut delta = Vec3::splat(f32::MAX);
+        for axis in 0..3 {
+            if direction[axis] != 0.0 {
+                let boundary = if direction[axis] > 0.0 { cell[axis] + 1 } else { cell[axis] };
+                next[axis] = (self.bounds.min[axis] + boundary as f32 * size[axis] - origin[axis]) * inverse[axis];
+                delta[axis] = size[axis] * inverse[axis].abs();
+            }
+        }
+
+        let mut max_t = max_t;
+        loop {
+            for &thing in self.things(cell) {
+                if let Some(t) = hit(thing, max_t) {
+                    max_t = max_t.min(t);
+                }
+            }
+            let axis = if next.x < next.y && next.x < next.z { 0 } else if next.y < next.z { 1 } else { 2 };
+            let exit = next[axis];
+            if max_t <= exit || exit > far {
+                break;
+            }
+            if direction[axis] > 0.0 {
+                cell[axis] += 1;
+                if cell[axis] == self.resolution[axis] {
+                    break;
+                }
+            } else if cell[axis] == 0 {
+                break;
+            } else {
+                cell[axis] -= 1;
+            }
+            next[axis] += delta[axis];
+        }
+    }
+
+    // Cells are the leaves, all at the one level
+    fn stats(&self) -> Stats {
+        let cells = self.cell_starts.len() - 1;
+        let largest_leaf = (0..cells).map(|cell| self.cell_starts[cell + 1] - self.cell_starts[cell]).max().unwrap_or(0);
+        Stats { nodes: cells, leaves: cells, depth: 1, largest_leaf }
+    }
+}
@@ -0,0 +1,202 @@
+// Equirectangular high dynamic range environment maps, loaded from Radiance .hdr files.
+//
+// The whole sphere of directions is unwrapped onto the image: longitude goes along the width
+// (the center of the image looks down +Z) and latitude along the height (the top row looks up).
+
+use glam::Vec3;
+use std::f32::consts::PI;
+use std::fs;
+
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>
+}
+
+impl EnvironmentMap {
+    pub fn load(path: &str) -> Result<EnvironmentMap, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        EnvironmentMap::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<EnvironmentMap, String> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        let magic = reader.line()?;
+        if magic != "#?RADIANCE" && magic != "#?RGBE" {
+            return Err(String::from("not a Radiance HDR file"));
+        }
+        // Header variables until an empty line
+        loop {
+            let line = reader.line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(format!("unsupported pixel format {}", format));
+                }
+            }
+        }
+
+        // Only the standard orientation, rows from top to bottom and columns from left to right
+        let resolution = reader.line()?;
+        let parts: Vec<&str> = resolution.split_whitespace().collect();
+        if parts.len() != 4 || parts[0] != "-Y" || parts[2] != "+X" {
+            return Err(format!("unsupported resolution line '{}'", resolution));
+        }
+        let height: usize = parts[1].parse().map_err(|_| "invalid height")?;
+        let width: usize = parts[3].parse().map_err(|_| "invalid width")?;
+        if width == 0 || height == 0 {
+            return Err(String::from("image has no pixels"));
+        }
+        // The best a file can do is a run of 127 pixels of one channel in two bytes, so a
+        // resolution beyond that is a broken header rather than something worth allocating
+        let size = width.checked_mul(height).ok_or("image too big")?;
+        if size > bytes.len().saturating_mul(127) / 8 {
+            return Err(String::from("image bigger than the file"));
+        }
+
+        let mut pixels = Vec::with_capacity(size);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            reader.scanline(&mut scanline)?;
+            pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_rgb(rgbe)));
+        }
+
+        Ok(EnvironmentMap { width, height, pixels })
+    }
+
+    // Radiance arriving from `direction`, bilinearly filtered
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let d = direction.normalize();
+        let u = 0.5 + d.x.atan2(d.z) / (2.0 * PI);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+        // Longitude wraps around, latitude stops at the poles
+        let column = |x: f32| (x as i64).rem_euclid(self.width as i64) as usize;
+        let row = |y: f32| (y as usize).min(self.height - 1);
+        let pixel = |x: f32, y: f32| self.pixels[row(y) * self.width + column(x)];
+
+        let top = pixel(x0, y0).lerp(pixel(x0 + 1.0, y0), tx);
+        let bottom = pixel(x0, y0 + 1.0).lerp(pixel(x0 + 1.0, y0 + 1.0), tx);
+        top.lerp(bottom, ty)
+    }
+}
+
+fn rgbe_to_rgb(rgbe: [u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::ZERO;
+    }
+    let scale = 2.0_f32.powi(rgbe[3] as i32 - (128 + 8));
+    Vec3::new(rgbe[0] as f32, rgbe[1] as f32, rgbe[2] as f32) * scale
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.position).ok_or("unexpected end of file")?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn line(&mut self) -> Result<String, String> {
+        let mut line = Vec::new();
+        loop {
+            match self.byte()? {
+                b'\n' => break,
+                byte => line.push(byte)
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+
+    fn scanline(&mut self, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+        let width = scanline.len();
+        let start = self.position;
+        let header = [self.byte()?, self.byte()?, self.byte()?, self.byte()?];
+
+        // Run length encoded scanlines start with 2, 2 and the width, and store each channel separately
+        let encoded = (8..0x8000).contains(&width)
+            && header[0] == 2
+            && header[1] == 2
+            && ((header[2] as usize) << 8 | header[3] as usize) == width;
+        if !encoded {
+            self.position = start;
+            for pixel in scanline.iter_mut() {
+                *pixel = [self.byte()?, self.byte()?, self.byte()?, self.byte()?];
+            }
+            return Ok(());
+        }
+
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.byte()? as usize;
+                if count > 128 {
+                    // A run of the same value
+                    let count = count - 128;
+                    let value = self.byte()?;
+                    if x + count > width {
+                        return Err(String::from("run goes past the end of the scanline"));
+                    }
+                    for pixel in &mut scanline[x..x + count] {
+                        pixel[channel] = value;
+                    }
+                    x += count;
+                } else {
+                    // Literal values
+                    if count == 0 || x + count > width {
+                        return Err(String::from("invalid scanline data"));
+                    }
+                    for pixel in &mut scanline[x..x + count] {
+                        pixel[channel] = self.byte()?;
+                    }
+                    x += count;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+        bytes.extend_from_slice(pixels);
+        bytes
+    }
+
+    #[test]
+    fn flat_pixels() {
+        let map = EnvironmentMap::parse(&hdr("-Y 1 +X 2", &[128, 64, 0, 129, 0, 0, 0, 0])).unwrap();
+        assert_eq!(map.pixels, vec![Vec3::new(1.0, 0.5, 0.0), Vec3::ZERO]);
+        assert!(map.radiance(Vec3::Y).is_finite());
+    }
+
+    #[test]
+    fn no_pixels() {
+        assert!(EnvironmentMap::parse(&hdr("-Y 0 +X 16", &[])).is_err());
+        assert!(EnvironmentMap::parse(&hdr("-Y 16 +X 0", &[])).is_err());
+    }
+
+    #[test]
+    fn too_many_pixels() {
+        let overflow = format!("-Y {} +X {}", usize::MAX, 2);
+        assert!(EnvironmentMap::parse(&hdr(&overflow, &[])).is_err());
+        assert!(EnvironmentMap::parse(&hdr("-Y 1000000 +X 1000000", &[0; 64])).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+// Parser for IES LM-63 photometric files, which describe how the intensity of a real
+// light fixture changes with the direction it's looked at from.
+//
+// Only type C photometry is supported, which is what practically every architectural fixture
+// uses: vertical angles go from 0 (straight down the light axis) to 180 (straight up), and
+// horizontal angles go around that axis.
+
+use glam::Vec3;
+use std::fs;
+
+// Real fixtures are measured every degree or so, tens of thousands of angles is a broken file
+const MAX_ANGLES: usize = 10_000;
+
+pub struct IesProfile {
+    vertical_angles: Vec<f32>,
+    horizontal_angles: Vec<f32>,
+    // One row of candela values per horizontal angle, one value per vertical angle
+    candela: Vec<Vec<f32>>,
+    max_candela: f32
+}
+
+impl IesProfile {
+    pub fn load(path: &str) -> Result<IesProfile, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        IesProfile::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<IesProfile, String> {
+        // Everything up to the TILT line is free form keywords we don't care about
+        let tilt_start = contents.find("TILT=").ok_or("missing TILT line")?;
+        let after_tilt = &contents[tilt_start..];
+        let tilt_end = after_tilt.find('\n').unwrap_or(after_tilt.len());
+        let tilt = after_tilt[5..tilt_end].trim();
+
+        let mut numbers = after_tilt[tilt_end..]
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f32>().map_err(|_| format!("invalid number '{}'", token)));
+        let mut next = || numbers.next().unwrap_or(Err(String::from("unexpected end of file")));
+
+        match tilt {
+            "NONE" => {},
+            "INCLUDE" => {
+                // Lamp to luminaire geometry, then the tilt angles and their multiplying factors
+                next()?;
+                let count = count(next()?)?;
+                for _ in 0..2 * count {
+                    next()?;
+                }
+            },
+            _ => return Err(format!("TILT={} needs an external file, which is not supported", tilt))
+        }
+
+        let _number_of_lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = count(next()?)?;
+        let horizontal_count = count(next()?)?;
+        let photometric_type = next()?;
+        if photometric_type != 1.0 {
+            return Err(String::from("only type C photometry is supported"));
+        }
+        // Units and luminous opening dimensions
+        for _ in 0..4 {
+            next()?;
+        }
+        let ballast_factor = next()?;
+        // Ballast-lamp photometric factor, then input watts
+        next()?;
+        next()?;
+
+        let vertical_angles = (0..vertical_count).map(|_| next()).collect::<Result<Vec<_>, _>>()?;
+        let horizontal_angles = (0..horizontal_count).map(|_| next()).collect::<Result<Vec<_>, _>>()?;
+        let mut candela = Vec::with_capacity(horizontal_count);
+        for _ in 0..horizontal_count {
+            let row = (0..vertical_count)
+                .map(|_| next().map(|value| value * multiplier * ballast_factor))
+                .collect::<Result<Vec<_>, _>>()?;
+            candela.push(row);
+        }
+
+        if vertical_angles.is_empty() || horizontal_angles.is_empty() {
+            return Err(String::from("the profile has no angles"));
+        }
+        let max_candela = candela.iter().flatten().cloned().fold(0.0, f32::max);
+        if max_candela <= 0.0 {
+            return Err(String::from("the profile emits no light"));
+        }
+
+        Ok(IesProfile { vertical_angles, horizontal_angles, candela, max_candela })
+    }
+
+    // Relative intensity in [0, 1] towards `to_point`, for a fixture pointing along `axis`
+    pub fn intensity_towards(&self, to_point: Vec3, axis: Vec3) -> f32 {
+        let axis = axis.normalize();
+        let d = to_point.normalize();
+        let vertical = d.dot(axis).clamp(-1.0, 1.0).acos().to_degrees();
+        let (tangent, bitangent) = axis.any_orthonormal_pair();
+        let horizontal = d.dot(bitangent).atan2(d.dot(tangent)).to_degrees().rem_euclid(360.0);
+        self.intensity(vertical, horizontal)
+    }
+
+    // Relative intensity in [0, 1] at the given angles, in degrees
+    pub fn intensity(&self, vertical: f32, horizontal: f32) -> f32 {
+        let horizontal = self.apply_symmetry(horizontal);
+
+        // Find the two horizontal planes around the angle and blend between them
+        let (h0, h1, h_blend) = match interpolation_span(&self.horizontal_angles, horizontal) {
+            Some(span) => span,
+            None => return 0.0
+        };
+        let (v0, v1, v_blend) = match interpolation_span(&self.vertical_angles, vertical) {
+            Some(span) => span,
+            None => return 0.0
+        };
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let near = lerp(self.candela[h0][v0], self.candela[h0][v1], v_blend);
+        let far = lerp(self.candela[h1][v0], self.candela[h1][v1], v_blend);
+        lerp(near, far, h_blend) / self.max_candela
+    }
+
+    // The file only stores the part of the distribution that isn't a mirror of the rest,
+    // the last horizontal angle tells which symmetry the fixture has
+    fn apply_symmetry(&self, horizontal: f32) -> f32 {
+        let last = *self.horizontal_angles.last().unwrap();
+        if last == 0.0 {
+            // Same distribution all around the axis
+            0.0
+        } else if last == 90.0 {
+            // Symmetric in each quadrant
+            let h = if horizontal > 180.0 { 360.0 - horizontal } else { horizontal };
+            if h > 90.0 { 180.0 - h } else { h }
+        } else if last == 180.0 {
+            // Symmetric about the 0-180 plane
+            if horizontal > 180.0 { 360.0 - horizontal } else { horizontal }
+        } else {
+            horizontal
+        }
+    }
+}
+
+fn count(value: f32) -> Result<usize, String> {
+    if value < 1.0 || value.fract() != 0.0 || value > MAX_ANGLES as f32 {
+        return Err(format!("invalid number of angles {}", value));
+    }
+    Ok(value as usize)
+}
+
+// Indices of the two angles around `angle` and how far between them it is,
+// None if it's outside the measured range
+fn interpolation_span(angles: &[f32], angle: f32) -> Option<(usize, usize, f32)> {
+    if angles.len() == 1 {
+        return Some((0, 0, 0.0));
+    }
+    if angle < angles[0] || angle > angles[angles.len() - 1] {
+        return None;
+    }
+    let upper = angles.iter().position(|&a| a >= angle).unwrap();
+    if upper == 0 {
+        return Some((0, 0, 0.0));
+    }
+    let lower = upper - 1;
+    let t = (angle - angles[lower]) / (angles[upper] - angles[lower]);
+    Some((lower, upper, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ies(vertical_count: &str, horizontal_count: &str) -> String {
+        format!(
+            "IESNA:LM-63-2002\nTILT=NONE\n1 1000 1 {} {} 1 2 0 0 0\n1 1 100\n0 90\n0\n100 50\n",
+            vertical_count, horizontal_count
+        )
+    }
+
+    #[test]
+    fn single_plane() {
+        let profile = IesProfile::parse(&ies("2", "1")).unwrap();
+        assert_eq!(profile.intensity(0.0, 0.0), 1.0);
+        assert_eq!(profile.intensity(45.0, 123.0), 0.75);
+    }
+
+    #[test]
+    fn invalid_counts() {
+        for (vertical, horizontal) in [("-2", "1"), ("2", "0"), ("2.5", "1"), ("2", "1e9")] {
+            assert!(IesProfile::parse(&ies(vertical, horizontal)).is_err());
+        }
+    }
+}
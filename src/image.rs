@@ -0,0 +1,111 @@
+// Images for textures, loaded from Netpbm .ppm files (binary P6 or plain text P3).
+//
+// Texture coordinates go from (0, 0) at the bottom left corner of the image to (1, 1) at the
+// top right, and repeat outside of that.
+
+use glam::{Vec2, Vec3};
+use std::fs;
+
+pub struct Image {
+    width: usize,
+    height: usize,
+    // Rows from top to bottom, channels in [0, 1]
+    pixels: Vec<Vec3>
+}
+
+impl Image {
+    pub fn load(path: &str) -> Result<Image, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Image::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Image, String> {
+        let mut position = 0;
+        let magic = header_token(bytes, &mut position)?;
+        if magic != "P6" && magic != "P3" {
+            return Err(String::from("not a PPM file"));
+        }
+        let mut number = |name: &str| -> Result<usize, String> {
+            header_token(bytes, &mut position)?.parse().map_err(|_| format!("invalid {}", name))
+        };
+        let width = number("width")?;
+        let height = number("height")?;
+        let max_value = number("maximum value")?;
+        if width == 0 || height == 0 {
+            return Err(String::from("empty image"));
+        }
+        if max_value == 0 || max_value > 65535 {
+            return Err(format!("invalid maximum value {}", max_value));
+        }
+
+        let count = width * height * 3;
+        let samples: Vec<usize> = if magic == "P6" {
+            // A single whitespace character separates the header from the pixels, which take
+            // two bytes per sample, most significant first, when they don't fit in one
+            let data = &bytes[(position + 1).min(bytes.len())..];
+            let size = if max_value < 256 { 1 } else { 2 };
+            if data.len() < count * size {
+                return Err(String::from("unexpected end of file"));
+            }
+            match size {
+                1 => data[..count].iter().map(|&byte| byte as usize).collect(),
+                _ => data[..count * 2].chunks(2).map(|pair| (pair[0] as usize) << 8 | pair[1] as usize).collect()
+            }
+        } else {
+            let text = String::from_utf8_lossy(&bytes[position..]);
+            let samples: Result<Vec<usize>, _> = text.split_whitespace().take(count).map(str::parse).collect();
+            let samples = samples.map_err(|_| "invalid pixel value")?;
+            if samples.len() < count {
+                return Err(String::from("unexpected end of file"));
+            }
+            samples
+        };
+
+        let scale = 1.0 / max_value as f32;
+        let pixels = samples
+            .chunks(3)
+            .map(|rgb| Vec3::new(rgb[0] as f32, rgb[1] as f32, rgb[2] as f32) * scale)
+            .collect();
+        Ok(Image { width, height, pixels })
+    }
+
+    // Color at the texture coordinates `uv`, bilinearly filtered
+    pub fn sample(&self, uv: Vec2) -> Vec3 {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+        let pixel = |x: f32, y: f32| {
+            let column = (x as i64).rem_euclid(self.width as i64) as usize;
+            let row = (y as i64).rem_euclid(self.height as i64) as usize;
+            self.pixels[row * self.width + column]
+        };
+
+        let top = pixel(x0, y0).lerp(pixel(x0 + 1.0, y0), tx);
+        let bottom = pixel(x0, y0 + 1.0).lerp(pixel(x0 + 1.0, y0 + 1.0), tx);
+        top.lerp(bottom, ty)
+    }
+}
+
+// Next word of the header, skipping whitespace and comments
+fn header_token(bytes: &[u8], position: &mut usize) -> Result<String, String> {
+    loop {
+        match bytes.get(*position) {
+            Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+            Some(b'#') => {
+                while bytes.get(*position).is_some_and(|&byte| byte != b'\n') {
+                    *position += 1;
+                }
+            },
+            Some(_) => break,
+            None => return Err(String::from("unexpected end of file"))
+        }
+    }
+    let start = *position;
+    while bytes.get(*position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+        *position += 1;
+    }
+    Ok(String::from_utf8_lossy(&bytes[start..*position]).to_string())
+}
@@ -0,0 +1,185 @@
+// Kd-tree, which cuts the space around the things a ray can hit with a plane along one of the
+// axes, then each side again, until the pieces hold few things. Unlike a BVH the pieces never
+// overlap, so a ray goes through them strictly in order, but a thing that straddles a cut is
+// in both sides.
+//
+// Like the BVH it only knows the boxes of the things and refers to them by index.
+
+use crate::accel::{AccelStructure, Stats};
+use crate::bvh::Aabb;
+use glam::Vec3;
+
+// Relative costs of stepping through a node and of trying a thing, for the surface area
+// heuristic
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.5;
+
+// The children of an inner node are the next node, below the cut, and the one at `above`.
+// Leaves hold `count` things, from `first` on in the indices
+#[derive(Copy, Clone)]
+enum Node {
+    Inner { axis: usize, split: f32, above: usize },
+    Leaf { first: usize, count: usize }
+}
+
+pub struct KdTree {
+    bounds: Aabb,
+    nodes: Vec<Node>,
+    indices: Vec<usize>
+}
+
+impl KdTree {
+    pub fn new(boxes: &[Aabb]) -> KdTree {
+        let bounds = boxes.iter().fold(Aabb::EMPTY, |bounds, &b| bounds.union(b));
+        let mut tree = KdTree { bounds, nodes: vec![], indices: vec![] };
+        if !boxes.is_empty() {
+            // Deep enough for the tree to be worth it, not so deep that it blows up in size
+            // when things overlap (Havran)
+            let max_depth = 8 + (1.3 * (boxes.len() as f32).log2()) as usize;
+            tree.build(boxes, (0..boxes.len()).collect(), bounds, max_depth);
+        }
+        tree
+    }
+
+    // Adds the node for the things in `things`, which are within `bounds`
+    fn build(&mut self, boxes: &[Aabb], things: Vec<usize>, bounds: Aabb, depth: usize) {
+        let split = if depth == 0 || things.len() <= 1 { None } else { best_split(boxes, &things, bounds) };
+        let Some((axis, split)) = split else {
+            self.nodes.push(Node::Leaf { first: self.indices.len(), count: things.len() });
+            self.indices.extend(things);
+            return;
+        };
+
+        // Flat things lying right on the cut go below it
+        let below: Vec<usize> = things.iter().copied().filter(|&index| {
+            boxes[index].min[axis] < split || boxes[index].max[axis] <= split
+        }).collect();
+        let above: Vec<usize> = things.into_iter().filter(|&index| boxes[index].max[axis] > split).collect();
+        let (mut below_bounds, mut above_bounds) = (bounds, bounds);
+        below_bounds.max[axis] = split;
+        above_bounds.min[axis] = split;
+
+        let node = self.nodes.len();
+        self.nodes.push(Node::Inner { axis, split, above: 0 });
+        self.build(boxes, below, below_bounds, depth - 1);
+        let above_node = self.nodes.len();
+        self.build(boxes, above, above_bounds, depth - 1);
+        self.nodes[node] = Node::Inner { axis, split, above: above_node };
+    }
+
+    fn tree_stats(&self) -> Stats {
+        let mut stats = Stats { nodes: self.nodes.len(), leaves: 0, depth: 0, largest_leaf: 0 };
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![(0, 1)] };
+        while let Some((index, depth)) = stack.pop() {
+            stats.depth = stats.depth.max(depth);
+            match self.nodes[index] {
+                Node::Leaf { count, .. } => {
+                    stats.leaves += 1;
+                    stats.largest_leaf = stats.largest_leaf.max(count);
+                },
+                Node::Inner { above, .. } => stack.extend([(index + 1, depth + 1), (above, depth + 1)])
+            }
+        }
+        stats
+    }
+}
+
+// Cut through `bounds` where the surface area heuristic expects rays to try the fewest things,
+// looking at the sides of the boxes along each axis. None when no cut is cheaper than trying
+// everything
+fn best_split(boxes: &[Aabb], things: &[usize], bounds: Aabb) -> Option<(usize, f32)> {
+    let area = bounds.surface_area();
+    if area <= 0.0 {
+        return None;
+    }
+    let leaf_cost = INTERSECTION_COST * things.len() as f32;
+    let mut best: Option<(usize, f32, f32)> = None;
+    for axis in 0..3 {
+        let mut starts: Vec<f32> = things.iter().map(|&index| boxes[index].min[axis]).collect();
+        let mut ends: Vec<f32> = things.iter().map(|&index| boxes[index].max[axis]).collect();
+        starts.sort_unstable_by(f32::total_cmp);
+        ends.sort_unstable_by(f32::total_cmp);
+
+        for &split in starts.iter().chain(&ends) {
+            if split <= bounds.min[axis] || split >= bounds.max[axis] {
+                continue;
+            }
+            let below_count = starts.partition_point(|&start| start < split);
+            let above_count = things.len() - ends.partition_point(|&end| end <= split);
+            let (mut below, mut above) = (bounds, bounds);
+            below.max[axis] = split;
+            above.min[axis] = split;
+            let cost = TRAVERSAL_COST
+                + INTERSECTION_COST * (below.surface_area() * below_count as f32 + above.surface_area() * above_count as f32) / area;
+            if cost < leaf_cost && best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, split, cost));
+            }
+        }
+    }
+    best.map(|(axis, split, _)| (axis, split))
+}
+
+impl AccelStructure for KdTree {
+    // Goes through the pieces the ray crosses in order, keeping the far side of each cut for
+    // later. A thing can stick out of the piece it was found in, so the search only stops once
+    // the closest hit so far is inside the pieces already gone through
+    fn traverse(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, hit: &mut dyn FnMut(usize, f32) -> Option<f32>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse_direction = direction.recip();
+        let Some((near, far)) = self.bounds.entry_exit(origin, inverse_direction, min_t, max_t) else {
+            return;
+        };
+        let mut max_t = max_t;
+        // Nodes left for later, with the part of the ray inside them
+        let mut stack = vec![(0, near, far)];
+        while let Some((mut index, near, mut far)) = stack.pop() {
+            if near > max_t {
+                break;
+            }
+            loop {
+                match self.nodes[index] {
+                    Node::Leaf { first, count } => {
+                        for &thing in &self.indices[first..first + count] {
+                            if let Some(t) = hit(thing, max_t) {
+                                max_t = max_t.min(t);
+                            }
+                        }
+                        break;
+                    },
+                    Node::Inner { axis, split, above } => {
+                        let below = index + 1;
+                        if direction[axis] == 0.0 {
+                            // Running along the cut, on one side of it or right on it
+                            if origin[axis] < split {
+                                index = below;
+                            } else if origin[axis] > split {
+                                index = above;
+                            } else {
+                                stack.push((above, near, far));
+                                index = below;
+                            }
+                            continue;
+                        }
+                        let (first, second) = if direction[axis] > 0.0 { (below, above) } else { (above, below) };
+                        let t = (split - origin[axis]) * inverse_direction[axis];
+                        if t >= far {
+                            index = first;
+                        } else if t <= near {
+                            index = second;
+                        } else {
+                            stack.push((second, t, far));
+                            index = first;
+                            far = t;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        self.tree_stats()
+    }
+}
@@ -1,82 +1,646 @@
-use glam::Vec3;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Point;
-use std::time::Duration;
+mod accel;
+mod animation;
+mod bezier;
+mod bvh;
+mod camera;
+#[cfg(feature = "embree")]
+mod embree;
+mod gltf;
+mod grid;
+mod hdri;
+mod ies;
+mod image;
+mod json;
+mod kdtree;
+mod materials;
+mod network;
+mod obj;
+mod ply;
+mod profile;
+mod rng;
+mod shapes;
+mod sky;
+mod stl;
+mod tiles;
+#[cfg(feature = "window")]
+mod window;
+
+use accel::{AccelStructure, Accelerator};
+use bvh::{Aabb, Bvh, BvhOptions, Split};
+use camera::{Bokeh, Camera, Distortion, Lens, Projection, DEFAULT_FOV};
+use glam::{Mat4, Quat, Vec2, Vec3};
+use hdri::EnvironmentMap;
+use ies::IesProfile;
+use image::Image;
+use profile::Phase;
+use materials::{noise, presets, Material, MaterialId, Pbr, Phong, ShadowCatcher, Sided, SpecularModel, SurfaceHit, Texture, Toon};
+use rng::{cosine_hemisphere, hash_random, Rng};
+use shapes::{sdf, Cone, Csg, Cuboid, Curve, Cylinder, Disc, Heightfield, Intersection, Mesh, Operation, Plane, PointCloud, Quadric, Shape, Sphere, Spheres, Torus, Transformed};
+use sky::Sky;
+use network::{Job, Workers};
+use tiles::{Tile, TileQueue};
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
 const INF: f32 = f32::MAX;
 
 const BACKGROUND_COLOR: Color = Color::WHITE;
 
-struct Sphere {
-    radius: f32,
-    center: Vec3,
-    color: Color,
+// Side in pixels of the blocks of the first, coarsest preview of a frame
+const PREVIEW_BLOCK: i32 = 8;
+// How often the window is updated while a pass of a frame is rendered
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(100);
+// Side in pixels of the tiles frames are split into, a multiple of PREVIEW_BLOCK so the blocks
+// don't straddle tiles
+const TILE_SIZE: i32 = 32;
+
+// Something in the scene: its shape and what it's made of
+struct Object {
+    // Identifies the object, e.g. for light linking
+    name: String,
+    shape: Box<dyn Shape>,
+    material: MaterialId
 }
 
-impl Sphere {
-    fn ray_intersection(&self, origin: Vec3, distance: Vec3) -> (f32, f32) {
-        let r = self.radius;
-        let co = origin - self.center;
+impl Object {
+    // Moves, turns and scales the object by the matrix, leaving its shape as it was made
+    fn transformed(mut self, matrix: Mat4) -> Object {
+        self.shape = Box::new(Transformed::new(self.shape, matrix));
+        self
+    }
+}
 
-        let a = distance.dot(distance);
-        let b = 2.0 * co.dot(distance);
-        let c = co.dot(co) - r * r;
+// Evaluates only some of the lights at each shading point, for scenes with lots of lights
+struct LightSampling {
+    // Lights picked per shading point
+    count: u32,
+    // Pick brighter lights more often instead of all of them equally
+    power_weighted: bool
+}
 
-        let discriminant = b * b - 4.0 * a * c;
-        if discriminant < 0.0 {
-            return (INF, INF);
+// What light intensities mean
+#[derive(Copy, Clone)]
+enum LightUnits {
+    // Unitless, around [0, 1], where 1 lights a white surface facing the light to full white
+    Relative,
+    // Lumens for point, spot and area lights, lux for directional lights and nits (cd/m^2) for
+    // ambient lights and emissive objects. The image is exposed like a camera would with this
+    // exposure value at ISO 100, a higher value for brighter scenes
+    Physical { ev100: f32 }
+}
+
+impl LightUnits {
+    // Converts the intensity of a light to the light reflected by a white diffuse surface
+    // facing it at a distance of 1
+    fn light_scale(&self, light: &Light) -> f32 {
+        let pi = std::f32::consts::PI;
+        match self {
+            LightUnits::Relative => light.intensity,
+            LightUnits::Physical { .. } => match light.kind {
+                LightType::Ambient | LightType::Portal { .. } => light.intensity,
+                // Luminous intensity (candela) from the flux spread over the whole sphere,
+                // area lights are treated as a point light from far enough away
+                LightType::Point | LightType::Rect { .. } | LightType::Disc { .. } => light.intensity / (4.0 * pi * pi),
+                // The flux is spread over the cone instead
+                LightType::Spot { outer_angle, .. } => {
+                    let solid_angle = 2.0 * pi * (1.0 - outer_angle.to_radians().cos());
+                    light.intensity / (solid_angle * pi)
+                },
+                // A diffuse surface reflects 1 / pi of the illuminance per unit of solid angle
+                LightType::Directional => light.intensity / pi
+            }
+        }
+    }
+
+    fn sky_scale(&self, environment: &Environment) -> f32 {
+        match (self, environment) {
+            (LightUnits::Physical { .. }, Environment::Sky(_)) => sky::NITS_PER_UNIT,
+            _ => 1.0
+        }
+    }
+
+    fn sun_scale(&self) -> f32 {
+        match self {
+            LightUnits::Relative => 1.0,
+            LightUnits::Physical { .. } => sky::SUN_ILLUMINANCE / std::f32::consts::PI
+        }
+    }
+
+    // Multiplier from the light in the scene to pixel values. With physical units the
+    // brightest light that isn't clipped is 1.2 * 2^EV100 nits (Lagarde and de Rousiers)
+    fn exposure(&self) -> f32 {
+        match self {
+            LightUnits::Relative => 1.0,
+            LightUnits::Physical { ev100 } => 1.0 / (1.2 * 2.0_f32.powf(*ev100))
+        }
+    }
+}
+
+struct RenderSettings {
+    // Shadow rays ignore hits closer than this along the ray. Too small and surfaces shadow
+    // themselves (shadow acne), too big and shadows detach from the objects casting them
+    shadow_bias: f32,
+    // Shadow rays start this far off the surface along the normal, which gets rid of acne
+    // at grazing angles where the constant bias isn't enough
+    normal_offset: f32,
+    light_units: LightUnits,
+    specular_model: SpecularModel,
+    // None evaluates every light
+    light_sampling: Option<LightSampling>,
+    // How many times a ray can bounce off reflective surfaces
+    max_depth: u32,
+    // Rays that have bounced this many times only go on at random, with a chance that follows
+    // how much they still carry, and the ones that do carry more to make up for the others
+    // (Russian roulette). Dim paths end early without darkening the picture on average. None
+    // follows every ray up to max_depth
+    roulette_depth: Option<u32>,
+    // Number of shadow rays cast towards each area light per shading point
+    area_light_samples: u32,
+    // Number of directions the environment map is sampled in per shading point
+    environment_samples: u32,
+    // Number of rays used to find how hidden a point is from the ambient light, 0 disables
+    // ambient occlusion
+    ao_samples: u32,
+    // Occluders further away than this don't darken the ambient light
+    ao_distance: f32,
+    // What the objects are sorted into for quick ray queries
+    accelerator: Accelerator,
+    // Side in pixels of the squares whose primary rays are traced together as a packet, which
+    // goes through the BVH once for all of them. 0 traces every ray on its own. When it's on the
+    // shadow rays from a hit towards the samples of a light, an emitter or the environment go
+    // together as a packet too. Bounces are always traced one by one
+    packet_size: u32,
+    // How the BVH over the objects, and the ones over the triangles of imported models, are built
+    bvh: BvhOptions,
+    // Rays per pixel. Every pixel gets at least `min_samples`, and noisy ones, like along
+    // edges and in soft shadows, more up to `max_samples`, until the standard error of their
+    // brightness is under `noise_threshold`
+    min_samples: u32,
+    max_samples: u32,
+    noise_threshold: f32
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            shadow_bias: 0.001,
+            normal_offset: 0.001,
+            light_units: LightUnits::Relative,
+            specular_model: SpecularModel::Phong,
+            light_sampling: None,
+            max_depth: 3,
+            roulette_depth: None,
+            area_light_samples: 16,
+            environment_samples: 64,
+            ao_samples: 0,
+            ao_distance: 1.5,
+            accelerator: Accelerator::default(),
+            packet_size: 0,
+            bvh: BvhOptions::default(),
+            min_samples: 1,
+            max_samples: 1,
+            noise_threshold: 0.01
         }
+    }
+}
 
-        let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
-        let t2 = (-b - discriminant.sqrt()) / (2.0 * a);
+// What rays that don't hit anything see, and what lights the scene from far away
+enum Environment {
+    Color(Color),
+    Sky(Sky),
+    Hdri(EnvironmentMap)
+}
 
-        return (t1, t2);
+impl Environment {
+    // Light arriving from far away along `direction`
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Environment::Color(color) => Vec3::new(color.r as f32, color.g as f32, color.b as f32) / 255.0,
+            Environment::Sky(sky) => sky.radiance(direction),
+            Environment::Hdri(map) => map.radiance(direction)
+        }
     }
 }
 
 struct Scene {
-    spheres: Vec<Sphere>,
-    lighting: Vec<Light>
+    objects: Vec<Object>,
+    // Built once all the objects are in place
+    acceleration: Acceleration,
+    // Shared by the objects, which refer to them by index
+    materials: Vec<Box<dyn Material>>,
+    lighting: Vec<Light>,
+    environment: Environment,
+    settings: RenderSettings
+}
+
+// The objects sorted into a BVH or a kd-tree, so rays only try the ones they pass near. Objects
+// that go on forever, like planes, don't fit in a box and are always tried
+struct Acceleration {
+    structure: Box<dyn AccelStructure>,
+    // Object of each box in the structure
+    bounded: Vec<usize>,
+    arena: Arena,
+    unbounded: Vec<usize>,
+    // The objects that glow, which light the others
+    emitters: Vec<Emitter>
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Acceleration { structure: Box::new(Bvh::default()), bounded: vec![], arena: Arena::default(), unbounded: vec![], emitters: vec![] }
+    }
+}
+
+// What the ray loops read of the objects over and over, each in an array of its own, so it's
+// read one value after the other instead of from the objects wherever they are in memory.
+// The spheres around the objects of the boxes, which the candidates of a ray are tried against
+// before their shape is looked up, are by box. Material ids, looked up for every hit, by object
+#[derive(Default)]
+struct Arena {
+    centers: Vec<Vec3>,
+    radii: Vec<f32>,
+    materials: Vec<MaterialId>
+}
+
+// An object that glows, with the sphere around it, a part for each of its materials that glow
+// and how many materials it has in all
+struct Emitter {
+    object: usize,
+    center: Vec3,
+    radius: f32,
+    parts: Vec<usize>,
+    materials: usize
+}
+
+impl Acceleration {
+    // Whether the ray goes through the sphere around the object of box `k` between `min_t`
+    // and `max_t`
+    fn crosses(&self, k: usize, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> bool {
+        let to_origin = origin - self.arena.centers[k];
+        let a = direction.dot(direction);
+        let b = to_origin.dot(direction);
+        let radius = self.arena.radii[k];
+        let discriminant = b * b - a * (to_origin.dot(to_origin) - radius * radius);
+        if discriminant < 0.0 {
+            return false;
+        }
+        let root = discriminant.sqrt();
+        (-b + root) / a >= min_t && (-b - root) / a <= max_t
+    }
 }
 
 impl Scene {
-    fn trace_ray(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Color {
-        let mut closest_t = INF;
-        let mut closest_sphere = None;
-
-        for sphere in &self.spheres {
-            let ts = sphere.ray_intersection(origin, direction);
-            let t1 = ts.0;
-            let t2 = ts.1;
-            if min_t < t1 && t1 < max_t && t1 < closest_t {
-                closest_t = t1;
-                closest_sphere = Some(sphere);
-            }
-            if min_t < t2 && t2 < max_t && t2 < closest_t {
-                closest_t = t2;
-                closest_sphere = Some(sphere);
-            }
-        }
-
-        return match closest_sphere {
-            None => BACKGROUND_COLOR,
-            Some(sphere) => { 
-                let p = origin + closest_t * direction;
-                let mut n = p - sphere.center;
-                n = n / n.length();
-                let light_intensity = compute_lighting(p, n, self);
-                return Color::RGB(
-                    ( sphere.color.r as f32 * light_intensity ) as u8,
-                    ( sphere.color.g as f32 * light_intensity ) as u8,
-                    ( sphere.color.b as f32 * light_intensity ) as u8
-                );
-            },
+    // Sorts the objects for quick ray queries, once they're all in place, and tells how long
+    // it took and what the tree looks like
+    fn build_acceleration(&mut self) {
+        let _span = profile::span(Phase::Build);
+        let start = Instant::now();
+        let mut acceleration = Acceleration::default();
+        let mut boxes = vec![];
+        for (index, object) in self.objects.iter().enumerate() {
+            acceleration.arena.materials.push(object.material);
+            match object.shape.bounding_sphere() {
+                Some((center, radius)) => {
+                    boxes.push(Aabb::around_sphere(center, radius));
+                    acceleration.bounded.push(index);
+                    acceleration.arena.centers.push(center);
+                    // A little bigger, so rounding never has a ray miss the sphere but hit the
+                    // object
+                    acceleration.arena.radii.push(radius * 1.0001 + 1e-5);
+                },
+                None => acceleration.unbounded.push(index)
+            }
+            // Whether a material glows is told from a point at the top of the object, the glow
+            // of textured ones may change from place to place
+            let top = object.shape.bounding_sphere().map_or(Vec3::ZERO, |(center, radius)| center + Vec3::Y * radius);
+            let parts = object.shape.material_parts();
+            let glowing: Vec<usize> = parts
+                .iter()
+                .copied()
+                .filter(|&part| self.material(object, part).emission(&object.shape.surface_hit(top, part)) != Vec3::ZERO)
+                .collect();
+            match (glowing.is_empty(), object.shape.bounding_sphere()) {
+                (true, _) => {},
+                (false, Some((center, radius))) => {
+                    acceleration.emitters.push(Emitter { object: index, center, radius, parts: glowing, materials: parts.len() });
+                },
+                (false, None) => println!("{} glows but goes on forever, so it doesn't light the other objects", object.name)
+            }
+        }
+        acceleration.structure = self.settings.accelerator.build(&boxes, self.settings.bvh);
+        let stats = acceleration.structure.stats();
+        println!(
+            "{} over {} objects built in {:.2?}: {} nodes, {} leaves of up to {} objects, {} levels deep, {} unbounded objects",
+            self.settings.accelerator.name(),
+            boxes.len(),
+            start.elapsed(),
+            stats.nodes,
+            stats.leaves,
+            stats.largest_leaf,
+            stats.depth,
+            acceleration.unbounded.len()
+        );
+        self.acceleration = acceleration;
+    }
+
+    // Adds a material the objects can refer to by the returned id
+    fn add_material(&mut self, material: impl Material + 'static) -> MaterialId {
+        self.materials.push(Box::new(material));
+        self.materials.len() - 1
+    }
+
+    // Material of a part of the object, like a triangle of a mesh
+    fn material(&self, object: &Object, part: usize) -> &dyn Material {
+        let id = object.shape.material(part).unwrap_or(object.material);
+        self.materials[id].as_ref()
+    }
+
+    // `material` of the object at `index`, for hits, with the id of its own from the arena
+    fn material_at(&self, index: usize, part: usize) -> &dyn Material {
+        let id = self.objects[index].shape.material(part).unwrap_or(self.acceleration.arena.materials[index]);
+        self.materials[id].as_ref()
+    }
+
+    // Light arriving from the environment, in the units of the scene. The sky is physically
+    // based and can be converted to nits, other environments are used as they are
+    fn environment_radiance(&self, direction: Vec3) -> Vec3 {
+        self.environment.radiance(direction) * self.settings.light_units.sky_scale(&self.environment)
+    }
+
+    // What the ray hits first between `min_t` and `max_t`: the index of the object, the object
+    // and where
+    fn closest_intersection(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<(usize, &Object, Intersection)> {
+        let _span = profile::span(Phase::Traversal);
+        let mut closest_t = max_t;
+        let mut closest = None;
+
+        for &index in &self.acceleration.unbounded {
+            let object = &self.objects[index];
+            if let Some(intersection) = object.shape.intersect(origin, direction, min_t, closest_t) {
+                closest_t = intersection.t;
+                closest = Some((index, object, intersection));
+            }
+        }
+        self.acceleration.structure.traverse(origin, direction, min_t, closest_t, &mut |k, max_t| {
+            if !self.acceleration.crosses(k, origin, direction, min_t, max_t) {
+                return None;
+            }
+            let index = self.acceleration.bounded[k];
+            let object = &self.objects[index];
+            let intersection = object.shape.intersect(origin, direction, min_t, max_t)?;
+            closest = Some((index, object, intersection));
+            Some(intersection.t)
+        });
+
+        closest
+    }
+
+    // Closest hits of a bundle of rays going about the same way, each an origin and a
+    // direction, like the primary rays of a few pixels next to each other. The acceleration
+    // structure is walked once for all of them when it can, and ray by ray otherwise
+    fn closest_intersections(&self, rays: &[(Vec3, Vec3)], min_t: f32, max_t: f32) -> Vec<Option<(usize, &Object, Intersection)>> {
+        if rays.len() == 1 {
+            return vec![self.closest_intersection(rays[0].0, rays[0].1, min_t, max_t)];
+        }
+        let _span = profile::span(Phase::Traversal);
+        let mut closest = vec![None; rays.len()];
+        let mut closest_t = vec![max_t; rays.len()];
+        for (k, &(origin, direction)) in rays.iter().enumerate() {
+            for &index in &self.acceleration.unbounded {
+                let object = &self.objects[index];
+                if let Some(intersection) = object.shape.intersect(origin, direction, min_t, closest_t[k]) {
+                    closest_t[k] = intersection.t;
+                    closest[k] = Some((index, object, intersection));
+                }
+            }
+        }
+        self.acceleration.structure.traverse_packet(rays, min_t, &mut closest_t, &mut |k, thing, max_t| {
+            if !self.acceleration.crosses(thing, rays[k].0, rays[k].1, min_t, max_t) {
+                return None;
+            }
+            let index = self.acceleration.bounded[thing];
+            let object = &self.objects[index];
+            let intersection = object.shape.intersect(rays[k].0, rays[k].1, min_t, max_t)?;
+            closest[k] = Some((index, object, intersection));
+            Some(intersection.t)
+        });
+        closest
+    }
+
+    // Whether anything is in the way of the ray between `min_t` and `max_t`. It doesn't matter
+    // what or how far, so the search stops at the first thing found
+    fn is_occluded(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> bool {
+        let _span = profile::span(Phase::Traversal);
+        self.objects_along(origin, direction, min_t, max_t, |object| object.shape.occludes(origin, direction, min_t, max_t))
+    }
+
+    // Calls `f` once with every object the segment of the ray from `min_t` to `max_t` could
+    // cross, until it returns true. Kd-trees and grids can come across the same object in more
+    // than one piece, and the ones already seen are skipped
+    fn objects_along(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, mut f: impl FnMut(&Object) -> bool) -> bool {
+        if self.acceleration.unbounded.iter().any(|&index| f(&self.objects[index])) {
+            return true;
+        }
+        let mut seen = vec![];
+        self.acceleration.structure.any_hit(origin, direction, min_t, max_t, &mut |k| {
+            if seen.contains(&k) || !self.acceleration.crosses(k, origin, direction, min_t, max_t) {
+                return false;
+            }
+            seen.push(k);
+            f(&self.objects[self.acceleration.bounded[k]])
+        })
+    }
+
+    // Shadow rays start slightly off the surface, on the side they're going towards, so that
+    // floating point errors in the hit point don't make the surface shadow itself
+    fn shadow_origin(&self, p: Vec3, n: Vec3, l: Vec3) -> Vec3 {
+        let offset = self.settings.normal_offset;
+        if n.dot(l) >= 0.0 { p + n * offset } else { p - n * offset }
+    }
+
+    // Whether anything blocks the way from `p` along `l` before `t_max`
+    fn occluded(&self, p: Vec3, n: Vec3, l: Vec3, t_max: f32) -> bool {
+        let origin = self.shadow_origin(p, n, l);
+        self.is_occluded(origin, l, self.settings.shadow_bias, t_max)
+    }
+
+    // Fraction of the light that makes it from `p` along `l` up to `t_max`. Opaque objects
+    // block it, transparent ones tint it with their transmission color. `ignore` is left out,
+    // e.g. the light source itself
+    fn transmittance(&self, p: Vec3, n: Vec3, l: Vec3, t_max: f32, ignore: Option<&Object>) -> Vec3 {
+        let _span = profile::span(Phase::Traversal);
+        let origin = self.shadow_origin(p, n, l);
+        let min_t = self.settings.shadow_bias;
+        let mut transmittance = Vec3::ONE;
+        self.objects_along(origin, l, min_t, t_max, |object| self.tint(object, origin, l, t_max, ignore, &mut transmittance));
+        transmittance
+    }
+
+    // `transmittance` along each of `rays` from `p`, a direction and the `t_max` along it, like
+    // the shadow rays towards the samples of an area light. With packets on they go through the
+    // acceleration structure together
+    fn transmittances(&self, p: Vec3, n: Vec3, rays: &[(Vec3, f32)], ignore: Option<&Object>) -> Vec<Vec3> {
+        if self.settings.packet_size == 0 || rays.len() < 2 {
+            return rays.iter().map(|&(l, t_max)| self.transmittance(p, n, l, t_max, ignore)).collect();
+        }
+        let _span = profile::span(Phase::Traversal);
+        let packet: Vec<(Vec3, Vec3)> = rays.iter().map(|&(l, _)| (self.shadow_origin(p, n, l), l)).collect();
+        let max_t: Vec<f32> = rays.iter().map(|&(_, t_max)| t_max).collect();
+        let min_t = self.settings.shadow_bias;
+        let mut transmittances = vec![Vec3::ONE; rays.len()];
+        let mut tint = |k: usize, object: &Object| self.tint(object, packet[k].0, packet[k].1, max_t[k], ignore, &mut transmittances[k]);
+        let mut blocked: Vec<bool> = (0..rays.len())
+            .map(|k| self.acceleration.unbounded.iter().any(|&index| tint(k, &self.objects[index])))
+            .collect();
+        // Like in `objects_along`, but what each ray has seen is kept apart
+        let mut seen = vec![vec![]; rays.len()];
+        self.acceleration.structure.any_hit_packet(&packet, min_t, &max_t, &mut blocked, &mut |k, thing| {
+            if seen[k].contains(&thing) || !self.acceleration.crosses(thing, packet[k].0, packet[k].1, min_t, max_t[k]) {
+                return false;
+            }
+            seen[k].push(thing);
+            tint(k, &self.objects[self.acceleration.bounded[thing]])
+        });
+        transmittances
+    }
+
+    // Tints `transmittance` by what's left of the light after it goes through `object` along
+    // the shadow ray, and says whether there's none left
+    fn tint(&self, object: &Object, origin: Vec3, l: Vec3, t_max: f32, ignore: Option<&Object>, transmittance: &mut Vec3) -> bool {
+        if ignore.is_some_and(|ignored| std::ptr::eq(ignored, object)) {
+            return false;
+        }
+        // Where the shadow ray first crosses the surface
+        if let Some(Intersection { t, part }) = object.shape.intersect(origin, l, self.settings.shadow_bias, t_max) {
+            let point = origin + t * l;
+            let hit = object.shape.surface_hit(point, part);
+            // The light goes through the whole object, tint it once
+            *transmittance *= self.material(object, part).transmission(&hit);
+        }
+        // Nothing gets past an opaque object, whatever else is in the way
+        *transmittance == Vec3::ZERO
+    }
+
+    // Light coming back along a primary ray from what it hits first, exposed for display.
+    // Nothing further than `far` along the rays after it counts either
+    fn shade_ray(&self, origin: Vec3, direction: Vec3, hit: Option<(usize, &Object, Intersection)>, far: f32) -> Vec3 {
+        let radiance = self.shade(origin, direction, hit, far, self.settings.max_depth, None);
+        radiance * self.settings.light_units.exposure()
+    }
+
+    // Light coming back along the ray. `depth` is how many more times it can bounce off
+    // reflective surfaces. Rays split up by dispersion only carry one color `channel`, the
+    // others are left out of the result by the caller. The rays it bounces into don't go
+    // further than `max_t` either
+    fn trace(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32, depth: u32, channel: Option<usize>) -> Vec3 {
+        let hit = self.closest_intersection(origin, direction, min_t, max_t);
+        self.shade(origin, direction, hit, max_t, depth, channel)
+    }
+
+    // Light coming back along the ray from what it hits first, already found, following the
+    // rays it bounces into up to `far`
+    fn shade(&self, origin: Vec3, direction: Vec3, hit: Option<(usize, &Object, Intersection)>, far: f32, depth: u32, channel: Option<usize>) -> Vec3 {
+        let _span = profile::span(Phase::Shading);
+        let (index, object, Intersection { t: closest_t, part }) = match hit {
+            None => return self.environment_radiance(direction),
+            Some(hit) => hit
         };
+
+        let p = origin + closest_t * direction;
+        let material = self.material_at(index, part);
+        let mut hit = object.shape.surface_hit(p, part);
+        material.orient(&mut hit, direction);
+        let n = hit.normal;
+        if material.catches_shadows() {
+            return self.catch_shadows(&hit, direction, (object, material), far, depth, channel);
+        }
+        // Glowing surfaces add their own light on top of the light they reflect
+        let mut radiance = material.emission(&hit) + compute_lighting(&hit, -direction, object, material, self, true);
+        if depth == 0 {
+            return radiance;
+        }
+
+        // Follow the reflected and refracted rays to see what they bring back
+        let bounces = self.settings.max_depth - depth;
+        for (k, ray) in material.scatter(&hit, direction).into_iter().enumerate() {
+            // Once a ray carries a single channel, the rays for the other ones don't count
+            if channel.is_some() && ray.channel.is_some() && ray.channel != channel {
+                continue;
+            }
+            let mut weight = ray.weight;
+            if self.settings.roulette_depth.is_some_and(|roulette_depth| bounces >= roulette_depth) {
+                let survival = weight.max_element().clamp(0.05, 1.0);
+                // Counted down from the top, so the choice doesn't follow the ones of the lights
+                if hash_random(p, u32::MAX - k as u32) >= survival {
+                    continue;
+                }
+                weight /= survival;
+            }
+            let origin = self.shadow_origin(p, n, ray.direction);
+            let channel = ray.channel.or(channel);
+            radiance += weight * self.trace(origin, ray.direction, self.settings.shadow_bias, far, depth - 1, channel);
+        }
+        radiance
+    }
+
+    // The background seen through a shadow catcher, darkened as much as the shadows darken the
+    // light falling on it, with the reflections it picks up on top
+    fn catch_shadows(&self, hit: &SurfaceHit, direction: Vec3, (object, material): (&Object, &dyn Material), far: f32, depth: u32, channel: Option<usize>) -> Vec3 {
+        let v = -direction;
+        let lit = compute_lighting(hit, v, object, material, self, true);
+        let unshadowed = compute_lighting(hit, v, object, material, self, false);
+        let ratio = |lit: f32, unshadowed: f32| if unshadowed > 0.0 { (lit / unshadowed).min(1.0) } else { 1.0 };
+        let shadow = Vec3::new(ratio(lit.x, unshadowed.x), ratio(lit.y, unshadowed.y), ratio(lit.z, unshadowed.z));
+
+        let mut radiance = self.environment_radiance(direction) * shadow;
+        if depth == 0 {
+            return radiance;
+        }
+        for ray in material.scatter(hit, direction) {
+            let origin = self.shadow_origin(hit.point, hit.normal, ray.direction);
+            let reflected = self.trace(origin, ray.direction, self.settings.shadow_bias, far, depth - 1, ray.channel.or(channel));
+            radiance = radiance * (Vec3::ONE - ray.weight) + reflected * ray.weight;
+        }
+        radiance
     }
 }
 
+// Color with 8 bits per channel, like the pixels of a frame
+#[derive(Copy, Clone)]
+struct Color {
+    r: u8,
+    g: u8,
+    b: u8
+}
+
+impl Color {
+    const BLACK: Color = Color::rgb(0, 0, 0);
+    const WHITE: Color = Color::rgb(255, 255, 255);
+
+    const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
+    }
+}
+
+// Pixel of a frame, from its top left corner
+#[derive(Copy, Clone)]
+struct Point {
+    x: i32,
+    y: i32
+}
+
+// Converts a linear color with channels in [0, 1] to 8 bits per channel
+fn to_color(c: Vec3) -> Color {
+    Color::rgb(
+        (c.x * 255.0) as u8,
+        (c.y * 255.0) as u8,
+        (c.z * 255.0) as u8
+    )
+}
+
 #[derive(Copy, Clone)]
 struct Canvas {
     width: i32,
@@ -84,171 +648,2387 @@ struct Canvas {
 }
 
 impl Canvas {
-    fn each(self, f: &mut dyn FnMut(i32, i32, i32, i32, Self)) {
-        for cx in (-self.width / 2)..(self.width / 2) {
-            for cy in (-self.height / 2)..(self.height / 2) {
-                f(cx, cy, self.width, self.height, self)
+    fn to_screen(self, x: i32, y: i32) -> Point {
+        Point { x: self.width / 2 + x, y: self.height / 2 - y }
+    }
+
+    // Where a canvas point goes in arrays with one entry per point, row by row from the bottom
+    fn index(self, (x, y): (i32, i32)) -> usize {
+        ((y + self.height / 2) * self.width + x + self.width / 2) as usize
+    }
+}
+
+// The rendered image, three bytes per pixel in RGB order, row by row from the top left corner.
+// That's the layout of the window texture, so the whole frame is uploaded to it at once
+struct Framebuffer {
+    width: usize,
+    pixels: Vec<u8>
+}
+
+impl Framebuffer {
+    fn new(canvas: Canvas) -> Framebuffer {
+        let (width, height) = (canvas.width as usize, canvas.height as usize);
+        let mut frame = Framebuffer { width, pixels: vec![0; width * height * 3] };
+        for index in 0..width * height {
+            frame.set(index, BACKGROUND_COLOR);
+        }
+        frame
+    }
+
+    fn set(&mut self, index: usize, color: Color) {
+        self.pixels[index * 3..index * 3 + 3].copy_from_slice(&[color.r, color.g, color.b]);
+    }
+
+    // Bytes from one row to the next
+    fn pitch(&self) -> usize {
+        self.width * 3
+    }
+
+    // This frame with another one as tall on its right, like the pictures of a stereo pair are
+    // put side by side
+    fn beside(&self, other: &Framebuffer) -> Framebuffer {
+        let mut pixels = Vec::with_capacity(self.pixels.len() + other.pixels.len());
+        for (left, right) in self.pixels.chunks(self.pitch()).zip(other.pixels.chunks(other.pitch())) {
+            pixels.extend(left);
+            pixels.extend(right);
+        }
+        Framebuffer { width: self.width + other.width, pixels }
+    }
+
+    // Writes the frame to a binary .ppm file, which is the same bytes after a short header
+    fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let height = self.pixels.len() / self.pitch();
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, height).into_bytes();
+        bytes.extend(&self.pixels);
+        std::fs::write(path, bytes)
+    }
+}
+
+// Renders the frames of a window, keeping what stays the same from one frame to the next
+struct Renderer {
+    canvas: Canvas,
+    // What the ray through each canvas point hits first, the object by its index, and the
+    // camera they were traced from. Turning lights on and off or changing materials doesn't
+    // move anything, so only the shading is done again. None for points not traced yet
+    first_hits: Vec<Option<Option<(usize, Intersection)>>>,
+    traced_from: Option<Camera>,
+    // Light through each pixel summed over the last `frames` frames, since the scene last
+    // changed
+    accumulated: Vec<Vec3>,
+    frames: u32,
+    // Other machines rendering tiles too, when the window listens for them
+    remote: Option<Workers>
+}
+
+impl Renderer {
+    fn new(canvas: Canvas) -> Renderer {
+        let pixels = (canvas.width * canvas.height) as usize;
+        Renderer { canvas, first_hits: vec![None; pixels], traced_from: None, accumulated: vec![Vec3::ZERO; pixels], frames: 0, remote: None }
+    }
+
+    // Traces the whole canvas into a new frame, coarse to fine. The first pass traces a point in
+    // every block of `coarsest` by `coarsest` pixels and fills the block with its color, and each
+    // pass after that halves the blocks, tracing only the points the ones before didn't, down to
+    // blocks of `finest`, 1 for every pixel. Both are powers of 2. Every pass is split into tiles, rendered by as many threads as there are
+    // cores and by the workers on other machines, from the middle of the picture out. `preview` is called with the frame after every
+    // pass but the last, and every so often while a pass goes on, to show the picture as it
+    // comes along
+    fn render(&mut self, scene: &Scene, camera: Camera, coarsest: i32, finest: i32, mut preview: impl FnMut(&Framebuffer)) -> Framebuffer {
+        let canvas = self.canvas;
+        // The objects stay where they are while the window is open, only the camera can make the
+        // hits out of date
+        if self.traced_from != Some(camera) {
+            self.first_hits.fill(None);
+            self.traced_from = Some(camera);
+        }
+        let mut frame = Framebuffer::new(canvas);
+        // What each pixel sees first, to find the edges outlines are drawn along. That needs every
+        // pixel, so frames made of blocks go without
+        let outlines = finest == 1 && scene.materials.iter().any(|material| material.outline().is_some());
+        let mut visible = vec![None; (canvas.width * canvas.height) as usize];
+        let tiles = tiles::spiral(canvas.width, canvas.height, TILE_SIZE);
+        let workers = std::thread::available_parallelism().map_or(1, |count| count.get());
+
+        let mut block = coarsest;
+        loop {
+            let pass = Pass { canvas, camera, block, coarsest, outlines };
+            // Workers on other machines don't send back what the points see, which outlines
+            // need
+            let remote = match &self.remote {
+                Some(remote) if !outlines => std::mem::take(&mut *remote.lock().unwrap()),
+                _ => vec![]
+            };
+            let queue = TileQueue::new(&tiles, workers + remote.len());
+            let first_hits = &self.first_hits;
+            let mut new_hits = vec![];
+            let mut still_there = vec![];
+            std::thread::scope(|threads| {
+                let (sender, receiver) = mpsc::channel();
+                let mut remote_threads = vec![];
+                for (k, stream) in remote.into_iter().enumerate() {
+                    let (queue, sender) = (&queue, sender.clone());
+                    remote_threads.push(threads.spawn(move || {
+                        let mut stream = Some(stream);
+                        while let Some(tile) = queue.next(workers + k) {
+                            let lights = scene.lighting.iter().map(|light| light.enabled).collect();
+                            let job = Job { width: canvas.width, height: canvas.height, tile, block, coarsest, camera, lights };
+                            let traced = match stream.as_mut().map(|stream| network::trace_remotely(stream, &job)) {
+                                Some(Ok(points)) => points.into_iter().map(|(cx, cy, radiance)| TracedPoint { cx, cy, radiance, seen: None, first_hit: None }).collect(),
+                                // The worker is gone, its tiles are rendered here from then on
+                                Some(Err(error)) => {
+                                    println!("Lost a worker: {}", error);
+                                    stream = None;
+                                    trace_tile(scene, tile, pass, first_hits)
+                                },
+                                None => trace_tile(scene, tile, pass, first_hits)
+                            };
+                            if sender.send(traced).is_err() {
+                                break;
+                            }
+                        }
+                        stream
+                    }));
+                }
+                for worker in 0..workers {
+                    let (queue, sender) = (&queue, sender.clone());
+                    threads.spawn(move || {
+                        while let Some(tile) = queue.next(worker) {
+                            let traced = trace_tile(scene, tile, pass, first_hits);
+                            if sender.send(traced).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+                drop(sender);
+
+                let mut shown = Instant::now();
+                for traced in receiver {
+                    for TracedPoint { cx, cy, radiance, seen, first_hit } in traced {
+                        if let Some(first_hit) = first_hit {
+                            new_hits.push((canvas.index((cx, cy)), first_hit));
+                        }
+                        let screen = canvas.to_screen(cx, cy);
+                        if screen.y < canvas.height {
+                            // The block goes right and up from the point, like the canvas
+                            let color = to_color(radiance);
+                            for y in (screen.y - block + 1).max(0)..=screen.y {
+                                for x in screen.x..(screen.x + block).min(canvas.width) {
+                                    frame.set((y * canvas.width + x) as usize, color);
+                                }
+                            }
+                            let index = (screen.y * canvas.width + screen.x) as usize;
+                            visible[index] = seen;
+                            self.accumulated[index] = radiance;
+                        }
+                    }
+                    if shown.elapsed() > PREVIEW_INTERVAL {
+                        preview(&frame);
+                        shown = Instant::now();
+                    }
+                }
+                still_there.extend(remote_threads.into_iter().filter_map(|thread| thread.join().unwrap()));
+            });
+            if let Some(remote) = &self.remote {
+                remote.lock().unwrap().extend(still_there);
+            }
+            for (index, first_hit) in new_hits {
+                self.first_hits[index] = Some(first_hit);
+            }
+            if block <= finest {
+                break;
+            }
+            preview(&frame);
+            block /= 2;
+        }
+
+        // A frame of every pixel is the first of the ones averaged together. Outlines are drawn
+        // over the picture, so frames with them aren't
+        self.frames = if finest == 1 && !outlines { 1 } else { 0 };
+        if outlines {
+            draw_outlines(&mut frame, &visible, canvas, scene);
+        }
+        frame
+    }
+}
+
+// What a pass of a frame traces, the same for all of its tiles
+#[derive(Copy, Clone)]
+struct Pass {
+    canvas: Canvas,
+    camera: Camera,
+    // Side of the blocks of the pass, and of the first pass of the frame
+    block: i32,
+    coarsest: i32,
+    // Whether to find what each point sees, for outlines
+    outlines: bool
+}
+
+// A canvas point traced in a pass and the light through it. With outlines, also the object
+// and part it sees, how far away and the normal there. `first_hit` is what it hits first when
+// that was just traced and can be kept for later frames
+struct TracedPoint<'a> {
+    cx: i32,
+    cy: i32,
+    radiance: Vec3,
+    seen: Option<(&'a Object, usize, f32, Vec3)>,
+    first_hit: Option<Option<(usize, Intersection)>>
+}
+
+// Traces the points of the tile the pass needs, all of them but the ones passes before it
+// traced. `first_hits` are the ones known from earlier frames, by canvas point, if any
+fn trace_tile<'a>(scene: &'a Scene, tile: Tile, pass: Pass, first_hits: &[Option<Option<(usize, Intersection)>>]) -> Vec<TracedPoint<'a>> {
+    let Pass { canvas, camera, block, coarsest, outlines } = pass;
+    let traced_before = |(cx, cy): (i32, i32)| {
+        block < coarsest && (cx + canvas.width / 2) % (2 * block) == 0 && (cy + canvas.height / 2) % (2 * block) == 0
+    };
+
+    let mut traced = vec![];
+    // For each square of points in the tile, a single point unless the scene traces rays in
+    // packets...
+    tile.each_square(scene.settings.packet_size.max(1) as i32, block, |points| {
+        // Get the casted rays, from the camera and through the canvas points. Points it doesn't
+        // see through, outside the circle of a fisheye lens, are left black. When the camera
+        // blurs they go through a point of the lens and at a time of their own, like the other
+        // samples of the pixel, rather than all through the middle of the lens when the shutter
+        // opens
+        let mut rays = vec![];
+        let mut seen_through = vec![];
+        for &(cx, cy) in points.iter().filter(|&&point| !traced_before(point)) {
+            let point = Vec2::new(cx as f32, cy as f32);
+            let ray = if camera.blurs() {
+                let pixel = point.extend(0.0);
+                camera.sample_ray(canvas, point, Vec3::new(hash_random(pixel, 0), hash_random(pixel, 1), hash_random(pixel, 2)))
+            } else {
+                camera.ray(canvas, point)
+            };
+            match ray {
+                Some(ray) => {
+                    rays.push(ray);
+                    seen_through.push((cx, cy));
+                },
+                None => traced.push(TracedPoint { cx, cy, radiance: Vec3::ZERO, seen: None, first_hit: None })
             }
         }
+        let points = seen_through;
+        // Traced again unless every point of the square was traced before, which holds for
+        // empty squares too
+        let cached: Option<Vec<_>> = points.iter().map(|&point| first_hits.get(canvas.index(point)).copied().flatten()).collect();
+        let hits = match &cached {
+            Some(cached) => cached.iter().map(|hit| hit.map(|(index, intersection)| (index, &scene.objects[index], intersection))).collect(),
+            None => scene.closest_intersections(&rays, camera.near, camera.far)
+        };
+
+        for (&(cx, cy), (&(origin, direction), hit)) in points.iter().zip(rays.iter().zip(hits)) {
+            // If the ray hits something, get the color of the object we hit, with more rays
+            // through the pixel while it looks noisy
+            let seed = (cx as u32 as u64) << 32 | cy as u32 as u64;
+            let ray = |offset, sample| camera.sample_ray(canvas, Vec2::new(cx as f32, cy as f32) + offset, sample);
+            let radiance = sample_pixel(scene, (origin, direction), hit, ray, (camera.near, camera.far), seed);
+            let seen = if outlines {
+                hit.map(|(_, object, Intersection { t, part })| {
+                    let p = origin + t * direction;
+                    (object, part, t * direction.length(), object.shape.surface_hit(p, part).normal)
+                })
+            } else {
+                None
+            };
+            let first_hit = cached.is_none().then(|| hit.map(|(index, _, intersection)| (index, intersection)));
+            traced.push(TracedPoint { cx, cy, radiance, seen, first_hit });
+        }
+    });
+    traced
+}
+
+// Renders tiles for the coordinator at the address until it goes away, each with the lights
+// turned on and off like in its window
+fn work_for(scene: &mut Scene, address: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect(address).map_err(|e| format!("couldn't reach the coordinator at {}: {}", address, e))?;
+    let _ = stream.set_nodelay(true);
+    println!("Rendering tiles for {}", address);
+    let mut jobs = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    while let Ok(job) = Job::read(&mut jobs, scene.lighting.len()) {
+        for (light, &on) in scene.lighting.iter_mut().zip(&job.lights) {
+            light.enabled = on;
+        }
+        let canvas = Canvas { width: job.width, height: job.height };
+        let pass = Pass { canvas, camera: job.camera, block: job.block, coarsest: job.coarsest, outlines: false };
+        let points: Vec<(i32, i32, Vec3)> = trace_tile(scene, job.tile, pass, &[]).into_iter().map(|point| (point.cx, point.cy, point.radiance)).collect();
+        if network::write_points(&mut stream, &points).is_err() {
+            break;
+        }
     }
+    println!("The coordinator is gone");
+    Ok(())
+}
 
-    fn to_screen(self, x: i32, y: i32) -> Point {
-        let sx = (self.width / 2) + x;
-        let sy = (self.height / 2) - y;
-        Point::new(sx as i32, sy as i32)
+// Light through a pixel, from the ray through its middle, already traced, and rays through
+// random points of it while the average is still noisy: at least `min_samples` of them, and
+// then more until the standard error of the brightness falls under the noise threshold, up to
+// `max_samples`. `ray` gives the ray through a point of the pixel, by how far it is from the
+// middle in pixels, and a point of the unit cube for the lens and shutter, if the camera sees
+// through it, only seeing between the `near` and `far` clip distances, and `seed` tells it
+// apart from the others
+fn sample_pixel(scene: &Scene, (origin, direction): (Vec3, Vec3), hit: Option<(usize, &Object, Intersection)>, ray: impl Fn(Vec2, Vec3) -> Option<(Vec3, Vec3)>, (near, far): (f32, f32), seed: u64) -> Vec3 {
+    let settings = &scene.settings;
+    let first = scene.shade_ray(origin, direction, hit, far);
+    // Running mean and sum of squared differences of the brightness (Welford)
+    let (mut sum, mut mean, mut squares) = (first, luminance(first), 0.0);
+    let mut samples = 1;
+    let mut rng = Rng::new(seed);
+    while samples < settings.max_samples {
+        if samples >= settings.min_samples.max(2) {
+            let error = (squares / (samples * (samples - 1)) as f32).sqrt();
+            if error < settings.noise_threshold {
+                break;
+            }
+        }
+        let offset = Vec2::new(rng.next_f32() - 0.5, rng.next_f32() - 0.5);
+        let radiance = match ray(offset, Vec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32())) {
+            Some((origin, direction)) => scene.shade_ray(origin, direction, scene.closest_intersection(origin, direction, near, far), far),
+            None => Vec3::ZERO
+        };
+        samples += 1;
+        sum += radiance;
+        let value = luminance(radiance);
+        let delta = value - mean;
+        mean += delta / samples as f32;
+        squares += delta * (value - mean);
     }
+    sum / samples as f32
 }
 
-fn canvas_2_viewport(
-    x: i32,
-    y: i32,
-    width: i32,
-    height: i32,
-    viewport: Vec3,
-) -> Vec3 {
-    return Vec3::new(
-        x as f32 * (viewport.x as f32 / width as f32),
-        y as f32 * (viewport.y as f32 / height as f32),
-        viewport.z as f32,
-    );
+fn luminance(c: Vec3) -> f32 {
+    c.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+// Inks the pixels of objects with an outline where the next pixel shows another object, or the
+// same one with a very different normal or depth
+fn draw_outlines(frame: &mut Framebuffer, visible: &[Option<(&Object, usize, f32, Vec3)>], canvas: Canvas, scene: &Scene) {
+    let (width, height) = (canvas.width as usize, canvas.height as usize);
+    let edge = |a: &Option<(&Object, usize, f32, Vec3)>, b: &Option<(&Object, usize, f32, Vec3)>| match (a, b) {
+        (Some((object_a, _, depth_a, normal_a)), Some((object_b, _, depth_b, normal_b))) => {
+            !std::ptr::eq(*object_a, *object_b)
+                || normal_a.dot(*normal_b) < 0.8
+                || (depth_a - depth_b).abs() > 0.05 * depth_a.min(*depth_b)
+        },
+        (None, None) => false,
+        _ => true
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let ink = match visible[index] {
+                Some((object, part, _, _)) => scene.material(object, part).outline(),
+                None => None
+            };
+            let ink = match ink {
+                Some(ink) => ink,
+                None => continue
+            };
+            let neighbours = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < width).then(|| index + 1),
+                (y > 0).then(|| index - width),
+                (y + 1 < height).then(|| index + width)
+            ];
+            if neighbours.into_iter().flatten().any(|neighbour| edge(&visible[index], &visible[neighbour])) {
+                frame.set(index, to_color(ink));
+            }
+        }
+    }
 }
 
+#[derive(Copy, Clone)]
 enum LightType {
     Ambient,
     Point,
-    Directional
+    Directional,
+    // Shines from `position` towards `direction`. Angles are in degrees, measured from the
+    // cone axis: full intensity inside the inner cone, fading out to nothing at the outer one
+    Spot {
+        inner_angle: f32,
+        outer_angle: f32,
+        falloff: f32
+    },
+    // Rectangle centered on `position`, spanned by the `u` and `v` edge vectors
+    Rect {
+        u: Vec3,
+        v: Vec3
+    },
+    // Disc centered on `position`, facing `direction`
+    Disc {
+        radius: f32
+    },
+    // Rectangular opening like a Rect light, the environment only lights the scene through
+    // it. Meant for interiors, where most directions from a point hit walls and a few
+    // windows let all the light in
+    Portal {
+        u: Vec3,
+        v: Vec3
+    }
+}
+
+// How the intensity of a light decreases with the distance to the lit point
+enum Attenuation {
+    None,
+    Linear,
+    InverseSquare,
+    // 1 / (constant + linear * d + quadratic * d^2)
+    Custom {
+        constant: f32,
+        linear: f32,
+        quadratic: f32
+    }
+}
+
+impl Attenuation {
+    fn factor(&self, distance: f32) -> f32 {
+        match *self {
+            Attenuation::None => 1.0,
+            Attenuation::Linear => 1.0 / distance,
+            Attenuation::InverseSquare => 1.0 / (distance * distance),
+            Attenuation::Custom { constant, linear, quadratic } => {
+                1.0 / (constant + linear * distance + quadratic * distance * distance)
+            }
+        }
+    }
 }
 
 struct Light {
     kind: LightType,
+    // RGB tint of the light, each channel in [0, 1], scaled by the intensity
+    color: Vec3,
+    // See LightUnits for what the value means
     intensity: f32,
     position: Option<Vec3>,
-    direction: Option<Vec3>
+    direction: Option<Vec3>,
+    // Only used by lights that have a position, physical light units always use inverse square
+    attenuation: Attenuation,
+    // Angular intensity distribution of a real fixture, for point and spot lights.
+    // Points lights without a direction are assumed to point down
+    profile: Option<IesProfile>,
+    // Which objects the light shines on
+    link: LightLink,
+    // Disabled lights are left out of the render, but stay in the scene
+    enabled: bool
 }
 
-fn compute_lighting(p: Vec3, n: Vec3, scene: &Scene) -> f32 {
-    let mut i = 0.0;
+// Restricts a light to some objects, by name
+enum LightLink {
+    All,
+    Only(Vec<String>),
+    Except(Vec<String>)
+}
 
-    for light in &scene.lighting {
-        match light.kind {
-            LightType::Ambient => {
-                i += light.intensity;
-            },
-            LightType::Point => {
-                let l = light.position.unwrap() - p;
-                let n_dot_l = n.dot(l);
-                // If the angle between the normal and the light vector is greater than 90, 
-                // the light is coming from behind the surface, so it cannot contribute to the lighting
-                if n_dot_l > 0.0 {
-                    i += light.intensity * n_dot_l / (n.length() * l.length());
-                }
-            },
-            LightType::Directional => {
-                let l = light.direction.unwrap();
-                let n_dot_l = n.dot(l);
-                // If the angle between the normal and the light vector is greater than 90, 
-                // the light is coming from behind the surface, so it cannot contribute to the lighting
-                if n_dot_l > 0.0 {
-                    i += light.intensity * n_dot_l / (n.length() * l.length());
-                }
-            }
+impl LightLink {
+    fn affects(&self, name: &str) -> bool {
+        match self {
+            LightLink::All => true,
+            LightLink::Only(names) => names.iter().any(|n| n == name),
+            LightLink::Except(names) => !names.iter().any(|n| n == name)
         }
     }
+}
+
+impl Light {
+    // Rough measure of how much light it gives off, to compare lights with each other
+    fn power(&self) -> f32 {
+        self.intensity * (self.color.x + self.color.y + self.color.z) / 3.0
+    }
 
-    return i;
+    // How much of the light goes towards `to_point`, as shaped by the photometric profile
+    fn profile_factor(&self, to_point: Vec3) -> f32 {
+        match &self.profile {
+            Some(profile) => profile.intensity_towards(to_point, self.direction.unwrap_or(Vec3::NEG_Y)),
+            None => 1.0
+        }
+    }
 }
 
-fn main() {
-    let canvas = Canvas{ width: 800, height: 600 };
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Raytracer", canvas.width as u32, canvas.height as u32)
-        .build()
-        .unwrap();
-    let mut sdl_canvas = window.into_canvas().present_vsync().build().unwrap();
-    let scene = Scene {
-        spheres: vec![
-            Sphere {
-                center: Vec3::new(0.0, -1.0, 3.0),
-                radius: 1.0,
-                color: Color::RGB(255, 0, 0),
-            },
-            Sphere {
-                center: Vec3::new(2.0, 0.0, 4.0),
-                radius: 1.0,
-                color: Color::RGB(0, 0, 255),
-            },
-            Sphere {
-                center: Vec3::new(-2.0, 0.0, 4.0),
-                radius: 1.0,
-                color: Color::RGB(0, 255, 0),
-            },
-            Sphere {
-                center: Vec3::new(0.0, -5001.0, 0.0),
-                radius: 5000.0,
-                color: Color::RGB(255, 255, 0)
-            }
-        ],
-        lighting: vec![
-            Light {
-                kind: LightType::Ambient,
-                intensity: 0.2,
-                position: None,
-                direction: None
-            },
-            Light {
-                kind: LightType::Point,
-                intensity: 0.6,
-                position: Some(Vec3::new(2.0, 1.0, 0.0)),
-                direction: None
-            },
-            Light {
-                kind: LightType::Directional,
-                intensity: 0.2,
-                position: None,
-                direction: Some(Vec3::new(1.0, 4.0, 4.0))
-            }
-        ]
-    };
+// How much of a spot light reaches a point lit along `to_point`, 1 inside the inner cone and 0 outside the outer one
+fn spot_attenuation(to_point: Vec3, axis: Vec3, inner_angle: f32, outer_angle: f32, falloff: f32) -> f32 {
+    let cos_angle = to_point.normalize().dot(axis.normalize());
+    let cos_inner = inner_angle.to_radians().cos();
+    let cos_outer = outer_angle.to_radians().cos();
+    if cos_angle <= cos_outer {
+        return 0.0;
+    }
+    if cos_angle >= cos_inner {
+        return 1.0;
+    }
 
-    // This is the camera origin
-    let origin = Vec3::new(0.0, 0.0, 0.0);
-    let viewport = Vec3::new(
-        // Viewport size or Frame size
-        1.0, 1.0, 
-        // Frame distance
-        1.0);
+    ((cos_angle - cos_outer) / (cos_inner - cos_outer)).powf(falloff)
+}
 
-    // For each point in the canvas...
-    canvas.each(&mut |cx, cy, width, height, instance| {
-        // Get the direction of the casted ray, from O and passing through V, that would go into the canvas point
-        let direction = canvas_2_viewport(cx, cy, width, height, viewport);
+// Points spread evenly over the surface of an area light where shadow rays are aimed at
+fn area_light_points(light: &Light, samples: u32) -> Vec<Vec3> {
+    let center = light.position.unwrap();
+    match light.kind {
+        LightType::Rect { u, v } | LightType::Portal { u, v } => {
+            // Stratify the rectangle into an n by n grid and take the center of each cell
+            let n = (samples as f32).sqrt().ceil().max(1.0) as u32;
+            let mut points = Vec::new();
+            for i in 0..n {
+                for j in 0..n {
+                    let s = (i as f32 + 0.5) / n as f32 - 0.5;
+                    let t = (j as f32 + 0.5) / n as f32 - 0.5;
+                    points.push(center + s * u + t * v);
+                }
+            }
+            points
+        },
+        LightType::Disc { radius } => disc_points(center, light.direction.unwrap(), radius, samples),
+        _ => vec![center]
+    }
+}
 
-        // See if the ray hits something, and if so, get the color of the object we hit
-        let color = scene.trace_ray(origin, direction, 1.0, INF);
+// Vogel's spiral, which covers a disc evenly for any number of points
+fn disc_points(center: Vec3, normal: Vec3, radius: f32, samples: u32) -> Vec<Vec3> {
+    let (tangent, bitangent) = normal.normalize().any_orthonormal_pair();
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+    let n = samples.max(1);
+    (0..n)
+        .map(|k| {
+            let r = radius * ((k as f32 + 0.5) / n as f32).sqrt();
+            let theta = k as f32 * golden_angle;
+            center + r * (theta.cos() * tangent + theta.sin() * bitangent)
+        })
+        .collect()
+}
 
-        sdl_canvas.set_draw_color(color);
+// Cosine distributed directions over the hemisphere around `n`, so averaging what they see
+// gives the diffuse lighting from that hemisphere. They follow a Fibonacci spiral, which covers
+// the hemisphere a lot more evenly than independent random directions, rotated and jittered
+// differently at each point so the pattern doesn't show up as bands
+fn cosine_hemisphere_directions(p: Vec3, n: Vec3, samples: u32) -> Vec<Vec3> {
+    let n = n.normalize();
+    let mut rng = Rng::from_point(p, 0);
+    let rotation = rng.next_f32();
+    // The golden angle as a fraction of a turn
+    let golden_turn = (3.0 - 5.0_f32.sqrt()) / 2.0;
+    let count = samples.max(1);
+    (0..count)
+        .map(|k| {
+            let u1 = (k as f32 + rng.next_f32()) / count as f32;
+            let u2 = (k as f32 * golden_turn + rotation).fract();
+            cosine_hemisphere(n, u1, u2)
+        })
+        .collect()
+}
 
-        sdl_canvas
-            .draw_point(instance.to_screen(cx, cy))
-            .unwrap();
-    });
+// Fraction of the hemisphere around the normal that isn't blocked by nearby objects,
+// which scales the ambient light so creases and contact points get darker
+fn ambient_visibility(p: Vec3, n: Vec3, scene: &Scene) -> f32 {
+    let settings = &scene.settings;
+    if settings.ao_samples == 0 {
+        return 1.0;
+    }
 
-    sdl_canvas.present();
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    let directions = cosine_hemisphere_directions(p, n, settings.ao_samples);
+    let total = directions.len();
+    let open = directions
+        .into_iter()
+        .filter(|&d| !scene.occluded(p, n, d, settings.ao_distance))
+        .count();
+    open as f32 / total as f32
+}
 
-    'running: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'running,
-                _ => {}
+// Light reaching the point from a light that isn't ambient
+// Without `shadows` nothing blocks the light
+fn direct_light(light: &Light, hit: &SurfaceHit, v: Vec3, material: &dyn Material, scene: &Scene, shadows: bool) -> Vec3 {
+    let (p, n) = (hit.point, hit.normal);
+    let units = scene.settings.light_units;
+    let mut light_color = light.color * units.light_scale(light);
+    // Vectors from the point to the light, how far along each of them the light is, and the
+    // light arriving along them. Area lights are sampled at several points, so they can be
+    // partially hidden
+    let samples = match light.kind {
+        LightType::Ambient => return Vec3::ZERO,
+        // The light is at t = 1 along the shadow ray, anything past it can't cast a shadow
+        LightType::Point => {
+            let l = light.position.unwrap() - p;
+            light_color *= light.profile_factor(-l);
+            vec![(l, 1.0, light_color)]
+        },
+        LightType::Directional => vec![(light.direction.unwrap(), INF, light_color)],
+        LightType::Spot { inner_angle, outer_angle, falloff } => {
+            let l = light.position.unwrap() - p;
+            light_color *= spot_attenuation(-l, light.direction.unwrap(), inner_angle, outer_angle, falloff);
+            light_color *= light.profile_factor(-l);
+            if light_color == Vec3::ZERO {
+                return Vec3::ZERO;
             }
+            vec![(l, 1.0, light_color)]
+        },
+        LightType::Rect { .. } | LightType::Disc { .. } => {
+            area_light_points(light, scene.settings.area_light_samples)
+                .into_iter()
+                .map(|point| (point - p, 1.0, light_color))
+                .collect()
+        },
+        // The environment seen through the opening, weighted by how big the opening looks from
+        // the point. The ray has to get all the way out of the scene
+        LightType::Portal { u, v: edge_v } => {
+            let normal = u.cross(edge_v);
+            let area = normal.length();
+            area_light_points(light, scene.settings.area_light_samples)
+                .into_iter()
+                .map(|point| {
+                    let l = point - p;
+                    let cos_portal = normal.dot(l).abs() / (area * l.length());
+                    let solid_angle = area * cos_portal / (std::f32::consts::PI * l.length_squared());
+                    (l, INF, light_color * scene.environment_radiance(l) * solid_angle)
+                })
+                .collect()
+        }
+    };
+
+    // Average the samples, the ones that are blocked contribute nothing
+    let mut i = Vec3::ZERO;
+    let weight = 1.0 / samples.len() as f32;
+    let rays: Vec<(Vec3, f32)> = samples.iter().map(|&(l, t_max, _)| (l, t_max)).collect();
+    let transmittances = if shadows { scene.transmittances(p, n, &rays, None) } else { vec![Vec3::ONE; rays.len()] };
+    for ((l, _, radiance), transmittance) in samples.into_iter().zip(transmittances) {
+        if transmittance == Vec3::ZERO {
+            continue;
         }
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        let distance_falloff = match (light.kind, units) {
+            (LightType::Directional, _) | (LightType::Portal { .. }, _) => 1.0,
+            // Physical light always falls off with the square of the distance
+            (_, LightUnits::Physical { .. }) => 1.0 / l.length_squared(),
+            (_, LightUnits::Relative) => light.attenuation.factor(l.length())
+        };
+        i += radiance * transmittance * weight * distance_falloff * material.shade(hit, l, v, &scene.settings);
+    }
+    i
+}
+
+// Picks `count` lights at random, with probabilities proportional to `weights`, along with the
+// probability each one had of being picked
+fn pick_lights<'a>(lights: &[&'a Light], weights: &[f32], count: u32, p: Vec3) -> Vec<(&'a Light, f32)> {
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return vec![];
+    }
+
+    (0..count)
+        .map(|k| {
+            let mut target = hash_random(p, k) * total;
+            let mut index = lights.len() - 1;
+            for (j, &weight) in weights.iter().enumerate() {
+                if target < weight {
+                    index = j;
+                    break;
+                }
+                target -= weight;
+            }
+            (lights[index], weights[index] / total)
+        })
+        .collect()
+}
+
+// Light reflected towards `v` by `material`, the one of the object at the hit point. Without `shadows`
+// nothing blocks the light, which shadow catchers compare against
+fn compute_lighting(hit: &SurfaceHit, v: Vec3, object: &Object, material: &dyn Material, scene: &Scene, shadows: bool) -> Vec3 {
+    let (p, n) = (hit.point, hit.normal);
+    let transmittances = |rays: &[(Vec3, f32)], ignore: Option<&Object>| {
+        if shadows { scene.transmittances(p, n, rays, ignore) } else { vec![Vec3::ONE; rays.len()] }
+    };
+    let ambient_visibility = if shadows { ambient_visibility(p, n, scene) } else { 1.0 };
+    // Light arriving from every direction, reflected by the material as a whole at the end
+    let mut ambient_light = Vec3::ZERO;
+    let mut i = Vec3::ZERO;
+
+    let lights: Vec<&Light> = scene
+        .lighting
+        .iter()
+        .filter(|light| light.enabled && light.link.affects(&object.name))
+        .collect();
+    // Ambient lights don't cast shadow rays, so they are always cheap enough to add
+    let (ambient, direct): (Vec<&Light>, Vec<&Light>) = lights
+        .into_iter()
+        .partition(|light| matches!(light.kind, LightType::Ambient));
+    for light in ambient {
+        ambient_light += light.color * light.intensity * ambient_visibility;
+    }
+
+    match &scene.settings.light_sampling {
+        // Only a few of the lights are evaluated, each one scaled by how unlikely it was to be
+        // picked, so on average the result is the same as evaluating all of them
+        Some(sampling) if (sampling.count as usize) < direct.len() => {
+            let weights: Vec<f32> = direct
+                .iter()
+                .map(|light| if sampling.power_weighted { light.power() } else { 1.0 })
+                .collect();
+            for (light, probability) in pick_lights(&direct, &weights, sampling.count, p) {
+                let contribution = direct_light(light, hit, v, material, scene, shadows);
+                i += contribution / (sampling.count as f32 * probability);
+            }
+        },
+        _ => {
+            for light in direct {
+                i += direct_light(light, hit, v, material, scene, shadows);
+            }
+        }
+    }
+
+    // When there are portals the environment only comes in through them
+    let has_portals = scene
+        .lighting
+        .iter()
+        .any(|light| light.enabled && matches!(light.kind, LightType::Portal { .. }));
+
+    // The sun is a directional light, and the rest of the sky lights everything a bit
+    if let Environment::Sky(sky) = &scene.environment {
+        let units = scene.settings.light_units;
+        if !has_portals {
+            ambient_light += sky.ambient() * units.sky_scale(&scene.environment) * ambient_visibility;
+        }
+        let l = sky.sun_direction();
+        if l.y > 0.0 {
+            let transmittance = if shadows { scene.transmittance(p, n, l, INF, None) } else { Vec3::ONE };
+            i += sky.sun_color() * transmittance * units.sun_scale() * material.shade(hit, l, v, &scene.settings);
+        }
+    }
+
+    // Instead of a constant ambient term, gather the light coming from the environment map
+    // over the hemisphere around the normal, except where something is in the way
+    if let (Environment::Hdri(map), false) = (&scene.environment, has_portals) {
+        let directions = cosine_hemisphere_directions(p, n, scene.settings.environment_samples);
+        let weight = 1.0 / directions.len() as f32;
+        let rays: Vec<(Vec3, f32)> = directions.iter().map(|&d| (d, INF)).collect();
+        for (d, transmittance) in directions.into_iter().zip(transmittances(&rays, None)) {
+            ambient_light += map.radiance(d) * transmittance * weight;
+        }
+    }
+
+    // Emissive objects light their surroundings too. From far away an object looks like a disc
+    // facing the point as big as its bounding sphere, so sample it like a disc light
+    for &Emitter { object: index, center, radius, ref parts, materials } in &scene.acceleration.emitters {
+        let emitter = &scene.objects[index];
+        let to_center = center - p;
+        let distance = to_center.length();
+        // Points on the emitter itself are not lit by it
+        if distance <= radius + scene.settings.shadow_bias {
+            continue;
+        }
+        // The glow of the side facing the point, of the materials that glow shared out over all
+        // of them
+        let facing = center - to_center / distance * radius;
+        let emission = parts
+            .iter()
+            .map(|&part| scene.material(emitter, part).emission(&emitter.shape.surface_hit(facing, part)))
+            .sum::<Vec3>()
+            / materials as f32;
+        if emission == Vec3::ZERO {
+            continue;
+        }
+        // Irradiance from a uniformly glowing sphere falls off with the square of the distance
+        let falloff = (radius / distance).powi(2);
+        let samples = disc_points(center, to_center, radius, scene.settings.area_light_samples);
+        let weight = 1.0 / samples.len() as f32;
+        let rays: Vec<(Vec3, f32)> = samples.iter().map(|&point| (point - p, 1.0)).collect();
+        // The shadow rays can hit the emitter itself, only other objects block them
+        for (&(l, _), transmittance) in rays.iter().zip(transmittances(&rays, Some(emitter))) {
+            i += emission * transmittance * weight * falloff * material.shade(hit, l, v, &scene.settings);
+        }
+    }
+
+    i + ambient_light * material.ambient(hit)
+}
+
+// Adds the spheres all the demo scenes share
+fn add_demo_spheres(scene: &mut Scene) {
+    let red = scene.add_material(presets::car_paint(Vec3::new(1.0, 0.0, 0.0)));
+    let blue = scene.add_material(Phong {
+        color: Vec3::new(0.0, 0.0, 1.0).into(),
+        specular: Some(500.0.into()),
+        specular_model: Some(SpecularModel::Ggx),
+        reflective: 0.3,
+        ..Phong::default()
+    });
+    let green = scene.add_material(Phong {
+        color: Vec3::new(0.0, 1.0, 0.0).into(),
+        specular: Some(40.0.into()),
+        specular_model: Some(SpecularModel::BlinnPhong),
+        reflective: 0.4,
+        ..Phong::default()
+    });
+    let ground = scene.add_material(Phong {
+        color: Vec3::new(1.0, 1.0, 0.0).into(),
+        sigma: 30.0,
+        specular: Some(1000.0.into()),
+        reflective: 0.5,
+        ..Phong::default()
+    });
+    let orb = scene.add_material(Phong {
+        color: Vec3::new(1.0, 0.78, 0.47).into(),
+        emission: Vec3::new(1.0, 0.6, 0.2) * 2.0,
+        ..Phong::default()
+    });
+    let glass = scene.add_material(Phong {
+        specular: Some(500.0.into()),
+        transmission: Vec3::new(0.85, 0.95, 0.9),
+        ior: 1.5,
+        ..Phong::default()
+    });
+
+    let spheres = [
+        ("red", Vec3::new(0.0, -1.0, 3.0), 1.0, red),
+        ("blue", Vec3::new(2.0, 0.0, 4.0), 1.0, blue),
+        ("green", Vec3::new(-2.0, 0.0, 4.0), 1.0, green),
+        ("orb", Vec3::new(0.6, -0.75, 1.6), 0.25, orb),
+        ("glass", Vec3::new(-0.9, -0.6, 1.8), 0.4, glass)
+    ];
+    for (name, center, radius, material) in spheres {
+        scene.objects.push(Object {
+            name: String::from(name),
+            shape: Box::new(Sphere { center, radius }),
+            material
+        });
+    }
+    scene.objects.push(Object {
+        name: String::from("ground"),
+        shape: Box::new(Plane {
+            point: Vec3::new(0.0, -1.0, 0.0),
+            normal: Vec3::Y
+        }),
+        material: ground
+    });
+}
+
+// Path of a file in the assets folder of the repository, wherever the renderer is run from
+fn asset(path: &str) -> String {
+    format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), path)
+}
+
+fn demo_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Ambient,
+                color: Vec3::ONE,
+                intensity: 0.2,
+                position: None,
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Point,
+                color: Vec3::new(1.0, 0.9, 0.75),
+                intensity: 8.0,
+                position: Some(Vec3::new(2.0, 1.0, 0.0)),
+                // Aims the photometric profile at the spheres
+                direction: Some(Vec3::new(-0.5, -1.0, 1.0)),
+                attenuation: Attenuation::InverseSquare,
+                // Without the file the lamp shines the same every way
+                profile: IesProfile::load(&asset("ies/downlight.ies")).map_err(|error| eprintln!("{}", error)).ok(),
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Directional,
+                color: Vec3::new(0.75, 0.85, 1.0),
+                intensity: 0.2,
+                position: None,
+                direction: Some(Vec3::new(1.0, 4.0, 4.0)),
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Spot {
+                    inner_angle: 10.0,
+                    outer_angle: 20.0,
+                    falloff: 2.0
+                },
+                color: Vec3::new(1.0, 1.0, 0.8),
+                intensity: 1.6,
+                position: Some(Vec3::new(-2.0, 3.0, 2.0)),
+                direction: Some(Vec3::new(0.0, -1.0, 0.7)),
+                attenuation: Attenuation::Linear,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Rect {
+                    u: Vec3::new(1.0, 0.0, 0.0),
+                    v: Vec3::new(0.0, 0.0, 1.0)
+                },
+                color: Vec3::ONE,
+                intensity: 0.1,
+                position: Some(Vec3::new(1.0, 3.0, 3.0)),
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                // Fill light for the spheres, leaves the ground alone
+                link: LightLink::Except(vec![String::from("ground")]),
+                enabled: true
+            },
+            Light {
+                kind: LightType::Disc {
+                    radius: 0.5
+                },
+                color: Vec3::new(1.0, 0.6, 0.6),
+                intensity: 0.3,
+                position: Some(Vec3::new(-3.0, 2.0, 5.0)),
+                direction: Some(Vec3::new(1.0, -1.0, 0.0)),
+                attenuation: Attenuation::Custom {
+                    constant: 1.0,
+                    linear: 0.5,
+                    quadratic: 0.0
+                },
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            // Rim light that only catches the edge of the red sphere
+            Light {
+                kind: LightType::Directional,
+                color: Vec3::new(0.6, 0.8, 1.0),
+                intensity: 0.8,
+                position: None,
+                direction: Some(Vec3::new(-1.0, 0.5, 1.5)),
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::Only(vec![String::from("red")]),
+                enabled: true
+            }
+        ],
+        environment: Environment::Color(BACKGROUND_COLOR),
+        settings: RenderSettings {
+            ao_samples: 16,
+            ..RenderSettings::default()
+        }
+    };
+    add_demo_spheres(&mut scene);
+    scene
+}
+
+// The same spheres lit only by a late afternoon sun and the sky
+fn outdoor_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![],
+        environment: Environment::Sky(Sky::new(25.0, 60.0, 3.0)),
+        settings: RenderSettings {
+            ao_samples: 16,
+            ..RenderSettings::default()
+        }
+    };
+    add_demo_spheres(&mut scene);
+    scene
+}
+
+// The HDR map of a studio several scenes are lit by, or the plain background when it can't be
+// read
+fn studio_environment() -> Environment {
+    match EnvironmentMap::load(&asset("studio.hdr")) {
+        Ok(map) => Environment::Hdri(map),
+        Err(error) => {
+            eprintln!("{}", error);
+            Environment::Color(BACKGROUND_COLOR)
+        }
+    }
+}
+
+// The same spheres lit by an HDR environment map
+fn studio_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![],
+        environment: studio_environment(),
+        // The environment is sampled at every point and leaves some noise, worst along the
+        // edges of the shadows, where the extra rays go
+        settings: RenderSettings {
+            min_samples: 2,
+            max_samples: 16,
+            ..RenderSettings::default()
+        }
+    };
+    add_demo_spheres(&mut scene);
+    scene
+}
+
+// The spheres under a ring of dozens of colored lights, only a few of them sampled per point
+fn many_lights_scene() -> Scene {
+    let mut lighting = vec![
+        Light {
+            kind: LightType::Ambient,
+            color: Vec3::ONE,
+            intensity: 0.1,
+            position: None,
+            direction: None,
+            attenuation: Attenuation::None,
+            profile: None,
+            link: LightLink::All,
+            enabled: true
+        }
+    ];
+    let count = 48;
+    for k in 0..count {
+        let angle = k as f32 / count as f32 * 2.0 * std::f32::consts::PI;
+        // Cycle through red, green and blue, with a few brighter ones
+        let mut color = Vec3::ZERO;
+        color[k % 3] = 1.0;
+        lighting.push(Light {
+            kind: LightType::Point,
+            color,
+            intensity: if k % 8 == 0 { 2.0 } else { 0.5 },
+            position: Some(Vec3::new(4.0 * angle.cos(), 1.5, 4.0 + 4.0 * angle.sin())),
+            direction: None,
+            attenuation: Attenuation::InverseSquare,
+            profile: None,
+            link: LightLink::All,
+            enabled: true
+        });
+    }
+
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting,
+        environment: Environment::Color(Color::BLACK),
+        settings: RenderSettings {
+            light_sampling: Some(LightSampling {
+                count: 8,
+                power_weighted: true
+            }),
+            ..RenderSettings::default()
+        }
+    };
+    add_demo_spheres(&mut scene);
+    scene
+}
+
+// A room with an open ceiling, lit by the HDR environment coming in through it
+fn room_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Portal {
+                    u: Vec3::new(8.0, 0.0, 0.0),
+                    v: Vec3::new(0.0, 0.0, 11.0)
+                },
+                color: Vec3::ONE,
+                intensity: 1.0,
+                position: Some(Vec3::new(0.0, 3.0, 2.5)),
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            }
+        ],
+        environment: studio_environment(),
+        settings: RenderSettings {
+            area_light_samples: 64,
+            ..RenderSettings::default()
+        }
+    };
+    add_demo_spheres(&mut scene);
+
+    // The walls all face into the room and are painted the same
+    let paint = scene.add_material(Phong {
+        color: Vec3::splat(0.9).into(),
+        ..Phong::default()
+    });
+    let walls = [
+        ("back wall", Vec3::new(0.0, 0.0, 8.0), Vec3::NEG_Z),
+        ("front wall", Vec3::new(0.0, 0.0, -3.0), Vec3::Z),
+        ("left wall", Vec3::new(-4.0, 0.0, 0.0), Vec3::X),
+        ("right wall", Vec3::new(4.0, 0.0, 0.0), Vec3::NEG_X)
+    ];
+    for (name, point, normal) in walls {
+        scene.objects.push(Object {
+            name: String::from(name),
+            shape: Box::new(Plane { point, normal }),
+            material: paint
+        });
+    }
+    scene
+}
+
+// The spheres at night, lit by a bare bulb and moonlight given in physical units
+fn physical_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Ambient,
+                color: Vec3::ONE,
+                intensity: 0.3,
+                position: None,
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            // Around a 200W incandescent bulb
+            Light {
+                kind: LightType::Point,
+                color: Vec3::new(1.0, 0.85, 0.6),
+                intensity: 3000.0,
+                position: Some(Vec3::new(2.0, 1.0, 0.0)),
+                direction: None,
+                attenuation: Attenuation::InverseSquare,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            // Bright full moon
+            Light {
+                kind: LightType::Directional,
+                color: Vec3::new(0.7, 0.8, 1.0),
+                intensity: 0.3,
+                position: None,
+                direction: Some(Vec3::new(1.0, 4.0, 4.0)),
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            }
+        ],
+        environment: Environment::Color(Color::BLACK),
+        settings: RenderSettings {
+            light_units: LightUnits::Physical { ev100: 3.0 },
+            ..RenderSettings::default()
+        }
+    };
+    add_demo_spheres(&mut scene);
+
+    let orb = scene.objects.iter().find(|object| object.name == "orb").unwrap().material;
+    scene.materials[orb] = Box::new(Phong {
+        color: Vec3::new(1.0, 0.78, 0.47).into(),
+        emission: Vec3::new(1.0, 0.6, 0.2) * 200.0,
+        ..Phong::default()
+    });
+    scene
+}
+
+// The demo spheres drawn like a cartoon, flat bands of color with black outlines
+fn toon_scene() -> Scene {
+    let mut scene = demo_scene();
+    let inked = [("red", Vec3::new(1.0, 0.2, 0.2)), ("blue", Vec3::new(0.2, 0.4, 1.0)), ("green", Vec3::new(0.3, 0.9, 0.3))];
+    for (name, color) in inked {
+        let id = scene.objects.iter().find(|object| object.name == name).unwrap().material;
+        scene.materials[id] = Box::new(Toon {
+            color: color.into(),
+            bands: 3,
+            ink: Some(Vec3::ZERO)
+        });
+    }
+    scene
+}
+
+// The demo spheres in front of a pyramid made of triangles, its sides painted in two colors
+fn mesh_scene() -> Scene {
+    let mut scene = demo_scene();
+    let light = scene.add_material(Phong {
+        color: Vec3::new(0.9, 0.85, 0.7).into(),
+        specular: Some(10.0.into()),
+        ..Phong::default()
+    });
+    let dark = scene.add_material(Phong {
+        color: Vec3::new(0.5, 0.3, 0.2).into(),
+        specular: Some(10.0.into()),
+        ..Phong::default()
+    });
+    let vertices = vec![
+        Vec3::new(-1.5, -1.0, 5.5),
+        Vec3::new(1.5, -1.0, 5.5),
+        Vec3::new(1.5, -1.0, 8.5),
+        Vec3::new(-1.5, -1.0, 8.5),
+        Vec3::new(0.0, 1.2, 7.0)
+    ];
+    // The four sides, the base sits on the ground and is never seen
+    let triangles = vec![[1, 0, 4], [2, 1, 4], [3, 2, 4], [0, 3, 4]];
+    scene.objects.push(Object {
+        name: String::from("pyramid"),
+        shape: Box::new(Mesh::new(vertices, triangles, vec![light, dark, light, dark])),
+        material: light
+    });
+    scene
+}
+
+// The demo spheres over rolling hills instead of the flat ground, shaped by noise or by the
+// brightness of a .ppm image, white the highest
+fn terrain_scene(path: Option<&str>) -> Result<Scene, String> {
+    let mut scene = demo_scene();
+    let height: Box<dyn Fn(Vec2) -> f32> = match path {
+        Some(path) => {
+            let image = Image::load(path)?;
+            Box::new(move |uv| image.sample(uv).dot(Vec3::new(0.2126, 0.7152, 0.0722)))
+        },
+        None => Box::new(|uv| noise(Vec3::new(uv.x * 8.0, 0.0, uv.y * 12.0)))
+    };
+    let terrain = Heightfield::from_fn(Vec3::new(-10.0, -1.8, 0.0), Vec3::new(20.0, 1.2, 30.0), 200, 300, height);
+    if let Some(ground) = scene.objects.iter_mut().find(|object| object.name == "ground") {
+        ground.shape = Box::new(terrain);
+    }
+    Ok(scene)
+}
+
+// The demo spheres on rocky ground, a flat rectangle of two triangles cut finely and pushed up
+// and down by noise
+fn rocks_scene() -> Scene {
+    let mut scene = demo_scene();
+    let vertices = vec![Vec3::new(-5.0, -1.2, 1.0), Vec3::new(5.0, -1.2, 1.0), Vec3::new(5.0, -1.2, 11.0), Vec3::new(-5.0, -1.2, 11.0)];
+    let height = Texture::Procedural(Box::new(|hit| noise(hit.point * 1.5)));
+    let rocks = Mesh::new(vertices, vec![[0, 2, 1], [0, 3, 2]], vec![]).tessellated(0.3).displaced(&height, 0.5);
+    if let Some(ground) = scene.objects.iter_mut().find(|object| object.name == "ground") {
+        ground.shape = Box::new(rocks);
+    }
+    scene
+}
+
+// The analytic shapes other than spheres on the ground of the demo scene
+fn shapes_scene() -> Scene {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let red = scene.add_material(presets::plastic(Vec3::new(0.8, 0.1, 0.1)));
+    let blue = scene.add_material(presets::plastic(Vec3::new(0.1, 0.2, 0.8)));
+    let copper = scene.add_material(presets::copper());
+    let chrome = scene.add_material(presets::chrome());
+
+    let shapes: Vec<(&str, Box<dyn Shape>, MaterialId)> = vec![
+        ("cylinder", Box::new(Cylinder {
+            base: Vec3::new(-1.8, -1.0, 4.5),
+            axis: Vec3::Y,
+            radius: 0.6,
+            height: 1.5,
+            capped: true
+        }), red),
+        ("cone", Box::new(Cone {
+            base: Vec3::new(0.0, -1.0, 5.0),
+            axis: Vec3::Y,
+            radius: 0.8,
+            height: 2.0,
+            capped: true
+        }), blue),
+        // Lying on its side, so you can see into it
+        ("pipe", Box::new(Cylinder {
+            base: Vec3::new(1.2, -0.6, 3.5),
+            axis: Vec3::new(1.0, 0.0, 1.5),
+            radius: 0.4,
+            height: 1.8,
+            capped: false
+        }), copper),
+        ("torus", Box::new(Torus {
+            center: Vec3::new(-0.3, -0.45, 2.8),
+            axis: Vec3::new(0.0, 1.0, -0.4),
+            major_radius: 0.6,
+            minor_radius: 0.25
+        }), chrome),
+        ("egg", Box::new(Quadric::ellipsoid(Vec3::new(0.9, -0.65, 2.4), Vec3::new(0.25, 0.35, 0.25))), red),
+        // Narrow in the middle, cut off by the ground and at the top
+        ("vase", Box::new(Quadric::hyperboloid()
+            .transformed(Mat4::from_scale_rotation_translation(Vec3::new(0.3, 0.5, 0.3), Quat::IDENTITY, Vec3::new(-1.0, -0.3, 6.5)))
+            .clipped(Vec3::new(-1.0, -0.3, 6.5), 0.7)), copper),
+        ("dish", Box::new(Quadric::paraboloid()
+            .transformed(Mat4::from_scale_rotation_translation(Vec3::splat(0.5), Quat::IDENTITY, Vec3::new(1.9, -1.0, 6.0)))
+            .clipped(Vec3::new(1.9, -0.6, 6.0), 0.5)), blue),
+        // A little round table, and a washer lying on the ground in front of it
+        ("table leg", Box::new(Cylinder {
+            base: Vec3::new(2.3, -1.0, 4.5),
+            axis: Vec3::Y,
+            radius: 0.05,
+            height: 0.8,
+            capped: false
+        }), chrome),
+        ("table top", Box::new(Disc {
+            center: Vec3::new(2.3, -0.2, 4.5),
+            normal: Vec3::Y,
+            inner_radius: 0.0,
+            outer_radius: 0.5
+        }), red),
+        ("washer", Box::new(Disc {
+            center: Vec3::new(1.9, -0.99, 3.2),
+            normal: Vec3::Y,
+            inner_radius: 0.15,
+            outer_radius: 0.3
+        }), chrome)
+    ];
+    for (name, shape, material) in shapes {
+        scene.objects.push(Object {
+            name: String::from(name),
+            shape,
+            material
+        });
+    }
+    scene
+}
+
+// Solids put together out of others on the ground of the demo scene: the part of a cube
+// inside a sphere with holes drilled through it along the three axes, and a sphere with a
+// corner cut out of it
+fn csg_scene() -> Scene {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let red = scene.add_material(presets::plastic(Vec3::new(0.8, 0.1, 0.1)));
+    let gold = scene.add_material(presets::gold());
+
+    let center = Vec3::new(-1.1, -0.4, 4.5);
+    let rounded = Csg {
+        operation: Operation::Intersection,
+        left: Box::new(Cuboid { min: center - 0.6, max: center + 0.6 }),
+        right: Box::new(Sphere { center, radius: 0.8 })
+    };
+    let drill = |axis: Vec3| -> Box<dyn Shape> {
+        Box::new(Cylinder { base: center - axis, axis, radius: 0.3, height: 2.0, capped: true })
+    };
+    let holes = Csg {
+        operation: Operation::Union,
+        left: drill(Vec3::X),
+        right: Box::new(Csg { operation: Operation::Union, left: drill(Vec3::Y), right: drill(Vec3::Z) })
+    };
+    scene.objects.push(Object {
+        name: String::from("drilled"),
+        shape: Box::new(Csg { operation: Operation::Difference, left: Box::new(rounded), right: Box::new(holes) }),
+        material: red
+    });
+
+    let center = Vec3::new(1.1, -0.3, 4.5);
+    scene.objects.push(Object {
+        name: String::from("cut"),
+        shape: Box::new(Csg {
+            operation: Operation::Difference,
+            left: Box::new(Sphere { center, radius: 0.7 }),
+            right: Box::new(Cuboid { min: center - Vec3::new(0.0, 0.0, 1.0), max: center + Vec3::new(1.0, 1.0, 0.0) })
+        }),
+        material: gold
+    });
+    scene
+}
+
+// Shapes found by ray marching their distance fields on the ground of the demo scene: balls
+// and a rod melted into a blob, and a rounded box melted into a ring around it
+fn sdf_scene() -> Scene {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let jade = scene.add_material(presets::jade());
+    let silver = scene.add_material(presets::silver());
+
+    let blob = sdf::SmoothUnion {
+        left: Box::new(sdf::SmoothUnion {
+            left: Box::new(sdf::Ball { center: Vec3::new(-1.3, -0.5, 4.5), radius: 0.5 }),
+            right: Box::new(sdf::Ball { center: Vec3::new(-0.7, -0.1, 4.8), radius: 0.35 }),
+            smoothness: 0.3
+        }),
+        right: Box::new(sdf::Capsule { a: Vec3::new(-1.6, -0.85, 4.0), b: Vec3::new(-0.4, -0.75, 4.0), radius: 0.12 }),
+        smoothness: 0.25
+    };
+    scene.objects.push(Object {
+        name: String::from("blob"),
+        shape: Box::new(sdf::Marched { sdf: Box::new(blob) }),
+        material: jade
+    });
+
+    let melted = sdf::SmoothUnion {
+        left: Box::new(sdf::RoundedBox { center: Vec3::new(1.1, -0.6, 4.5), half_size: Vec3::splat(0.25), radius: 0.1 }),
+        right: Box::new(sdf::Ring { center: Vec3::new(1.1, -0.45, 4.5), major_radius: 0.6, minor_radius: 0.1 }),
+        smoothness: 0.2
+    };
+    scene.objects.push(Object {
+        name: String::from("melted"),
+        shape: Box::new(sdf::Marched { sdf: Box::new(melted) }),
+        material: silver
+    });
+    scene
+}
+
+// Blobs of liquid on the ground of the demo scene: a line of drops running into each other
+// more the closer they are, and a puddle with a hollow pushed into it
+fn metaballs_scene() -> Scene {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let water = scene.add_material(presets::water());
+    let chrome = scene.add_material(presets::chrome());
+
+    let drops = (0..5)
+        .map(|k| sdf::Charge {
+            center: Vec3::new(-2.0 + k as f32 * (0.3 + 0.1 * k as f32), -0.6, 5.0),
+            radius: 0.7,
+            weight: 1.0
+        })
+        .collect();
+    scene.objects.push(Object {
+        name: String::from("drops"),
+        shape: Box::new(sdf::Marched { sdf: Box::new(sdf::Metaballs { charges: drops, threshold: 0.5 }) }),
+        material: water
+    });
+
+    let puddle = vec![
+        sdf::Charge { center: Vec3::new(1.2, -1.0, 4.0), radius: 1.0, weight: 1.0 },
+        sdf::Charge { center: Vec3::new(1.6, -0.9, 4.4), radius: 0.8, weight: 1.0 },
+        sdf::Charge { center: Vec3::new(1.4, -0.4, 4.1), radius: 0.5, weight: -1.5 }
+    ];
+    scene.objects.push(Object {
+        name: String::from("puddle"),
+        shape: Box::new(sdf::Marched { sdf: Box::new(sdf::Metaballs { charges: puddle, threshold: 0.4 }) }),
+        material: chrome
+    });
+    scene
+}
+
+// A Mandelbulb and a Menger sponge side by side on the ground of the demo scene. The power of
+// the bulb and how many times the sponge is punched through can be given on the command line
+fn fractals_scene(power: Option<&str>, iterations: Option<&str>) -> Result<Scene, String> {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let gold = scene.add_material(presets::gold());
+    let concrete = scene.add_material(presets::concrete());
+    let power = power.map_or(Ok(8.0), |power| parse(power, "a power"))?;
+    let iterations = iterations.map_or(Ok(4), |iterations| parse(iterations, "a whole number of iterations"))?;
+
+    scene.objects.push(Object {
+        name: String::from("mandelbulb"),
+        shape: Box::new(sdf::Marched {
+            sdf: Box::new(sdf::Mandelbulb { center: Vec3::new(-1.1, -0.2, 4.5), scale: 0.7, power, iterations: 12 })
+        }),
+        material: gold
+    });
+    scene.objects.push(Object {
+        name: String::from("sponge"),
+        shape: Box::new(sdf::Marched {
+            sdf: Box::new(sdf::MengerSponge { center: Vec3::new(1.1, -0.3, 4.5), half_size: 0.7, iterations })
+        }),
+        material: concrete
+    });
+    Ok(scene)
+}
+
+// The same unit sphere and unit cube placed around the ground of the demo scene, each one
+// stretched and turned its own way
+fn transforms_scene() -> Scene {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let red = scene.add_material(presets::plastic(Vec3::new(0.8, 0.1, 0.1)));
+    let blue = scene.add_material(presets::plastic(Vec3::new(0.1, 0.2, 0.8)));
+    let gold = scene.add_material(presets::gold());
+
+    let placements = [
+        ("squashed", Vec3::new(0.7, 0.3, 0.7), Quat::IDENTITY, Vec3::new(-1.6, -0.7, 4.5), red),
+        ("stretched", Vec3::new(0.25, 0.8, 0.25), Quat::from_rotation_z(0.5), Vec3::new(0.0, -0.25, 5.0), blue),
+        ("lying", Vec3::new(0.9, 0.35, 0.35), Quat::from_rotation_y(0.8), Vec3::new(1.5, -0.65, 4.0), gold)
+    ];
+    for (name, scale, rotation, translation, material) in placements {
+        let sphere = Object {
+            name: String::from(name),
+            shape: Box::new(Sphere { center: Vec3::ZERO, radius: 1.0 }),
+            material
+        };
+        scene.objects.push(sphere.transformed(Mat4::from_scale_rotation_translation(scale, rotation, translation)));
+    }
+
+    // Balanced on one corner
+    let cube = Object {
+        name: String::from("cube"),
+        shape: Box::new(Cuboid { min: Vec3::splat(-0.5), max: Vec3::splat(0.5) }),
+        material: gold
+    };
+    let corner_up = Quat::from_rotation_arc(Vec3::ONE.normalize(), Vec3::Y);
+    scene.objects.push(cube.transformed(Mat4::from_scale_rotation_translation(Vec3::splat(0.7), corner_up, Vec3::new(-0.6, -1.0 + 0.7 * 0.866, 2.8))));
+    scene
+}
+
+// A forest on the ground of the demo scene, one tree mesh placed hundreds of times over, each
+// one turned, sized and colored its own way. What the trees are sorted into can be given on
+// the command line, to compare them: bvh, kdtree or grid, the default
+fn instances_scene(accelerator: Option<&str>) -> Result<Scene, String> {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let greens: Vec<MaterialId> = [Vec3::new(0.1, 0.4, 0.1), Vec3::new(0.2, 0.5, 0.1), Vec3::new(0.05, 0.3, 0.15)]
+        .into_iter()
+        .map(|color| scene.add_material(Phong { color: color.into(), ..Phong::default() }))
+        .collect();
+
+    // A cone of eight sides, one unit tall, standing on the origin
+    let sides = 8;
+    let mut vertices: Vec<Vec3> = (0..sides)
+        .map(|k| {
+            let angle = k as f32 * 2.0 * std::f32::consts::PI / sides as f32;
+            Vec3::new(angle.cos(), 0.0, angle.sin()) * 0.35
+        })
+        .collect();
+    vertices.push(Vec3::Y);
+    let triangles = (0..sides).map(|k| [(k + 1) % sides, k, sides]).collect();
+    let tree: Arc<dyn Shape> = Arc::new(Mesh::new(vertices, triangles, vec![]));
+
+    // The trees are spread evenly, which is what a grid is best at. A BVH over them splits
+    // about as well in half as anywhere
+    scene.settings.accelerator = match accelerator {
+        None | Some("grid") => Accelerator::Grid,
+        Some("bvh") => Accelerator::Bvh,
+        Some("kdtree") => Accelerator::KdTree,
+        Some(other) => return Err(format!("unknown accelerator {}, expected bvh, kdtree or grid", other))
+    };
+    scene.settings.bvh = BvhOptions { split: Split::Median, leaf_size: 2 };
+    // Neighbouring primary rays go past the same trees, so they're traced in packets
+    scene.settings.packet_size = 8;
+
+    let mut rng = Rng::new(7);
+    for row in 0..15 {
+        for column in 0..15 {
+            let position = Vec3::new(-7.0 + column as f32 + rng.next_f32() * 0.6, -1.0, 4.0 + row as f32 + rng.next_f32() * 0.6);
+            let size = 0.6 + rng.next_f32() * 0.8;
+            let turn = Quat::from_rotation_y(rng.next_f32() * std::f32::consts::TAU);
+            let material = greens[rng.next_u32() as usize % greens.len()];
+            let placement = Mat4::from_scale_rotation_translation(Vec3::splat(size), turn, position);
+            scene.objects.push(Object {
+                name: format!("tree {}", row * 15 + column),
+                shape: Box::new(Transformed::new(tree.clone(), placement).with_material(material)),
+                material
+            });
+        }
+    }
+    Ok(scene)
+}
+
+// A ball pit on the ground of the demo scene, a heap of thousands of little plastic balls of a
+// few colors
+fn ball_pit_scene() -> Scene {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let colors: Vec<MaterialId> = [Vec3::new(0.8, 0.1, 0.1), Vec3::new(0.1, 0.3, 0.8), Vec3::new(0.9, 0.7, 0.1), Vec3::new(0.1, 0.6, 0.2)]
+        .into_iter()
+        .map(|color| scene.add_material(presets::plastic(color)))
+        .collect();
+
+    // Scattered over a disc, piled up higher in the middle
+    let mut rng = Rng::new(5);
+    let (mut centers, mut radii, mut materials) = (vec![], vec![], vec![]);
+    for _ in 0..20000 {
+        let angle = rng.next_f32() * std::f32::consts::TAU;
+        let distance = rng.next_f32().sqrt() * 2.5;
+        let height = (1.0 - distance / 2.5) * 1.2 * rng.next_f32();
+        let radius = 0.05 + rng.next_f32() * 0.03;
+        centers.push(Vec3::new(angle.cos() * distance, -1.0 + radius + height, 5.0 + angle.sin() * distance));
+        radii.push(radius);
+        materials.push(colors[rng.next_u32() as usize % colors.len()]);
+    }
+    scene.objects.push(Object {
+        name: String::from("balls"),
+        shape: Box::new(Spheres::new(centers, radii).with_materials(materials)),
+        material: colors[0]
+    });
+    scene
+}
+
+// A tuft of grass on the ground of the demo scene, blades curving away from the middle and
+// thinning to their tips, with a wire sagging between two poles behind it
+fn grass_scene() -> Scene {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let greens: Vec<MaterialId> = [Vec3::new(0.2, 0.5, 0.1), Vec3::new(0.3, 0.6, 0.15), Vec3::new(0.4, 0.55, 0.1)]
+        .into_iter()
+        .map(|color| scene.add_material(Phong { color: color.into(), specular: Some(20.0.into()), ..Phong::default() }))
+        .collect();
+    let copper = scene.add_material(presets::copper());
+
+    // The blades lean into each other and their boxes overlap a lot, which a kd-tree sorts out
+    // better than a BVH
+    scene.settings.accelerator = Accelerator::KdTree;
+
+    let mut rng = Rng::new(11);
+    for k in 0..150 {
+        let root = Vec3::new(-1.5 + rng.next_f32() * 3.0, -1.0, 3.5 + rng.next_f32() * 2.0);
+        let lean = Quat::from_rotation_y(rng.next_f32() * std::f32::consts::TAU) * Vec3::X * (0.2 + rng.next_f32() * 0.4);
+        let height = 0.5 + rng.next_f32() * 0.6;
+        let controls = [root, root + Vec3::Y * height * 0.5, root + Vec3::Y * height + lean * 0.5, root + Vec3::Y * height * 0.9 + lean];
+        scene.objects.push(Object {
+            name: format!("blade {}", k),
+            shape: Box::new(Curve::new(controls, &[0.03, 0.02, 0.0], 8)),
+            material: greens[k % greens.len()]
+        });
+    }
+
+    let (left, right) = (Vec3::new(-2.5, 1.0, 7.0), Vec3::new(2.5, 1.0, 7.0));
+    let wire = Curve::new([left, left + Vec3::new(1.5, -1.2, 0.0), right + Vec3::new(-1.5, -1.2, 0.0), right], &[0.02], 24);
+    scene.objects.push(Object { name: String::from("wire"), shape: Box::new(wire), material: copper });
+    for (name, top) in [("left pole", left), ("right pole", right)] {
+        scene.objects.push(Object {
+            name: String::from(name),
+            shape: Box::new(Cylinder { base: Vec3::new(top.x, -1.0, top.z), axis: Vec3::Y, radius: 0.05, height: top.y + 1.0, capped: true }),
+            material: copper
+        });
+    }
+    scene
+}
+
+// A model loaded from an .obj, .stl, .ply or .bpt file standing on the ground of the demo scene, in
+// place of the spheres, scaled to be about as big as two of them. Coarse models can be
+// subdivided a few times to round them off
+fn model_scene(path: &str, shading: Option<&str>, subdivisions: u32) -> Result<Scene, String> {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let extension = std::path::Path::new(path).extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    let (meshes, material) = match extension.as_deref() {
+        Some("obj") => {
+            let material = scene.add_material(Phong {
+                color: Vec3::splat(0.8).into(),
+                specular: Some(50.0.into()),
+                ..Phong::default()
+            });
+            (obj::load(path, &mut scene.materials)?, material)
+        },
+        // 3D printing models have Z going up, and are printed in plastic
+        Some("stl") => {
+            let mesh = stl::load(path)?.transformed(Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2));
+            let name = std::path::Path::new(path).file_stem().unwrap().to_string_lossy().to_string();
+            (vec![(name, mesh)], scene.add_material(presets::plastic(Vec3::new(0.9, 0.45, 0.1))))
+        },
+        // Scans come with their colors painted on the vertices
+        Some("ply") => {
+            let mesh = ply::load(path)?;
+            let name = std::path::Path::new(path).file_stem().unwrap().to_string_lossy().to_string();
+            let material = scene.add_material(Phong {
+                color: Texture::Procedural(Box::new(|hit| hit.color.unwrap_or(Vec3::splat(0.8)))),
+                ..Phong::default()
+            });
+            (vec![(name, mesh)], material)
+        },
+        // Bézier patches like the teapot, with Z going up too, glazed in white
+        Some("bpt") => {
+            let patches = bezier::load(path)?;
+            let points: Vec<Vec3> = patches.iter().flat_map(|patch| patch.points.into_iter().flatten()).collect();
+            let size = points.iter().copied().reduce(Vec3::max).unwrap_or(Vec3::ZERO) - points.iter().copied().reduce(Vec3::min).unwrap_or(Vec3::ZERO);
+            // Close enough to the curves not to see the triangles at the size it's shown
+            let mesh = bezier::tessellate(&patches, size.max_element() / 1000.0).transformed(Mat4::from_rotation_x(-std::f32::consts::FRAC_PI_2));
+            let name = std::path::Path::new(path).file_stem().unwrap().to_string_lossy().to_string();
+            let material = scene.add_material(Phong {
+                color: Vec3::splat(0.9).into(),
+                specular: Some(200.0.into()),
+                reflective: 0.1,
+                ..Phong::default()
+            });
+            (vec![(name, mesh)], material)
+        },
+        _ => return Err(format!("{}: unsupported model format, expected .obj, .stl, .ply or .bpt", path))
+    };
+    let meshes: Vec<(String, Mesh)> = meshes.into_iter().map(|(name, mesh)| (name, mesh.subdivided(subdivisions))).collect();
+
+    // Shaded flat, or smoothed over edges up to the given angle in degrees, instead of with
+    // the normals the file comes with if any
+    let meshes: Vec<(String, Mesh)> = match shading {
+        Some("flat") => meshes.into_iter().map(|(name, mesh)| (name, mesh.flat())).collect(),
+        Some(angle) => {
+            let angle: f32 = parse(angle, "flat or an angle in degrees")?;
+            meshes.into_iter().map(|(name, mesh)| (name, mesh.smoothed(angle.to_radians()))).collect()
+        },
+        None => meshes
+    };
+
+    let meshes: Vec<(String, Mesh)> = meshes.into_iter().map(|(name, mesh)| (name, mesh.with_bvh(scene.settings.bvh))).collect();
+    let placement = fit_on_ground(&meshes.iter().map(|(_, mesh)| mesh).collect::<Vec<_>>());
+    for (name, mesh) in meshes {
+        scene.objects.push(Object {
+            name,
+            shape: Box::new(mesh.transformed(placement)),
+            material
+        });
+    }
+    Ok(scene)
+}
+
+// A point cloud standing on the ground of the demo scene, each point drawn as a small ball in
+// its own color. It's read from the vertices of a .ply file, or made up as a scan of a lumpy
+// rock when there's no file
+fn points_scene(path: Option<&str>) -> Result<Scene, String> {
+    let mut scene = demo_scene();
+    scene.objects.retain(|object| object.name == "ground");
+    let cloud = match path {
+        Some(path) => ply::load_points(path, 0.01)?,
+        None => {
+            // Spread evenly over a sphere along a Fibonacci spiral, pushed in and out by noise
+            let count = 20000;
+            let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+            let (points, colors): (Vec<Vec3>, Vec<Vec3>) = (0..count)
+                .map(|k| {
+                    let y = 1.0 - 2.0 * (k as f32 + 0.5) / count as f32;
+                    let around = (1.0 - y * y).sqrt();
+                    let angle = k as f32 * golden_angle;
+                    let direction = Vec3::new(around * angle.cos(), y, around * angle.sin());
+                    let bump = noise(direction * 3.0);
+                    let point = direction * (0.8 + 0.4 * bump);
+                    (point, Vec3::new(0.4, 0.35, 0.3).lerp(Vec3::new(0.8, 0.75, 0.6), bump))
+                })
+                .unzip();
+            PointCloud::new(points, 0.015).with_colors(colors)
+        }
+    };
+    let placement = fit_on_ground(&[&cloud]);
+    let material = scene.add_material(Phong {
+        color: Texture::Procedural(Box::new(|hit| hit.color.unwrap_or(Vec3::splat(0.8)))),
+        ..Phong::default()
+    });
+    scene.objects.push(Object {
+        name: String::from("cloud"),
+        shape: Box::new(cloud.transformed(placement)),
+        material
+    });
+    Ok(scene)
+}
+
+// A glTF scene, seen through the camera of it called `view`, or numbered that from 1, or its
+// first one without a choice, and lit by a lamp over the shoulder of the camera. Without
+// cameras it's placed like the other models, and its meshes can be subdivided like them too.
+// Returns the camera too
+fn gltf_scene(path: &str, subdivisions: u32, view: Option<&str>) -> Result<(Scene, Option<gltf::Camera>), String> {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Ambient,
+                color: Vec3::ONE,
+                intensity: 0.2,
+                position: None,
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Directional,
+                color: Vec3::ONE,
+                intensity: 0.8,
+                position: None,
+                direction: Some(Vec3::new(-1.0, 2.0, -2.0)),
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            }
+        ],
+        environment: Environment::Color(BACKGROUND_COLOR),
+        settings: RenderSettings::default()
+    };
+    let mut model = gltf::load(path, &mut scene.materials)?;
+    let options = scene.settings.bvh;
+    model.meshes = model.meshes.into_iter().map(|(name, mesh, material)| (name, mesh.subdivided(subdivisions).with_bvh(options), material)).collect();
+
+    let names: Vec<&str> = model.cameras.iter().map(|camera| camera.name.as_str()).collect();
+    let chosen = match view {
+        Some(view) => {
+            let by_number = view.parse::<usize>().ok().filter(|&number| number >= 1).map(|number| number - 1);
+            let chosen = by_number.or_else(|| names.iter().position(|&name| name == view));
+            Some(chosen.filter(|&k| k < names.len()).ok_or_else(|| format!("no camera {} in {}, it has {:?}", view, path, names))?)
+        },
+        None => (!names.is_empty()).then_some(0)
+    };
+    if names.len() > 1 {
+        println!("Cameras: {}", names.iter().enumerate().map(|(k, name)| format!("{} {}", k + 1, name)).collect::<Vec<_>>().join(", "));
+    }
+    let camera = chosen.map(|k| model.cameras.swap_remove(k));
+    // The lamp turns with the camera, the meshes stay where they are around it
+    let placement = match &camera {
+        Some(camera) => {
+            match camera.aspect_ratio {
+                Some(ratio) => println!("Looking through {}, made for pictures {:.2} times as wide as they're tall", camera.name, ratio),
+                None => println!("Looking through {}", camera.name)
+            }
+            for light in &mut scene.lighting {
+                light.direction = light.direction.map(|direction| camera.transform.transform_vector3(direction));
+            }
+            Mat4::IDENTITY
+        },
+        None => {
+            let meshes: Vec<&Mesh> = model.meshes.iter().map(|(_, mesh, _)| mesh).collect();
+            fit_on_ground(&meshes)
+        }
+    };
+    for (name, mesh, material) in model.meshes {
+        scene.objects.push(Object {
+            name,
+            shape: Box::new(mesh.transformed(placement)),
+            material
+        });
+    }
+    Ok((scene, camera))
+}
+
+// Scales and moves imported meshes so they stand on the ground of the demo scene in front of
+// the camera, about as big as two of the spheres. They are moved together, so they stay where
+// they are from each other
+fn fit_on_ground<S: Shape>(meshes: &[&S]) -> Mat4 {
+    let bounds = meshes.iter().filter_map(|mesh| mesh.bounding_sphere());
+    let (min, max) = bounds.fold((Vec3::splat(INF), Vec3::splat(-INF)), |(min, max), (center, radius)| {
+        (min.min(center - radius), max.max(center + radius))
+    });
+    let scale = 2.0 / (max - min).max_element().max(1e-6);
+    let bottom_center = Vec3::new((min.x + max.x) / 2.0, min.y, (min.z + max.z) / 2.0);
+    let offset = Vec3::new(0.0, -1.0, 3.5) - bottom_center * scale;
+    Mat4::from_translation(offset) * Mat4::from_scale(Vec3::splat(scale))
+}
+
+// The spheres inside a big checkered dome, lit by a lamp hanging above them. The camera is
+// inside the dome, which only shades the side facing it because it's double sided
+fn dome_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Ambient,
+                color: Vec3::ONE,
+                intensity: 0.15,
+                position: None,
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Point,
+                color: Vec3::new(1.0, 0.95, 0.85),
+                intensity: 0.8,
+                position: Some(Vec3::new(0.0, 4.0, 3.0)),
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            }
+        ],
+        environment: Environment::Color(Color::BLACK),
+        settings: RenderSettings::default()
+    };
+    add_demo_spheres(&mut scene);
+
+    let dome = scene.add_material(Sided {
+        base: Box::new(Phong {
+            color: Texture::Checker {
+                even: Vec3::new(0.5, 0.6, 0.8),
+                odd: Vec3::new(0.8, 0.85, 0.9),
+                size: 2.0
+            },
+            ..Phong::default()
+        }),
+        double_sided: true,
+        flip_normals: false
+    });
+    scene.objects.push(Object {
+        name: String::from("dome"),
+        shape: Box::new(Sphere {
+            center: Vec3::new(0.0, 0.0, 3.0),
+            radius: 20.0
+        }),
+        material: dome
+    });
+    scene
+}
+
+// The spheres composited over the studio environment, sitting on a shadow catcher instead of
+// the yellow ground
+fn catcher_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Ambient,
+                color: Vec3::ONE,
+                intensity: 0.2,
+                position: None,
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Rect {
+                    u: Vec3::new(1.0, 0.0, 0.0),
+                    v: Vec3::new(0.0, 0.0, 1.0)
+                },
+                color: Vec3::ONE,
+                intensity: 8.0,
+                position: Some(Vec3::new(-1.0, 3.0, 2.0)),
+                direction: None,
+                attenuation: Attenuation::InverseSquare,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            }
+        ],
+        environment: studio_environment(),
+        settings: RenderSettings {
+            ao_samples: 16,
+            ..RenderSettings::default()
+        }
+    };
+    add_demo_spheres(&mut scene);
+
+    let ground = scene.objects.iter().find(|object| object.name == "ground").unwrap().material;
+    scene.materials[ground] = Box::new(ShadowCatcher { reflective: 0.1 });
+    scene
+}
+
+// A sphere of each preset material, two rows of them on a concrete floor
+fn presets_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Ambient,
+                color: Vec3::ONE,
+                intensity: 0.15,
+                position: None,
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Point,
+                color: Vec3::ONE,
+                intensity: 20.0,
+                position: Some(Vec3::new(-2.0, 3.0, 2.0)),
+                direction: None,
+                attenuation: Attenuation::InverseSquare,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            }
+        ],
+        environment: studio_environment(),
+        // The glass and the metals pass light on through many bounces, most of it too dim
+        // after the first few to be worth following every time
+        settings: RenderSettings {
+            max_depth: 8,
+            roulette_depth: Some(2),
+            ..RenderSettings::default()
+        }
+    };
+
+    let floor = scene.add_material(presets::concrete());
+    scene.objects.push(Object {
+        name: String::from("floor"),
+        shape: Box::new(Plane {
+            point: Vec3::new(0.0, -1.0, 0.0),
+            normal: Vec3::Y
+        }),
+        material: floor
+    });
+    let per_row = 9;
+    for (k, name) in presets::NAMES.iter().enumerate() {
+        let material = scene.materials.len();
+        scene.materials.push(presets::by_name(name).unwrap());
+        let (row, column) = (k / per_row, k % per_row);
+        scene.objects.push(Object {
+            name: name.to_string(),
+            shape: Box::new(Sphere {
+                center: Vec3::new(column as f32 * 0.7 - 2.8, 0.6 - row as f32 * 1.0, 6.0),
+                radius: 0.3
+            }),
+            material
+        });
+    }
+    scene
+}
+
+// Rows of physically based spheres going from smooth to rough, metals on top
+fn pbr_scene() -> Scene {
+    let mut scene = Scene {
+        objects: vec![],
+        acceleration: Acceleration::default(),
+        materials: vec![],
+        lighting: vec![
+            Light {
+                kind: LightType::Ambient,
+                color: Vec3::ONE,
+                intensity: 0.15,
+                position: None,
+                direction: None,
+                attenuation: Attenuation::None,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            },
+            Light {
+                kind: LightType::Point,
+                color: Vec3::ONE,
+                intensity: 12.0,
+                position: Some(Vec3::new(-2.0, 3.0, 1.0)),
+                direction: None,
+                attenuation: Attenuation::InverseSquare,
+                profile: None,
+                link: LightLink::All,
+                enabled: true
+            }
+        ],
+        environment: Environment::Color(BACKGROUND_COLOR),
+        settings: RenderSettings::default()
+    };
+
+    // Tiled floor, polished on the left and getting rougher to the right
+    let ground = scene.add_material(Pbr {
+        base_color: Texture::Checker {
+            even: Vec3::splat(0.4),
+            odd: Vec3::splat(0.6),
+            size: 1.0
+        },
+        roughness: Texture::Procedural(Box::new(|hit| (0.5 + hit.point.x / 8.0).clamp(0.05, 0.95))),
+        ..Pbr::default()
+    });
+    scene.objects.push(Object {
+        name: String::from("ground"),
+        shape: Box::new(Plane {
+            point: Vec3::new(0.0, -1.0, 0.0),
+            normal: Vec3::Y
+        }),
+        material: ground
+    });
+
+    // Brushed steel on the top row gets rougher across the brushing direction
+    let rows = [
+        ("steel", Vec3::new(0.56, 0.57, 0.58), 1.0, 1.5, true),
+        ("gold", Vec3::new(1.0, 0.77, 0.34), 1.0, 0.5, false),
+        ("plastic", Vec3::new(0.8, 0.1, 0.1), 0.0, -0.5, false)
+    ];
+    for (name, base_color, metallic, y, brushed) in rows {
+        for k in 0..5 {
+            let roughness = k as f32 / 4.0;
+            let material = scene.add_material(Pbr {
+                base_color: base_color.into(),
+                metallic: metallic.into(),
+                roughness: (if brushed { 0.1 } else { roughness }).into(),
+                bitangent_roughness: brushed.then(|| (0.1 + 0.6 * roughness).into()),
+                ..Pbr::default()
+            });
+            scene.objects.push(Object {
+                name: format!("{} {}", name, k + 1),
+                shape: Box::new(Sphere {
+                    center: Vec3::new(k as f32 - 2.0, y, 5.0),
+                    radius: 0.4
+                }),
+                material
+            });
+        }
+    }
+    scene
+}
+
+// The path of an output file with `-suffix` after its name, like render-left.ppm
+fn suffixed(output: &str, suffix: &str) -> PathBuf {
+    let output = Path::new(output);
+    let stem = output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("render");
+    let extension = output.extension().and_then(|extension| extension.to_str()).unwrap_or("ppm");
+    output.with_file_name(format!("{}-{}.{}", stem, suffix, extension))
+}
+
+// A value from the command line, `what` saying what it should have been when it isn't
+fn parse<T: std::str::FromStr>(text: &str, what: &str) -> Result<T, String> {
+    text.trim().parse().map_err(|_| format!("{} isn't {}", text, what))
+}
+
+// A point or vector from the command line, as x,y,z, or x,y for `N` of 2
+fn parse_vector<const N: usize>(text: &str) -> Result<[f32; N], String> {
+    let values = text.split(',').map(|value| parse(value, "a number")).collect::<Result<Vec<f32>, _>>()?;
+    values.try_into().map_err(|_| format!("{} isn't {} numbers", text, N))
+}
+
+const USAGE: &str = "\
+Usage: raytracer [scene] [scene arguments] [options]
+
+Scenes: demo (the default), outdoor, studio, lights, room, physical, pbr, catcher, presets,
+toon, dome, mesh, terrain [heightmap], rocks, shapes, csg, sdf, metaballs,
+fractals [power] [iterations], transforms, instances [bvh|kdtree|grid], grass, balls,
+points [file.ply], model <file> [flat|angle] [subdivisions], gltf <file> [subdivisions]
+
+Options:
+  --output <file>            save the first full frame as a .ppm image
+  --profile                  print where the time of each full render went
+  --listen <address>         have workers help render
+  --worker <address>         render tiles for the coordinator at the address
+  --camera <x,y,z>           where the camera is
+  --look-at <x,y,z>          where it looks
+  --fov <degrees>            how much it sees up and down
+  --ortho <width>            an orthographic camera, seeing that many units across
+  --fisheye <degrees>        an equidistant fisheye lens, up to 180 degrees
+  --equisolid <degrees>      an equisolid fisheye lens, up to 180 degrees
+  --panorama                 all the way around, twice as wide as tall
+  --distortion <k1,k2,k3,p1,p2>  lens distortion of a perspective camera
+  --shift <x,y>              move the picture across, in shares of its height
+  --tilt <x,y>               turn the plane of focus, in degrees
+  --aperture <radius>        blur what's out of focus
+  --focus <distance>         where the focus is, the point looked at without it
+  --blades <count>           a polygonal aperture
+  --shutter <time>           how long the shutter stays open
+  --velocity <x,y,z>         how fast the camera moves meanwhile
+  --near <distance>          leave out what's nearer
+  --far <distance>           leave out what's further
+  --stereo <separation>      render a stereo pair to -left and -right files
+  --convergence <distance>   where the eyes of the pair look
+  --side-by-side             put the pair in one file
+  --animation <file>         render a fly-through along the keyframes in the file
+  --view <name or number>    the camera of a glTF scene to look through
+  --help                     print this";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(error) = run(args) {
+        eprintln!("{}\n\n{}", error, USAGE);
+        std::process::exit(1);
+    }
+}
+
+fn run(mut args: Vec<String>) -> Result<(), String> {
+    let mut canvas = Canvas{ width: 800, height: 600 };
+    // The scene to render can be picked by name from the command line, and `--profile` anywhere
+    // on it prints where the time of each full render went. `--listen <address>` has other
+    // machines help render, each started with the same command line but `--worker <address>`,
+    // which renders the tiles it's given instead of opening a window. `--output <file>` saves the
+    // first full frame as a .ppm image. `--camera <x,y,z>` and `--look-at <x,y,z>` move the camera
+    // and turn it, from the origin looking down +Z, and `--fov <degrees>` sets how much it sees
+    // up and down. `--ortho <width>` makes it orthographic, seeing that many units across, and
+    // `--fisheye <degrees>` and `--equisolid <degrees>` put a fisheye lens on it, seeing up to
+    // 180 degrees around. `--panorama` sees all the way around instead, into a picture twice as
+    // wide as it's tall. `--stereo <separation>` renders a stereo pair from eyes that far apart,
+    // looking at a point `--convergence <distance>` away, or the one the camera looks at, into
+    // `-left` and `-right` files, or one with them `--side-by-side`. `--aperture <radius>` blurs
+    // what's nearer or further than `--focus <distance>`, or the point the camera looks at, and
+    // `--blades <count>` makes the aperture a polygon rather than round. `--shutter <time>` keeps
+    // the shutter open that long after the time of the picture, while the camera moves at
+    // `--velocity <x,y,z>`. `--animation <file>` renders the frames of a fly-through along the
+    // keyframes in the file, numbered after the output file. `--distortion <k1,k2,k3,p1,p2>`
+    // gives a perspective camera the distortion of a real lens, the ones left out 0.
+    // `--view <name or number>` picks the camera of a glTF scene to look through, the picture
+    // taking the shape it was made for, and the other camera options change it from there.
+    // `--shift <x,y>` moves the picture across by those shares of its height, and
+    // `--tilt <x,y>` turns the plane of focus that many degrees away on the right and at the top.
+    // `--near <distance>` and `--far <distance>` leave out what's nearer or further than that
+    let mut flag = |name: &str| {
+        let found = args.iter().any(|arg| arg == name);
+        args.retain(|arg| arg != name);
+        found
+    };
+    if flag("--profile") {
+        profile::enable();
+    }
+    let (panorama, side_by_side) = (flag("--panorama"), flag("--side-by-side"));
+    let mut option = |name: &str| {
+        let Some(k) = args.iter().position(|arg| arg == name) else {
+            return Ok(None);
+        };
+        args.remove(k);
+        if k == args.len() {
+            return Err(format!("{} needs a value", name));
+        }
+        Ok(Some(args.remove(k)))
+    };
+    let (listen, coordinator, output) = (option("--listen")?, option("--worker")?, option("--output")?);
+    let (position, target, fov, ortho) = (option("--camera")?, option("--look-at")?, option("--fov")?, option("--ortho")?);
+    let (stereo, convergence) = (option("--stereo")?, option("--convergence")?);
+    let (aperture, focus, blades) = (option("--aperture")?, option("--focus")?, option("--blades")?);
+    let (shutter, velocity, animation) = (option("--shutter")?, option("--velocity")?, option("--animation")?);
+    let (distortion, shift, tilt, view) = (option("--distortion")?, option("--shift")?, option("--tilt")?, option("--view")?);
+    let (near, far) = (option("--near")?, option("--far")?);
+    let fisheye = match (option("--fisheye")?, option("--equisolid")?) {
+        (Some(fov), _) => Some((fov, Lens::Equidistant)),
+        (None, fov) => fov.map(|fov| (fov, Lens::Equisolid))
+    };
+    // Whatever options are left weren't any of the above
+    if let Some(unknown) = args.iter().skip(1).find(|arg| arg.starts_with("--")) {
+        return Err(format!("unknown option {}", unknown));
+    }
+    let arg = |k: usize| args.get(k).map(String::as_str);
+    let mut imported = None;
+    let subdivisions = |k: usize| arg(k).map_or(Ok(0), |levels| parse(levels, "a whole number of subdivision levels"));
+    let mut scene = match arg(1) {
+        Some("outdoor") => outdoor_scene(),
+        Some("studio") => studio_scene(),
+        Some("lights") => many_lights_scene(),
+        Some("room") => room_scene(),
+        Some("physical") => physical_scene(),
+        Some("pbr") => pbr_scene(),
+        Some("catcher") => catcher_scene(),
+        Some("presets") => presets_scene(),
+        Some("toon") => toon_scene(),
+        Some("dome") => dome_scene(),
+        Some("mesh") => mesh_scene(),
+        Some("terrain") => terrain_scene(arg(2))?,
+        Some("rocks") => rocks_scene(),
+        Some("shapes") => shapes_scene(),
+        Some("csg") => csg_scene(),
+        Some("sdf") => sdf_scene(),
+        Some("metaballs") => metaballs_scene(),
+        Some("fractals") => fractals_scene(arg(2), arg(3))?,
+        Some("transforms") => transforms_scene(),
+        Some("instances") => instances_scene(arg(2))?,
+        Some("grass") => grass_scene(),
+        Some("balls") => ball_pit_scene(),
+        Some("points") => points_scene(arg(2))?,
+        Some("model") => model_scene(arg(2).ok_or("missing model file")?, arg(3), subdivisions(4)?)?,
+        Some("gltf") => {
+            let (scene, camera) = gltf_scene(arg(2).ok_or("missing .gltf or .glb file")?, subdivisions(3)?, view.as_deref())?;
+            imported = camera;
+            scene
+        },
+        None | Some("demo") => demo_scene(),
+        Some(other) => return Err(format!("unknown scene {}", other))
+    };
+    scene.build_acceleration();
+    if let Some(address) = coordinator {
+        return work_for(&mut scene, &address);
+    }
+
+    let fov = fov.map(|fov| parse(&fov, "a number of degrees")).transpose()?;
+    let camera_fov = imported.as_ref().map(|camera| camera.yfov.to_degrees());
+    if let Some(ratio) = imported.as_ref().and_then(|camera| camera.aspect_ratio) {
+        canvas.width = (canvas.height as f32 * ratio).round() as i32;
+    }
+    let mut camera = Camera::new(match (ortho, fisheye) {
+        _ if panorama => {
+            canvas.width = 2 * canvas.height;
+            Projection::Panorama
+        },
+        (Some(width), _) => Projection::Orthographic { width: parse(&width, "a width")? },
+        (None, Some((fov, lens))) => {
+            let fov: f32 = parse(&fov, "a number of degrees")?;
+            if fov <= 0.0 || fov > 180.0 {
+                return Err(String::from("fisheye lenses see up to 180 degrees"));
+            }
+            Projection::Fisheye { fov, lens }
+        },
+        (None, None) => Projection::Perspective { fov: fov.or(camera_fov).unwrap_or(DEFAULT_FOV) }
+    });
+    // Looking down +Z from the origin of its own space, with +Y up
+    if let Some(view) = &imported {
+        camera.position = view.transform.transform_point3(Vec3::ZERO);
+        camera.target = camera.position + view.transform.transform_vector3(Vec3::Z).normalize();
+        camera.up = view.transform.transform_vector3(Vec3::Y);
+    }
+    if let Some(position) = position {
+        camera.position = Vec3::from_array(parse_vector(&position)?);
+    }
+    if let Some(target) = target {
+        camera.target = Vec3::from_array(parse_vector(&target)?);
+    }
+    if let Some(distortion) = distortion {
+        let mut coefficients = [0.0; 5];
+        for (coefficient, value) in coefficients.iter_mut().zip(distortion.split(',')) {
+            *coefficient = parse(value, "a distortion coefficient")?;
+        }
+        let [k1, k2, k3, p1, p2] = coefficients;
+        camera.distortion = Distortion { k1, k2, k3, p1, p2 };
+    }
+    if let Some(shift) = shift {
+        camera.shift = Vec2::from_array(parse_vector(&shift)?);
+    }
+    if let Some(tilt) = tilt {
+        camera.tilt = Vec2::from_array(parse_vector(&tilt)?);
+    }
+    if let Some(aperture) = aperture {
+        camera.aperture = parse(&aperture, "an aperture radius")?;
+        camera.focus_distance = focus.map_or(Ok(camera.position.distance(camera.target)), |distance| parse(&distance, "a focus distance"))?;
+    }
+    if let Some(blades) = blades {
+        camera.bokeh = Bokeh::Polygon { sides: parse(&blades, "a whole number of blades")? };
+    }
+    if let Some(shutter) = shutter {
+        camera.shutter_close = parse(&shutter, "a shutter time")?;
+    }
+    if let Some(velocity) = velocity {
+        camera.velocity = Vec3::from_array(parse_vector(&velocity)?);
+    }
+    if let Some(near) = near {
+        camera.near = parse(&near, "a near distance")?;
+    }
+    if let Some(far) = far {
+        camera.far = parse(&far, "a far distance")?;
+    }
+    if camera.near < 0.0 || camera.near >= camera.far {
+        return Err(String::from("the near distance has to be 0 or more and less than the far one"));
+    }
+    // It takes a lot of rays through different points of the lens, or at different times, for
+    // the blur to come out smooth
+    if camera.blurs() {
+        scene.settings.min_samples = scene.settings.min_samples.max(16);
+        scene.settings.max_samples = scene.settings.max_samples.max(64);
+    }
+
+    let mut renderer = Renderer::new(canvas);
+    if let Some(address) = listen {
+        renderer.remote = Some(network::listen(&address).map_err(|e| format!("couldn't listen for workers on {}: {}", address, e))?);
+    }
+    // Stereo pairs are rendered straight to files, the window only shows one camera
+    if let Some(separation) = stereo {
+        let separation: f32 = parse(&separation, "an eye separation")?;
+        let convergence = convergence.map_or(Ok(camera.position.distance(camera.target)), |distance| parse(&distance, "a convergence distance"))?;
+        let (left, right) = camera.eyes(separation, convergence);
+        let [left, right] = [left, right].map(|eye| renderer.render(&scene, eye, PREVIEW_BLOCK, 1, |_| {}));
+        profile::report();
+        let output = output.as_deref().unwrap_or("render.ppm");
+        let saved = if side_by_side {
+            vec![(left.beside(&right), PathBuf::from(output))]
+        } else {
+            vec![(left, suffixed(output, "left")), (right, suffixed(output, "right"))]
+        };
+        for (frame, path) in saved {
+            frame.save(&path).map_err(|e| format!("couldn't save {}: {}", path.display(), e))?;
+            println!("Saved {}", path.display());
+        }
+        return Ok(());
+    }
+    // So are fly-throughs, a frame at a time
+    if let Some(file) = animation {
+        let path = animation::load(&file)?;
+        let frames = (path.duration() * path.fps).floor() as u32 + 1;
+        let output = output.as_deref().unwrap_or("render.ppm");
+        for k in 0..frames {
+            let frame = renderer.render(&scene, path.camera_at(camera, k as f32 / path.fps), PREVIEW_BLOCK, 1, |_| {});
+            let saved = suffixed(output, &format!("{:04}", k));
+            frame.save(&saved).map_err(|e| format!("couldn't save {}: {}", saved.display(), e))?;
+            println!("Saved {} of {} frames, {}", k + 1, frames, saved.display());
+        }
+        profile::report();
+        return Ok(());
+    }
+    #[cfg(feature = "window")]
+    window::run(&mut scene, renderer, camera, output.as_deref());
+    // Without a window the frame is rendered once and saved
+    #[cfg(not(feature = "window"))]
+    {
+        let frame = renderer.render(&scene, camera, PREVIEW_BLOCK, 1, |_| {});
+        profile::report();
+        let output = output.as_deref().unwrap_or("render.ppm");
+        frame.save(output).map_err(|e| format!("couldn't save {}: {}", output, e))?;
+        println!("Saved {}", output);
     }
+    Ok(())
 }
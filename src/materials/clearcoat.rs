@@ -0,0 +1,69 @@
+use super::{fresnel, ggx, mirror_fraction, reflect_ray, roughness_to_alpha, Material, Scattered, SurfaceHit};
+use crate::RenderSettings;
+use glam::Vec3;
+
+// Thin smooth varnish over another material, like the lacquer on car paint. The coat reflects
+// a bit of the light with its own sharp highlight and lets the rest through to the base
+pub struct Clearcoat {
+    pub base: Box<dyn Material>,
+    // How much coat there is, from 0 for none to 1 for a full layer
+    pub strength: f32,
+    // Perceptual roughness of the coat, usually a lot smoother than the base
+    pub roughness: f32,
+    // Index of refraction of the coat, which sets how much it reflects
+    pub ior: f32
+}
+
+impl Clearcoat {
+    // Fraction of the light the coat reflects at the given angle
+    fn reflectance(&self, cos: f32) -> f32 {
+        let f0 = ((self.ior - 1.0) / (self.ior + 1.0)).powi(2);
+        self.strength * fresnel(Vec3::splat(f0), cos).x
+    }
+}
+
+impl Material for Clearcoat {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, v: Vec3, settings: &RenderSettings) -> Vec3 {
+        let n = hit.normal;
+        let h = (l.normalize() + v.normalize()).normalize();
+        let coat = self.reflectance(v.normalize().dot(h)) * ggx::reflectance(n, l, v, roughness_to_alpha(self.roughness));
+        // The light reflected by the coat on the way in and out never reaches the base
+        let through = (1.0 - self.reflectance(n.dot(l.normalize()))) * (1.0 - self.reflectance(n.dot(v.normalize())));
+        self.base.shade(hit, l, v, settings) * through + Vec3::splat(coat)
+    }
+
+    // The view direction isn't known here, so the coat is taken as seen head on
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3 {
+        let coat = self.reflectance(1.0);
+        self.base.ambient(hit) * (1.0 - coat) + Vec3::splat(coat * (1.0 - mirror_fraction(self.roughness)))
+    }
+
+    fn scatter(&self, hit: &SurfaceHit, direction: Vec3) -> Vec<Scattered> {
+        let v = -direction.normalize();
+        let coat = self.reflectance(hit.normal.dot(v).abs());
+        let mut rays: Vec<Scattered> = self
+            .base
+            .scatter(hit, direction)
+            .into_iter()
+            .map(|ray| Scattered { weight: ray.weight * (1.0 - coat), ..ray })
+            .collect();
+        rays.push(Scattered {
+            direction: reflect_ray(v, hit.normal),
+            weight: Vec3::splat(coat * mirror_fraction(self.roughness)),
+            channel: None
+        });
+        rays
+    }
+
+    fn emission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.emission(hit)
+    }
+
+    fn transmission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.transmission(hit)
+    }
+
+    fn orient(&self, hit: &mut SurfaceHit, direction: Vec3) {
+        self.base.orient(hit, direction);
+    }
+}
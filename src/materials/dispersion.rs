@@ -0,0 +1,26 @@
+// Index of refraction that depends on the wavelength of the light. Wavelengths are in
+// micrometers, as the published coefficients use them.
+
+// Wavelength each color channel is refracted at
+pub const CHANNEL_WAVELENGTHS: [f32; 3] = [0.65, 0.55, 0.45];
+
+pub enum Dispersion {
+    // n = a + b / wavelength^2, a simple fit that is good enough for most glasses
+    Cauchy { a: f32, b: f32 },
+    // n^2 = 1 + sum of b[i] * wavelength^2 / (wavelength^2 - c[i]), the form glass makers
+    // publish their measurements in
+    Sellmeier { b: [f32; 3], c: [f32; 3] }
+}
+
+impl Dispersion {
+    pub fn ior(&self, wavelength: f32) -> f32 {
+        let w2 = wavelength * wavelength;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / w2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * w2 / (w2 - c[i])).sum::<f32>();
+                n2.sqrt()
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+// GGX (Trowbridge-Reitz) microfacet specular reflection with Smith shadowing-masking.
+//
+// The surface is modeled as lots of tiny mirrors, `alpha` sets how spread out their
+// orientations are. Unlike a Phong exponent the lobe keeps a long tail at high roughness,
+// which is what makes rough metals look right. The spread can differ along the tangent and
+// the bitangent, which stretches the highlight like on brushed metal.
+
+use glam::Vec3;
+use std::f32::consts::PI;
+
+// Specular reflection of light arriving along `l` towards `v`, before the Fresnel term and with
+// the cosine of the angle of incidence applied, scaled like the diffuse term so that the lights
+// don't need a 1 / pi
+pub fn reflectance(n: Vec3, l: Vec3, v: Vec3, alpha: f32) -> f32 {
+    anisotropic_reflectance(n, n.any_orthonormal_vector(), l, v, alpha, alpha)
+}
+
+// Same as `reflectance`, with `alpha_t` along the tangent `t` and `alpha_b` across it
+pub fn anisotropic_reflectance(n: Vec3, t: Vec3, l: Vec3, v: Vec3, alpha_t: f32, alpha_b: f32) -> f32 {
+    let l = l.normalize();
+    let v = v.normalize();
+    let n_dot_l = n.dot(l);
+    let n_dot_v = n.dot(v);
+    if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+        return 0.0;
+    }
+    let frame = Frame { n, t, b: n.cross(t), alpha_t, alpha_b };
+    let h = (l + v).normalize();
+    PI * frame.distribution(h) * frame.smith_g1(l) * frame.smith_g1(v) / (4.0 * n_dot_v)
+}
+
+struct Frame {
+    n: Vec3,
+    t: Vec3,
+    b: Vec3,
+    alpha_t: f32,
+    alpha_b: f32
+}
+
+impl Frame {
+    // Density of the microfacets facing along the half vector
+    fn distribution(&self, h: Vec3) -> f32 {
+        let n_dot_h = self.n.dot(h);
+        if n_dot_h <= 0.0 {
+            return 0.0;
+        }
+        let x = self.t.dot(h) / self.alpha_t;
+        let y = self.b.dot(h) / self.alpha_b;
+        let d = x * x + y * y + n_dot_h * n_dot_h;
+        1.0 / (PI * self.alpha_t * self.alpha_b * d * d)
+    }
+
+    // Fraction of the microfacets seen from direction `x` that aren't hidden by others, the
+    // light and view directions are treated as independent
+    fn smith_g1(&self, x: Vec3) -> f32 {
+        let n_dot_x = self.n.dot(x);
+        let along = self.t.dot(x) * self.alpha_t;
+        let across = self.b.dot(x) * self.alpha_b;
+        2.0 * n_dot_x / (n_dot_x + (along * along + across * across + n_dot_x * n_dot_x).sqrt())
+    }
+}
@@ -0,0 +1,43 @@
+use super::{Material, ScalarTexture, Scattered, SurfaceHit};
+use crate::RenderSettings;
+use glam::Vec3;
+
+// Blend of two materials, like rust patches over metal. The factor can vary over the surface
+pub struct Mix {
+    pub first: Box<dyn Material>,
+    pub second: Box<dyn Material>,
+    // 0 for only the first material, 1 for only the second one
+    pub factor: ScalarTexture
+}
+
+impl Mix {
+    fn blend(&self, hit: &SurfaceHit, first: Vec3, second: Vec3) -> Vec3 {
+        first.lerp(second, self.factor.sample(hit).clamp(0.0, 1.0))
+    }
+}
+
+impl Material for Mix {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, v: Vec3, settings: &RenderSettings) -> Vec3 {
+        self.blend(hit, self.first.shade(hit, l, v, settings), self.second.shade(hit, l, v, settings))
+    }
+
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3 {
+        self.blend(hit, self.first.ambient(hit), self.second.ambient(hit))
+    }
+
+    // The rays of both materials, weighted by how much of each there is
+    fn scatter(&self, hit: &SurfaceHit, direction: Vec3) -> Vec<Scattered> {
+        let factor = self.factor.sample(hit).clamp(0.0, 1.0);
+        let first = self.first.scatter(hit, direction).into_iter().map(|ray| Scattered { weight: ray.weight * (1.0 - factor), ..ray });
+        let second = self.second.scatter(hit, direction).into_iter().map(|ray| Scattered { weight: ray.weight * factor, ..ray });
+        first.chain(second).filter(|ray| ray.weight != Vec3::ZERO).collect()
+    }
+
+    fn emission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.blend(hit, self.first.emission(hit), self.second.emission(hit))
+    }
+
+    fn transmission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.blend(hit, self.first.transmission(hit), self.second.transmission(hit))
+    }
+}
@@ -0,0 +1,157 @@
+// How surfaces respond to light.
+//
+// A material answers two questions about a point on a surface: how much of the light arriving
+// from a light source is sent towards the viewer (`shade`), and which rays have to be traced
+// further to see what the surface reflects or lets through (`scatter`). Objects refer to their
+// material by id, so several of them can share one.
+
+mod clearcoat;
+mod dispersion;
+mod ggx;
+mod mix;
+mod oren_nayar;
+mod pbr;
+mod phong;
+pub mod presets;
+mod shadow_catcher;
+mod sheen;
+mod sided;
+mod texture;
+mod thin_film;
+mod toon;
+
+pub use clearcoat::Clearcoat;
+pub use dispersion::Dispersion;
+pub use mix::Mix;
+pub use pbr::Pbr;
+pub use phong::{Phong, SpecularModel};
+pub use shadow_catcher::ShadowCatcher;
+pub use sheen::Sheen;
+pub use sided::Sided;
+pub use texture::{noise, ColorTexture, ScalarTexture, Texture};
+pub use thin_film::ThinFilm;
+pub use toon::Toon;
+
+use crate::RenderSettings;
+use glam::{Vec2, Vec3};
+
+// Reflectance at normal incidence of dielectrics, which is about the same for all of them
+const DIELECTRIC_F0: f32 = 0.04;
+
+// Index into the materials of the scene
+pub type MaterialId = usize;
+
+// The point being shaded
+pub struct SurfaceHit {
+    pub point: Vec3,
+    // Unit normal pointing out of the object
+    pub normal: Vec3,
+    // Unit vector on the surface, perpendicular to the normal, that orients direction
+    // dependent effects like anisotropic highlights
+    pub tangent: Vec3,
+    // Texture coordinates, where images are looked up
+    pub uv: Vec2,
+    // Color painted on the vertices of a mesh, like scanners do, None when there isn't any
+    pub color: Option<Vec3>
+}
+
+// A ray the surface sends on, and how much of what it sees ends up in the color of the surface
+pub struct Scattered {
+    pub direction: Vec3,
+    pub weight: Vec3,
+    // Set when the ray only carries one color channel, like the light of a single wavelength
+    // split off by a dispersive material
+    pub channel: Option<usize>
+}
+
+pub trait Material: Send + Sync {
+    // Fraction of the light arriving along `l` that is reflected towards `v`, per channel,
+    // with the cosine of the angle of incidence already applied
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, v: Vec3, settings: &RenderSettings) -> Vec3;
+
+    // Fraction of the ambient light that is reflected, which comes from every direction
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3;
+
+    // Rays to follow from the surface, for a ray that hit it going along `direction`
+    fn scatter(&self, _hit: &SurfaceHit, _direction: Vec3) -> Vec<Scattered> {
+        vec![]
+    }
+
+    // Light given off by the surface itself
+    fn emission(&self, _hit: &SurfaceHit) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    // Fraction of the light that goes through the object, per channel, for shadow rays.
+    // Opaque objects block it completely
+    fn transmission(&self, _hit: &SurfaceHit) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    // Whether the surface is only there to show the shadows and reflections it receives over
+    // the background, see ShadowCatcher
+    fn catches_shadows(&self) -> bool {
+        false
+    }
+
+    // Color of the ink line drawn around the object and along its creases, for non
+    // photorealistic materials
+    fn outline(&self) -> Option<Vec3> {
+        None
+    }
+
+    // Turns the hit around to the side the material shades, for a ray going along `direction`.
+    // The normal points out of the object unless the material changes it
+    fn orient(&self, _hit: &mut SurfaceHit, _direction: Vec3) {}
+}
+
+// Reflects `r` around the normal `n`
+fn reflect_ray(r: Vec3, n: Vec3) -> Vec3 {
+    2.0 * n * n.dot(r) - r
+}
+
+// Schlick's approximation of the Fresnel reflectance of a dielectric, for a ray going along `d`
+// that refracts into `refracted` through a surface with outward normal `n`
+fn schlick(d: Vec3, n: Vec3, refracted: Vec3, ior: f32) -> f32 {
+    let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+    // The approximation needs the angle on the less dense side of the surface, which is the
+    // incoming ray going in and the refracted ray coming out
+    let cos = if d.dot(n) < 0.0 {
+        -d.normalize().dot(n)
+    } else {
+        refracted.normalize().dot(n)
+    };
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+// Schlick's approximation for a surface with reflectance `f0` at normal incidence, which can be
+// colored for metals, seen at an angle with cosine `cos`
+fn fresnel(f0: Vec3, cos: f32) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+// Width of a GGX highlight, squared as glTF does so roughness looks linear
+fn roughness_to_alpha(roughness: f32) -> f32 {
+    // A perfectly smooth surface would have an infinitely small highlight
+    roughness.clamp(0.03, 1.0).powi(2)
+}
+
+// How much of the reflection of a surface with this roughness is sharp enough to be traced with
+// a single mirror ray. Rough surfaces blur it out, which one ray can't do, so it fades away
+fn mirror_fraction(roughness: f32) -> f32 {
+    (1.0 - roughness.clamp(0.0, 1.0)).powi(2)
+}
+
+// Bends `d` through a surface with outward normal `n` between the air and a medium of index
+// of refraction `ior`, following Snell's law. None on total internal reflection
+fn refract_ray(d: Vec3, n: Vec3, ior: f32) -> Option<Vec3> {
+    let d = d.normalize();
+    // Going in or coming out of the medium
+    let (n, eta) = if d.dot(n) < 0.0 { (n, 1.0 / ior) } else { (-n, ior) };
+    let cos_i = -n.dot(d);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        return None;
+    }
+    Some(eta * d + (eta * cos_i - k.sqrt()) * n)
+}
@@ -0,0 +1,39 @@
+// Oren-Nayar diffuse reflection of rough surfaces ("Generalization of Lambert's Reflectance
+// Model", 1994), in its common qualitative form.
+//
+// The surface is made of tiny V-shaped grooves, each one a Lambertian reflector. Facets facing
+// back towards the light light up when seen from the light's side, so rough materials like
+// chalk or concrete look flatter and brighter at the edges than a Lambertian surface.
+
+use glam::Vec3;
+
+// Diffuse reflection of light arriving along `l` towards `v`, with the cosine of the angle of
+// incidence applied. `sigma` is the spread of the groove angles in degrees, 0 is Lambertian
+pub fn reflectance(n: Vec3, l: Vec3, v: Vec3, sigma: f32) -> f32 {
+    let l = l.normalize();
+    let v = v.normalize();
+    let cos_i = n.dot(l);
+    if cos_i <= 0.0 {
+        return 0.0;
+    }
+    let cos_r = n.dot(v).clamp(0.0, 1.0);
+
+    let s2 = sigma.to_radians().powi(2);
+    let a = 1.0 - 0.5 * s2 / (s2 + 0.33);
+    let b = 0.45 * s2 / (s2 + 0.09);
+
+    // Cosine of the azimuth between the light and the viewer, around the normal
+    let l_tangent = l - n * cos_i;
+    let v_tangent = v - n * cos_r;
+    let cos_azimuth = if l_tangent.length_squared() > 1e-12 && v_tangent.length_squared() > 1e-12 {
+        l_tangent.normalize().dot(v_tangent.normalize()).max(0.0)
+    } else {
+        0.0
+    };
+
+    let theta_i = cos_i.acos();
+    let theta_r = cos_r.acos();
+    let alpha = theta_i.max(theta_r);
+    let beta = theta_i.min(theta_r);
+    cos_i * (a + b * cos_azimuth * alpha.sin() * beta.tan())
+}
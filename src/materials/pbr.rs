@@ -0,0 +1,108 @@
+use super::{fresnel, ggx, mirror_fraction, reflect_ray, roughness_to_alpha, ColorTexture, Material, ScalarTexture, Scattered, Sheen, SurfaceHit, DIELECTRIC_F0};
+use crate::RenderSettings;
+use glam::Vec3;
+
+// Physically based material following the glTF metallic-roughness model. Dielectrics have a
+// diffuse base color and a faint white highlight, metals have no diffuse part and tint their
+// reflections with the base color instead
+pub struct Pbr {
+    // Linear RGB, each channel in [0, 1]
+    pub base_color: ColorTexture,
+    // 0 for dielectrics, 1 for metals, in between only to blend the two
+    pub metallic: ScalarTexture,
+    // Perceptual roughness in [0, 1], from a perfect mirror to a completely rough surface
+    pub roughness: ScalarTexture,
+    // Roughness across the tangent when it differs from the one along it, which stretches the
+    // highlight across the tangent like on brushed metal. None for an isotropic surface
+    pub bitangent_roughness: Option<ScalarTexture>,
+    // Extra glow at grazing angles for cloth, None for no sheen
+    pub sheen: Option<Sheen>,
+    // Light given off by the surface, like glTF's emissive factor
+    pub emission: Vec3
+}
+
+impl Default for Pbr {
+    fn default() -> Self {
+        Pbr {
+            base_color: Vec3::ONE.into(),
+            metallic: 0.0.into(),
+            roughness: 0.5.into(),
+            bitangent_roughness: None,
+            sheen: None,
+            emission: Vec3::ZERO
+        }
+    }
+}
+
+impl Pbr {
+    fn f0(&self, hit: &SurfaceHit) -> Vec3 {
+        Vec3::splat(DIELECTRIC_F0).lerp(self.base_color.sample(hit), self.metallic.sample(hit))
+    }
+
+    fn diffuse_color(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base_color.sample(hit) * (1.0 - self.metallic.sample(hit))
+    }
+
+    fn roughness(&self, hit: &SurfaceHit) -> (f32, f32) {
+        let roughness = self.roughness.sample(hit);
+        (roughness, self.bitangent_roughness.as_ref().map_or(roughness, |r| r.sample(hit)))
+    }
+
+    // Width of the highlight along the tangent and across it, squared as glTF does so
+    // roughness looks linear
+    fn alpha(&self, hit: &SurfaceHit) -> (f32, f32) {
+        let (along, across) = self.roughness(hit);
+        (roughness_to_alpha(along), roughness_to_alpha(across))
+    }
+
+    fn mirror_fraction(&self, hit: &SurfaceHit) -> f32 {
+        let (along, across) = self.roughness(hit);
+        mirror_fraction(along.max(across))
+    }
+}
+
+impl Material for Pbr {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, v: Vec3, _settings: &RenderSettings) -> Vec3 {
+        let n = hit.normal;
+        let l = l.normalize();
+        let v = v.normalize();
+        let n_dot_l = n.dot(l);
+        if n_dot_l <= 0.0 || n.dot(v) <= 0.0 {
+            return Vec3::ZERO;
+        }
+
+        let h = (l + v).normalize();
+        let f = fresnel(self.f0(hit), v.dot(h));
+        let (alpha_t, alpha_b) = self.alpha(hit);
+        let specular = f * ggx::anisotropic_reflectance(n, hit.tangent, l, v, alpha_t, alpha_b);
+        // What isn't reflected at the surface goes in and is scattered back out diffusely
+        let diffuse = (Vec3::ONE - f) * self.diffuse_color(hit);
+
+        // The sheen sits on top of the rest, without taking any light away from it
+        let sheen = self.sheen.as_ref().map_or(Vec3::ZERO, |sheen| sheen.reflectance(hit, l, v));
+
+        diffuse * n_dot_l + specular + sheen
+    }
+
+    // The part of the reflections that the mirror ray leaves out on rough surfaces is made up
+    // for with the ambient light, so rough metals aren't black where nothing lights them
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3 {
+        self.diffuse_color(hit) + self.f0(hit) * (1.0 - self.mirror_fraction(hit))
+    }
+
+    // Mirror reflection of the surroundings. Rough surfaces blur it out, which a single ray
+    // can't do, so it fades away as the roughness goes up
+    fn scatter(&self, hit: &SurfaceHit, direction: Vec3) -> Vec<Scattered> {
+        let n = hit.normal;
+        let v = -direction.normalize();
+        let weight = fresnel(self.f0(hit), n.dot(v)) * self.mirror_fraction(hit);
+        if weight == Vec3::ZERO {
+            return vec![];
+        }
+        vec![Scattered { direction: reflect_ray(v, n), weight, channel: None }]
+    }
+
+    fn emission(&self, _hit: &SurfaceHit) -> Vec3 {
+        self.emission
+    }
+}
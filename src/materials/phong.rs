@@ -0,0 +1,225 @@
+use super::{dispersion, fresnel, ggx, oren_nayar, reflect_ray, refract_ray, schlick, ColorTexture, Dispersion, Material, ScalarTexture, Scattered, SurfaceHit, DIELECTRIC_F0};
+use crate::RenderSettings;
+use glam::Vec3;
+
+// How highlights are computed. Blinn-Phong needs about 4 times the exponent of Phong
+// for a highlight of the same size. GGX turns the exponent into the equivalent roughness
+// and gives a plastic-like highlight that is brighter when sharp and has wider tails
+#[derive(Copy, Clone)]
+pub enum SpecularModel {
+    Phong,
+    BlinnPhong,
+    Ggx
+}
+
+// The classic material of the book: diffuse color with a Phong highlight, plus mirror
+// reflections and refraction for glass
+pub struct Phong {
+    // Linear RGB, each channel in [0, 1]
+    pub color: ColorTexture,
+    // Roughness of the diffuse reflection, as the spread of the angles of the microscopic
+    // grooves in degrees (see oren_nayar). 0 is a smooth Lambertian surface, 20 to 40 suits
+    // matte materials like chalk or concrete
+    pub sigma: f32,
+    // Shininess exponent of the highlight, None for a matte surface
+    pub specular: Option<ScalarTexture>,
+    // Overrides the specular model of the render settings
+    pub specular_model: Option<SpecularModel>,
+    // How much of the surrounding the surface reflects like a mirror, from 0 to 1. Transparent
+    // surfaces already reflect depending on the angle they're seen at, this adds to it
+    pub reflective: f32,
+    // Fraction of the light that goes through the surface, per channel. Zero for opaque ones
+    pub transmission: Vec3,
+    // Index of refraction of transparent objects, 1.0 for air and about 1.5 for glass
+    pub ior: f32,
+    // How the index of refraction changes with the wavelength, which splits white light into
+    // colors. Replaces `ior` when set
+    pub dispersion: Option<Dispersion>,
+    // Light given off by the surface (color times strength), zero if it doesn't glow.
+    // In nits when the scene uses physical light units
+    pub emission: Vec3
+}
+
+impl Default for Phong {
+    fn default() -> Self {
+        Phong {
+            color: Vec3::ONE.into(),
+            sigma: 0.0,
+            specular: None,
+            specular_model: None,
+            reflective: 0.0,
+            transmission: Vec3::ZERO,
+            ior: 1.0,
+            dispersion: None,
+            emission: Vec3::ZERO
+        }
+    }
+}
+
+impl Phong {
+    // Part of the surface color that is lit locally, the rest comes from the scattered rays
+    fn local_weight(&self) -> Vec3 {
+        (Vec3::ONE - self.transmission) * (1.0 - self.reflective)
+    }
+}
+
+impl Material for Phong {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, v: Vec3, settings: &RenderSettings) -> Vec3 {
+        let model = self.specular_model.unwrap_or(settings.specular_model);
+        let specular = self.specular.as_ref().map(|specular| specular.sample(hit));
+        // Each channel of the surface is lit by the same channel of the lights
+        self.color.sample(hit) * self.local_weight() * reflected_light(hit.normal, l, v, self.sigma, specular, model)
+    }
+
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3 {
+        self.color.sample(hit) * self.local_weight()
+    }
+
+    fn scatter(&self, hit: &SurfaceHit, direction: Vec3) -> Vec<Scattered> {
+        let n = hit.normal;
+        let mut rays = Vec::new();
+        // Mirror the ray around the normal to see what the surface reflects
+        let mut reflected_weight = Vec3::splat(self.reflective);
+
+        // Bend the ray into (or out of) the object to see what's behind the surface. How much
+        // is reflected instead depends on the angle, a lot more at grazing angles
+        if self.transmission != Vec3::ZERO {
+            let dielectric_weight = self.transmission * (1.0 - self.reflective);
+            // A dispersive material bends each channel by a different amount, so each one
+            // needs its own ray
+            let refractions = match &self.dispersion {
+                None => vec![(self.ior, Vec3::ONE, None)],
+                Some(dispersion) => (0..3)
+                    .map(|channel| {
+                        let mut mask = Vec3::ZERO;
+                        mask[channel] = 1.0;
+                        (dispersion.ior(dispersion::CHANNEL_WAVELENGTHS[channel]), mask, Some(channel))
+                    })
+                    .collect()
+            };
+            for (ior, mask, channel) in refractions {
+                let weight = dielectric_weight * mask;
+                match refract_ray(direction, n, ior) {
+                    Some(refracted) => {
+                        let reflectance = schlick(direction, n, refracted, ior);
+                        rays.push(Scattered { direction: refracted, weight: weight * (1.0 - reflectance), channel });
+                        reflected_weight += weight * reflectance;
+                    },
+                    // Past the critical angle all the light is reflected back inside
+                    None => reflected_weight += weight
+                }
+            }
+        }
+
+        if reflected_weight != Vec3::ZERO {
+            rays.push(Scattered { direction: reflect_ray(-direction, n), weight: reflected_weight, channel: None });
+        }
+        rays
+    }
+
+    fn emission(&self, _hit: &SurfaceHit) -> Vec3 {
+        self.emission
+    }
+
+    fn transmission(&self, _hit: &SurfaceHit) -> Vec3 {
+        self.transmission
+    }
+}
+
+// Diffuse and specular reflection of a light coming from `l`, seen from `v`
+fn reflected_light(n: Vec3, l: Vec3, v: Vec3, sigma: f32, specular: Option<f32>, model: SpecularModel) -> f32 {
+    let mut i = 0.0;
+
+    // Diffuse
+    let n_dot_l = n.dot(l);
+    // If the angle between the normal and the light vector is greater than 90,
+    // the light is coming from behind the surface, so it cannot contribute to the lighting
+    if sigma > 0.0 {
+        i += oren_nayar::reflectance(n, l, v, sigma);
+    } else if n_dot_l > 0.0 {
+        i += n_dot_l / (n.length() * l.length());
+    }
+
+    // Specular
+    match (specular, model) {
+        (None, _) => {},
+        (Some(s), SpecularModel::Phong) => {
+            // Reflect the light vector around the normal
+            let r = reflect_ray(l, n);
+            let r_dot_v = r.dot(v);
+            // The highlight only shows up when the reflected light goes towards the viewer
+            if r_dot_v > 0.0 {
+                i += (r_dot_v / (r.length() * v.length())).powf(s);
+            }
+        },
+        (Some(s), SpecularModel::BlinnPhong) => {
+            // Halfway between the light and the viewer, the closer to the normal the brighter
+            let h = (l.normalize() + v.normalize()).normalize();
+            let n_dot_h = n.normalize().dot(h);
+            if n_dot_l > 0.0 && n_dot_h > 0.0 {
+                i += n_dot_h.powf(s);
+            }
+        },
+        (Some(s), SpecularModel::Ggx) => {
+            let alpha = (2.0 / (s + 2.0)).sqrt();
+            let h = (l.normalize() + v.normalize()).normalize();
+            let f = fresnel(Vec3::splat(DIELECTRIC_F0), v.normalize().dot(h)).x;
+            i += f * ggx::reflectance(n.normalize(), l, v, alpha);
+        }
+    }
+
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec2;
+
+    fn facing_up() -> SurfaceHit {
+        SurfaceHit { point: Vec3::ZERO, normal: Vec3::Y, tangent: Vec3::X, uv: Vec2::ZERO, color: None }
+    }
+
+    #[test]
+    fn matte_surfaces_follow_the_cosine_of_the_light() {
+        let color = Vec3::new(0.5, 0.25, 1.0);
+        let matte = Phong { color: color.into(), ..Phong::default() };
+        let settings = RenderSettings::default();
+        let hit = facing_up();
+        assert_eq!(matte.shade(&hit, Vec3::Y, Vec3::Y, &settings), color);
+        // 60 degrees away from the normal, and not of length 1
+        let slanted = matte.shade(&hit, Vec3::new(3.0_f32.sqrt(), 1.0, 0.0), Vec3::Y, &settings);
+        assert!(slanted.distance(color * 0.5) < 1e-6);
+        assert_eq!(matte.shade(&hit, -Vec3::Y, Vec3::Y, &settings), Vec3::ZERO);
+        assert_eq!(matte.ambient(&hit), color);
+    }
+
+    #[test]
+    fn mirrors_reflect_around_the_normal() {
+        let mirror = Phong { reflective: 1.0, ..Phong::default() };
+        let rays = mirror.scatter(&facing_up(), Vec3::new(1.0, -1.0, 0.0).normalize());
+        assert_eq!(rays.len(), 1);
+        assert!(rays[0].direction.distance(Vec3::new(1.0, 1.0, 0.0).normalize()) < 1e-6);
+        assert_eq!(rays[0].weight, Vec3::ONE);
+        // Nothing is left to be lit locally
+        assert_eq!(mirror.ambient(&facing_up()), Vec3::ZERO);
+    }
+
+    #[test]
+    fn glass_splits_the_light_between_reflection_and_refraction() {
+        let glass = Phong { transmission: Vec3::ONE, ior: 1.5, ..Phong::default() };
+        let rays = glass.scatter(&facing_up(), Vec3::new(1.0, -1.0, 0.0).normalize());
+        assert_eq!(rays.len(), 2);
+        let total: Vec3 = rays.iter().map(|ray| ray.weight).sum();
+        assert!(total.distance(Vec3::ONE) < 1e-6);
+        // Bent towards the normal going in, by Snell's law
+        let refracted = rays[0].direction.normalize();
+        assert!(refracted.y < 0.0 && (refracted.x - 0.5_f32.sqrt() / 1.5).abs() < 1e-5);
+
+        // Coming out past the critical angle all of it is reflected back inside
+        let rays = glass.scatter(&facing_up(), Vec3::new(1.0, 0.3, 0.0).normalize());
+        assert_eq!(rays.len(), 1);
+        assert_eq!(rays[0].weight, Vec3::ONE);
+        assert!(rays[0].direction.y < 0.0);
+    }
+}
@@ -0,0 +1,198 @@
+// Ready made materials with measured or commonly used values, so scenes don't have to work
+// them out again. Metal colors are their reflectance at normal incidence in linear RGB.
+
+use super::{noise, Clearcoat, Dispersion, Material, Mix, Pbr, Phong, Sheen, Texture, ThinFilm};
+use glam::Vec3;
+
+// Names accepted by `by_name`
+pub const NAMES: [&str; 18] = [
+    "gold", "silver", "copper", "aluminium", "chrome", "glass", "flint glass", "water", "diamond",
+    "rubber", "jade", "plastic", "chalk", "concrete", "car paint", "soap bubble", "velvet", "rusty metal"
+];
+
+pub fn by_name(name: &str) -> Option<Box<dyn Material>> {
+    let material: Box<dyn Material> = match name {
+        "gold" => Box::new(gold()),
+        "silver" => Box::new(silver()),
+        "copper" => Box::new(copper()),
+        "aluminium" => Box::new(aluminium()),
+        "chrome" => Box::new(chrome()),
+        "glass" => Box::new(glass()),
+        "flint glass" => Box::new(flint_glass()),
+        "water" => Box::new(water()),
+        "diamond" => Box::new(diamond()),
+        "rubber" => Box::new(rubber()),
+        "jade" => Box::new(jade()),
+        "plastic" => Box::new(plastic(Vec3::new(0.8, 0.1, 0.1))),
+        "chalk" => Box::new(chalk()),
+        "concrete" => Box::new(concrete()),
+        "car paint" => Box::new(car_paint(Vec3::new(0.05, 0.1, 0.6))),
+        "soap bubble" => Box::new(soap_bubble()),
+        "velvet" => Box::new(velvet(Vec3::new(0.3, 0.02, 0.08))),
+        "rusty metal" => Box::new(rusty_metal()),
+        _ => return None
+    };
+    Some(material)
+}
+
+fn metal(base_color: Vec3, roughness: f32) -> Pbr {
+    Pbr {
+        base_color: base_color.into(),
+        metallic: 1.0.into(),
+        roughness: roughness.into(),
+        ..Pbr::default()
+    }
+}
+
+pub fn gold() -> Pbr {
+    metal(Vec3::new(1.0, 0.766, 0.336), 0.2)
+}
+
+pub fn silver() -> Pbr {
+    metal(Vec3::new(0.972, 0.960, 0.915), 0.15)
+}
+
+pub fn copper() -> Pbr {
+    metal(Vec3::new(0.955, 0.638, 0.538), 0.25)
+}
+
+pub fn aluminium() -> Pbr {
+    metal(Vec3::new(0.913, 0.922, 0.924), 0.35)
+}
+
+pub fn chrome() -> Pbr {
+    metal(Vec3::new(0.550, 0.556, 0.554), 0.05)
+}
+
+// Clear transparent material with the given index of refraction
+fn dielectric(ior: f32) -> Phong {
+    Phong {
+        specular: Some(1000.0.into()),
+        transmission: Vec3::splat(0.95),
+        ior,
+        ..Phong::default()
+    }
+}
+
+pub fn glass() -> Phong {
+    dielectric(1.5)
+}
+
+// Dense glass with strong dispersion, what prisms are made of
+pub fn flint_glass() -> Phong {
+    Phong {
+        dispersion: Some(Dispersion::Cauchy { a: 1.7, b: 0.012 }),
+        ..dielectric(1.75)
+    }
+}
+
+pub fn water() -> Phong {
+    dielectric(1.33)
+}
+
+// Very high index of refraction that changes a lot with the wavelength, which gives it its fire
+pub fn diamond() -> Phong {
+    Phong {
+        dispersion: Some(Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0]
+        }),
+        ..dielectric(2.42)
+    }
+}
+
+pub fn rubber() -> Pbr {
+    Pbr {
+        base_color: Vec3::splat(0.02).into(),
+        roughness: 0.9.into(),
+        ..Pbr::default()
+    }
+}
+
+// Polished green stone
+pub fn jade() -> Pbr {
+    Pbr {
+        base_color: Vec3::new(0.2, 0.55, 0.3).into(),
+        roughness: 0.2.into(),
+        ..Pbr::default()
+    }
+}
+
+pub fn plastic(color: Vec3) -> Pbr {
+    Pbr {
+        base_color: color.into(),
+        roughness: 0.35.into(),
+        ..Pbr::default()
+    }
+}
+
+pub fn chalk() -> Phong {
+    Phong {
+        color: Vec3::splat(0.9).into(),
+        sigma: 35.0,
+        ..Phong::default()
+    }
+}
+
+pub fn concrete() -> Phong {
+    Phong {
+        color: Vec3::splat(0.5).into(),
+        sigma: 25.0,
+        ..Phong::default()
+    }
+}
+
+// Metallic paint under a glossy clear coat
+pub fn car_paint(color: Vec3) -> Clearcoat {
+    Clearcoat {
+        base: Box::new(Pbr {
+            base_color: color.into(),
+            metallic: 0.4.into(),
+            roughness: 0.45.into(),
+            ..Pbr::default()
+        }),
+        strength: 1.0,
+        roughness: 0.05,
+        ior: 1.5
+    }
+}
+
+// Film of soapy water with air on both sides, only the interference colors show up
+pub fn soap_bubble() -> ThinFilm {
+    ThinFilm {
+        base: Box::new(Phong {
+            transmission: Vec3::ONE,
+            ..Phong::default()
+        }),
+        thickness: 400.0,
+        ior: 1.33,
+        substrate_ior: 1.0
+    }
+}
+
+// Dark cloth with a bright sheen along the silhouette
+pub fn velvet(color: Vec3) -> Pbr {
+    Pbr {
+        base_color: color.into(),
+        roughness: 0.9.into(),
+        sheen: Some(Sheen {
+            color: (color * 2.0 + Vec3::splat(0.3)).min(Vec3::ONE).into(),
+            roughness: 0.4.into()
+        }),
+        ..Pbr::default()
+    }
+}
+
+// Iron with patches of rust, spread by a noise mask
+pub fn rusty_metal() -> Mix {
+    Mix {
+        first: Box::new(metal(Vec3::new(0.56, 0.57, 0.58), 0.3)),
+        second: Box::new(Phong {
+            color: Vec3::new(0.45, 0.18, 0.06).into(),
+            sigma: 30.0,
+            ..Phong::default()
+        }),
+        // Sharpen the noise into patches with soft edges
+        factor: Texture::Procedural(Box::new(|hit| ((noise(hit.point * 6.0) - 0.45) * 6.0).clamp(0.0, 1.0)))
+    }
+}
@@ -0,0 +1,32 @@
+use super::{reflect_ray, Material, Scattered, SurfaceHit};
+use crate::RenderSettings;
+use glam::Vec3;
+
+// Invisible surface that only shows the shadows and reflections falling on it, for compositing
+// the render over a photograph. Where it's hit the background is seen instead, darkened by the
+// shadows of the objects above it. Lights and objects still see it as a white diffuse surface
+pub struct ShadowCatcher {
+    // Strength of the reflections, from 0 for none to 1 for a mirror
+    pub reflective: f32
+}
+
+impl Material for ShadowCatcher {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, _v: Vec3, _settings: &RenderSettings) -> Vec3 {
+        Vec3::splat(hit.normal.dot(l.normalize()).max(0.0))
+    }
+
+    fn ambient(&self, _hit: &SurfaceHit) -> Vec3 {
+        Vec3::ONE
+    }
+
+    fn scatter(&self, hit: &SurfaceHit, direction: Vec3) -> Vec<Scattered> {
+        if self.reflective <= 0.0 {
+            return vec![];
+        }
+        vec![Scattered { direction: reflect_ray(-direction, hit.normal), weight: Vec3::splat(self.reflective), channel: None }]
+    }
+
+    fn catches_shadows(&self) -> bool {
+        true
+    }
+}
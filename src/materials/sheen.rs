@@ -0,0 +1,38 @@
+// Sheen of cloth, following glTF's KHR_materials_sheen: a soft glow at grazing angles from
+// fibers that catch the light edge on. Uses the "Charlie" distribution (Estevez and Kulla,
+// "Production Friendly Microfacet Sheen BRDF") with Neubelt's visibility term.
+
+use super::{ColorTexture, ScalarTexture, SurfaceHit};
+use glam::Vec3;
+use std::f32::consts::PI;
+
+pub struct Sheen {
+    // Like the sheen color factor, black for no sheen
+    pub color: ColorTexture,
+    // Like the sheen roughness factor, how far from the silhouette the sheen reaches
+    pub roughness: ScalarTexture
+}
+
+impl Sheen {
+    // Sheen reflected from light arriving along `l` towards `v`, with the cosine of the angle of
+    // incidence applied and scaled like the diffuse term
+    pub fn reflectance(&self, hit: &SurfaceHit, l: Vec3, v: Vec3) -> Vec3 {
+        let n = hit.normal;
+        let l = l.normalize();
+        let v = v.normalize();
+        let n_dot_l = n.dot(l);
+        let n_dot_v = n.dot(v);
+        if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+            return Vec3::ZERO;
+        }
+
+        // Very small values make the distribution blow up
+        let alpha = self.roughness.sample(hit).clamp(0.07, 1.0).powi(2);
+        let cos_h = n.dot((l + v).normalize());
+        let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+        let distribution = (2.0 + 1.0 / alpha) * sin_h.powf(1.0 / alpha) / (2.0 * PI);
+        let visibility = 1.0 / (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v));
+
+        self.color.sample(hit) * PI * distribution * visibility * n_dot_l
+    }
+}
@@ -0,0 +1,45 @@
+use super::{Material, Scattered, SurfaceHit};
+use crate::RenderSettings;
+use glam::Vec3;
+
+// Controls which side of the surface a material shades. Surfaces normally face out of the
+// object, so seen from inside a sphere, or from the back of an open mesh, they are unlit
+pub struct Sided {
+    pub base: Box<dyn Material>,
+    // Shade the back side like the front one, by turning the normal towards the viewer
+    pub double_sided: bool,
+    // Swap the sides, for objects that are meant to be seen from inside like a dome
+    pub flip_normals: bool
+}
+
+impl Material for Sided {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, v: Vec3, settings: &RenderSettings) -> Vec3 {
+        self.base.shade(hit, l, v, settings)
+    }
+
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.ambient(hit)
+    }
+
+    fn scatter(&self, hit: &SurfaceHit, direction: Vec3) -> Vec<Scattered> {
+        self.base.scatter(hit, direction)
+    }
+
+    fn emission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.emission(hit)
+    }
+
+    fn transmission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.transmission(hit)
+    }
+
+    fn orient(&self, hit: &mut SurfaceHit, direction: Vec3) {
+        if self.flip_normals {
+            hit.normal = -hit.normal;
+        }
+        if self.double_sided && hit.normal.dot(direction) > 0.0 {
+            hit.normal = -hit.normal;
+        }
+        self.base.orient(hit, direction);
+    }
+}
@@ -0,0 +1,71 @@
+use super::SurfaceHit;
+use crate::rng::hash_random;
+use glam::Vec3;
+
+// Where a material parameter comes from: a constant, or something that varies over the
+// surface and is looked up at each hit point. Constants convert with `into()`
+pub enum Texture<T> {
+    Constant(T),
+    // 3D checkerboard of cubes of the given size, the same pattern whatever the shape
+    Checker {
+        even: T,
+        odd: T,
+        size: f32
+    },
+    // Any function of the hit point, for procedural patterns
+    Procedural(Box<dyn Fn(&SurfaceHit) -> T + Send + Sync>)
+}
+
+impl<T: Copy> Texture<T> {
+    pub fn sample(&self, hit: &SurfaceHit) -> T {
+        match self {
+            Texture::Constant(value) => *value,
+            Texture::Checker { even, odd, size } => {
+                let cell = (hit.point / *size).floor();
+                if (cell.x + cell.y + cell.z).rem_euclid(2.0) < 1.0 { *even } else { *odd }
+            },
+            Texture::Procedural(f) => f(hit)
+        }
+    }
+}
+
+impl<T> From<T> for Texture<T> {
+    fn from(value: T) -> Self {
+        Texture::Constant(value)
+    }
+}
+
+// Colors are linear RGB, scalars whatever the parameter they drive expects
+pub type ColorTexture = Texture<Vec3>;
+pub type ScalarTexture = Texture<f32>;
+
+// Smooth pseudo random pattern in [0, 1] with features about 1 unit across, with a few octaves
+// of finer detail on top. Meant for masks and variation in procedural textures
+pub fn noise(p: Vec3) -> f32 {
+    let mut total = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+    for octave in 0..4 {
+        total += amplitude * value_noise(p * frequency, octave);
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    total / (1.0 - amplitude * 2.0)
+}
+
+// Random values at the corners of a unit lattice, smoothly interpolated in between
+fn value_noise(p: Vec3, seed: u32) -> f32 {
+    let cell = p.floor();
+    let f = p - cell;
+    // Smoothstep, so the pattern has no visible creases along the lattice
+    let t = f * f * (Vec3::splat(3.0) - 2.0 * f);
+    let corner = |x: f32, y: f32, z: f32| hash_random(cell + Vec3::new(x, y, z), seed);
+
+    let x00 = corner(0.0, 0.0, 0.0) + (corner(1.0, 0.0, 0.0) - corner(0.0, 0.0, 0.0)) * t.x;
+    let x10 = corner(0.0, 1.0, 0.0) + (corner(1.0, 1.0, 0.0) - corner(0.0, 1.0, 0.0)) * t.x;
+    let x01 = corner(0.0, 0.0, 1.0) + (corner(1.0, 0.0, 1.0) - corner(0.0, 0.0, 1.0)) * t.x;
+    let x11 = corner(0.0, 1.0, 1.0) + (corner(1.0, 1.0, 1.0) - corner(0.0, 1.0, 1.0)) * t.x;
+    let y0 = x00 + (x10 - x00) * t.y;
+    let y1 = x01 + (x11 - x01) * t.y;
+    y0 + (y1 - y0) * t.z
+}
@@ -0,0 +1,97 @@
+use super::{dispersion, ggx, reflect_ray, roughness_to_alpha, Material, Scattered, SurfaceHit};
+use crate::RenderSettings;
+use glam::Vec3;
+use std::f32::consts::PI;
+
+// The film is smooth, its highlight is as sharp as the GGX lobe allows
+const FILM_ROUGHNESS: f32 = 0.0;
+
+// Transparent film a few hundred nanometers thick over another material, like a soap bubble or
+// oil on water. Light reflected off the top and the bottom of the film interferes, which
+// reflects some wavelengths more than others depending on the thickness and the angle
+pub struct ThinFilm {
+    pub base: Box<dyn Material>,
+    // Thickness of the film in nanometers, the colors show up between about 100 and 1000
+    pub thickness: f32,
+    // Index of refraction of the film
+    pub ior: f32,
+    // Index of refraction of what's under the film, 1.0 when it's air like inside a bubble
+    pub substrate_ior: f32
+}
+
+impl ThinFilm {
+    // Fraction of the light the film reflects per channel, for light arriving at an angle with
+    // cosine `cos` (Airy's formula for the two interfaces, averaged over both polarizations)
+    fn reflectance(&self, cos: f32) -> Vec3 {
+        let cos_i = cos.clamp(0.0, 1.0);
+        let sin_t = (1.0 - cos_i * cos_i).sqrt() / self.ior;
+        let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
+        let sin_s = sin_t * self.ior / self.substrate_ior;
+        // Total internal reflection at the bottom of the film
+        if sin_s >= 1.0 {
+            return Vec3::ONE;
+        }
+        let cos_s = (1.0 - sin_s * sin_s).sqrt();
+
+        // Fresnel amplitudes at the top and the bottom of the film for both polarizations
+        let (n1, n2, n3) = (1.0, self.ior, self.substrate_ior);
+        let top_s = (n1 * cos_i - n2 * cos_t) / (n1 * cos_i + n2 * cos_t);
+        let top_p = (n2 * cos_i - n1 * cos_t) / (n2 * cos_i + n1 * cos_t);
+        let bottom_s = (n2 * cos_t - n3 * cos_s) / (n2 * cos_t + n3 * cos_s);
+        let bottom_p = (n3 * cos_t - n2 * cos_s) / (n3 * cos_t + n2 * cos_s);
+
+        let mut reflectance = Vec3::ZERO;
+        for channel in 0..3 {
+            let wavelength = dispersion::CHANNEL_WAVELENGTHS[channel] * 1000.0;
+            // Phase difference picked up going down and back up through the film
+            let phase = 4.0 * PI * self.ior * self.thickness * cos_t / wavelength;
+            let airy = |r1: f32, r2: f32| {
+                let cross = 2.0 * r1 * r2 * phase.cos();
+                (r1 * r1 + r2 * r2 + cross) / (1.0 + r1 * r1 * r2 * r2 + cross)
+            };
+            reflectance[channel] = 0.5 * (airy(top_s, bottom_s) + airy(top_p, bottom_p));
+        }
+        reflectance
+    }
+}
+
+impl Material for ThinFilm {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, v: Vec3, settings: &RenderSettings) -> Vec3 {
+        let n = hit.normal;
+        let h = (l.normalize() + v.normalize()).normalize();
+        let film = self.reflectance(v.normalize().dot(h)) * ggx::reflectance(n, l, v, roughness_to_alpha(FILM_ROUGHNESS));
+        // The light reflected by the film on the way in and out never reaches the base
+        let through = (Vec3::ONE - self.reflectance(n.dot(l.normalize()))) * (Vec3::ONE - self.reflectance(n.dot(v.normalize())));
+        self.base.shade(hit, l, v, settings) * through + film
+    }
+
+    // The view direction isn't known here, so the film is taken as seen head on
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.ambient(hit) * (Vec3::ONE - self.reflectance(1.0))
+    }
+
+    fn scatter(&self, hit: &SurfaceHit, direction: Vec3) -> Vec<Scattered> {
+        let v = -direction.normalize();
+        let film = self.reflectance(hit.normal.dot(v).abs());
+        let mut rays: Vec<Scattered> = self
+            .base
+            .scatter(hit, direction)
+            .into_iter()
+            .map(|ray| Scattered { weight: ray.weight * (Vec3::ONE - film), ..ray })
+            .collect();
+        rays.push(Scattered { direction: reflect_ray(v, hit.normal), weight: film, channel: None });
+        rays
+    }
+
+    fn emission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.emission(hit)
+    }
+
+    fn transmission(&self, hit: &SurfaceHit) -> Vec3 {
+        self.base.transmission(hit) * (Vec3::ONE - self.reflectance(1.0))
+    }
+
+    fn orient(&self, hit: &mut SurfaceHit, direction: Vec3) {
+        self.base.orient(hit, direction);
+    }
+}
@@ -0,0 +1,34 @@
+use super::{ColorTexture, Material, SurfaceHit};
+use crate::RenderSettings;
+use glam::Vec3;
+
+// Cel shading: flat color in a few bands of brightness instead of a smooth gradient, with an
+// optional ink line around the object like in a cartoon
+pub struct Toon {
+    pub color: ColorTexture,
+    // Number of brightness levels, the darkest one being unlit
+    pub bands: u32,
+    // Color of the outline, None for no outline
+    pub ink: Option<Vec3>
+}
+
+impl Material for Toon {
+    fn shade(&self, hit: &SurfaceHit, l: Vec3, _v: Vec3, _settings: &RenderSettings) -> Vec3 {
+        let n_dot_l = hit.normal.dot(l.normalize());
+        if n_dot_l <= 0.0 {
+            return Vec3::ZERO;
+        }
+        // Round the diffuse term up to the next band
+        let bands = self.bands.max(1) as f32;
+        let level = (n_dot_l * bands).ceil() / bands;
+        self.color.sample(hit) * level
+    }
+
+    fn ambient(&self, hit: &SurfaceHit) -> Vec3 {
+        self.color.sample(hit)
+    }
+
+    fn outline(&self) -> Option<Vec3> {
+        self.ink
+    }
+}
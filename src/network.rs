@@ -0,0 +1,301 @@
+// Rendering a frame on more than one machine. The one with the window, the coordinator, listens
+// for workers, and every worker builds the same scene from the same command line and connects to
+// it. Tiles of each pass are then handed out to the workers as well as to the threads of the
+// coordinator, and the light through their points sent back.
+//
+// The messages are plain little endian numbers over TCP. A job is the size of the canvas, the
+// tile, the pass and the camera, and which lights are on, since those can be toggled while the
+// window is open. The answer is the number of points traced, then each point and its light.
+
+use crate::camera::{Bokeh, Camera, Distortion, Lens, Projection};
+use crate::tiles::Tile;
+use glam::{Vec2, Vec3};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+// Longest side of a canvas a worker renders, a sanity check on jobs
+const MAX_SIDE: i32 = 1 << 15;
+
+pub struct Job {
+    pub width: i32,
+    pub height: i32,
+    pub tile: Tile,
+    // Side of the blocks of the pass, and of the first pass of the frame, whose points the ones
+    // after it don't trace again
+    pub block: i32,
+    pub coarsest: i32,
+    pub camera: Camera,
+    pub lights: Vec<bool>
+}
+
+impl Job {
+    pub fn write(&self, stream: &mut impl Write) -> io::Result<()> {
+        let mut bytes = vec![];
+        for value in [self.width, self.height, self.tile.x, self.tile.y, self.tile.width, self.tile.height, self.block, self.coarsest] {
+            bytes.extend(value.to_le_bytes());
+        }
+        let Camera { position, target, up, projection, distortion, shift, aperture, focus_distance, tilt, bokeh, shutter_open, shutter_close, velocity, near, far } = self.camera;
+        for value in [position, target, up].into_iter().flat_map(|vector| vector.to_array()) {
+            bytes.extend(value.to_le_bytes());
+        }
+        // Which projection, and its size
+        let (kind, size) = match projection {
+            Projection::Perspective { fov } => (0u32, fov),
+            Projection::Orthographic { width } => (1, width),
+            Projection::Fisheye { fov, lens: Lens::Equidistant } => (2, fov),
+            Projection::Fisheye { fov, lens: Lens::Equisolid } => (3, fov),
+            Projection::Panorama => (4, 0.0)
+        };
+        bytes.extend(kind.to_le_bytes());
+        bytes.extend(size.to_le_bytes());
+        let Distortion { k1, k2, k3, p1, p2 } = distortion;
+        for value in [k1, k2, k3, p1, p2, shift.x, shift.y] {
+            bytes.extend(value.to_le_bytes());
+        }
+        // The lens, its aperture having no sides when it's round
+        bytes.extend(aperture.to_le_bytes());
+        bytes.extend(focus_distance.to_le_bytes());
+        bytes.extend(tilt.x.to_le_bytes());
+        bytes.extend(tilt.y.to_le_bytes());
+        let sides = match bokeh {
+            Bokeh::Disc => 0,
+            Bokeh::Polygon { sides } => sides
+        };
+        bytes.extend(sides.to_le_bytes());
+        for value in [shutter_open, shutter_close].into_iter().chain(velocity.to_array()).chain([near, far]) {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend((self.lights.len() as u32).to_le_bytes());
+        bytes.extend(self.lights.iter().map(|&on| on as u8));
+        stream.write_all(&bytes)
+    }
+
+    // The worker has the same scene, so no more than `max_lights` lights can be on or off
+    pub fn read(stream: &mut impl Read, max_lights: usize) -> io::Result<Job> {
+        let mut numbers = [0; 8];
+        for number in &mut numbers {
+            *number = read_i32(stream)?;
+        }
+        let mut camera = [0.0; 9];
+        for value in &mut camera {
+            *value = read_f32(stream)?;
+        }
+        let projection = match (read_u32(stream)?, read_f32(stream)?) {
+            (0, fov) => Projection::Perspective { fov },
+            (1, width) => Projection::Orthographic { width },
+            (2, fov) => Projection::Fisheye { fov, lens: Lens::Equidistant },
+            (3, fov) => Projection::Fisheye { fov, lens: Lens::Equisolid },
+            (4, _) => Projection::Panorama,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown projection"))
+        };
+        let mut lens = [0.0; 7];
+        for value in &mut lens {
+            *value = read_f32(stream)?;
+        }
+        let [k1, k2, k3, p1, p2, shift_x, shift_y] = lens;
+        let (aperture, focus_distance) = (read_f32(stream)?, read_f32(stream)?);
+        let tilt = Vec2::new(read_f32(stream)?, read_f32(stream)?);
+        let bokeh = match read_u32(stream)? {
+            0 => Bokeh::Disc,
+            sides => Bokeh::Polygon { sides }
+        };
+        let (shutter_open, shutter_close) = (read_f32(stream)?, read_f32(stream)?);
+        let velocity = Vec3::new(read_f32(stream)?, read_f32(stream)?, read_f32(stream)?);
+        let (near, far) = (read_f32(stream)?, read_f32(stream)?);
+        let mut lights = vec![0; read_count(stream, max_lights, "too many lights")?];
+        stream.read_exact(&mut lights)?;
+        let [width, height, x, y, tile_width, tile_height, block, coarsest] = numbers;
+        // A peer that gets these wrong would have the worker trace points off the canvas, or
+        // what would take forever. The tile has to be on the canvas, which is centered on the
+        // origin like the tiles of `tiles::spiral`
+        let on_canvas = |start: i32, size: i32, side: i32| start >= -side / 2 && size >= 0 && start as i64 + size as i64 <= (side / 2) as i64;
+        let side = 1..=MAX_SIDE;
+        if !side.contains(&width) || !side.contains(&height) || !on_canvas(x, tile_width, width) || !on_canvas(y, tile_height, height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "tile off the canvas"));
+        }
+        if !side.contains(&block) || !(block..=MAX_SIDE).contains(&coarsest) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "wrong block size"));
+        }
+        Ok(Job {
+            width,
+            height,
+            tile: Tile { x, y, width: tile_width, height: tile_height },
+            block,
+            coarsest,
+            camera: Camera {
+                position: Vec3::from_slice(&camera[..3]),
+                target: Vec3::from_slice(&camera[3..6]),
+                up: Vec3::from_slice(&camera[6..]),
+                projection,
+                distortion: Distortion { k1, k2, k3, p1, p2 },
+                shift: Vec2::new(shift_x, shift_y),
+                aperture,
+                focus_distance,
+                tilt,
+                bokeh,
+                shutter_open,
+                shutter_close,
+                velocity,
+                near,
+                far
+            },
+            lights: lights.into_iter().map(|on| on != 0).collect()
+        })
+    }
+}
+
+// Canvas points traced for a job and the light through each
+pub fn write_points(stream: &mut impl Write, points: &[(i32, i32, Vec3)]) -> io::Result<()> {
+    let mut bytes = (points.len() as u32).to_le_bytes().to_vec();
+    for &(x, y, radiance) in points {
+        bytes.extend(x.to_le_bytes());
+        bytes.extend(y.to_le_bytes());
+        for value in radiance.to_array() {
+            bytes.extend(value.to_le_bytes());
+        }
+    }
+    stream.write_all(&bytes)
+}
+
+// No more than `max_points` of them, the points of the tile the job was for
+pub fn read_points(stream: &mut impl Read, max_points: usize) -> io::Result<Vec<(i32, i32, Vec3)>> {
+    let count = read_count(stream, max_points, "too many points")?;
+    (0..count)
+        .map(|_| Ok((read_i32(stream)?, read_i32(stream)?, Vec3::new(read_f32(stream)?, read_f32(stream)?, read_f32(stream)?))))
+        .collect()
+}
+
+// Sends the job to a worker and waits for its points
+pub fn trace_remotely(stream: &mut TcpStream, job: &Job) -> io::Result<Vec<(i32, i32, Vec3)>> {
+    job.write(stream)?;
+    read_answer(&mut io::BufReader::new(stream), job)
+}
+
+// The points of a worker for the job, which have to be points of its tile. A worker that
+// answers with others is as good as gone
+fn read_answer(stream: &mut impl Read, job: &Job) -> io::Result<Vec<(i32, i32, Vec3)>> {
+    let points = read_points(stream, job.tile.width.max(0) as usize * job.tile.height.max(0) as usize)?;
+    if points.iter().any(|&(x, y, _)| !job.tile.contains(x, y)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "point outside the tile"));
+    }
+    Ok(points)
+}
+
+// Workers that have connected so far, taken out while a pass uses them and put back after
+pub type Workers = Arc<Mutex<Vec<TcpStream>>>;
+
+// Starts listening for workers at the address, taking them in on a thread of its own from
+// then on
+pub fn listen(address: impl ToSocketAddrs) -> io::Result<Workers> {
+    let listener = TcpListener::bind(address)?;
+    println!("Listening for workers on {}", listener.local_addr()?);
+    let workers = Workers::default();
+    let accepted = workers.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            println!("Worker {} joined", stream.peer_addr().map_or(String::from("?"), |address| address.to_string()));
+            // Jobs and answers are small, they shouldn't wait to be sent with others
+            let _ = stream.set_nodelay(true);
+            accepted.lock().unwrap().push(stream);
+        }
+    });
+    Ok(workers)
+}
+
+fn read_u32(stream: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    stream.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// A length, which the peer could get wrong, checked before anything is allocated for it
+fn read_count(stream: &mut impl Read, max: usize, message: &str) -> io::Result<usize> {
+    let count = read_u32(stream)? as usize;
+    if count > max {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(count)
+}
+
+fn read_i32(stream: &mut impl Read) -> io::Result<i32> {
+    read_u32(stream).map(|value| value as i32)
+}
+
+fn read_f32(stream: &mut impl Read) -> io::Result<f32> {
+    read_u32(stream).map(f32::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(lights: usize) -> Job {
+        Job {
+            width: 64,
+            height: 48,
+            tile: Tile { x: 0, y: 0, width: 2, height: 2 },
+            block: 1,
+            coarsest: 8,
+            camera: Camera::new(Projection::Perspective { fov: 60.0 }),
+            lights: vec![true; lights]
+        }
+    }
+
+    fn bytes(job: &Job) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        job.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn lights() {
+        assert_eq!(Job::read(&mut bytes(&job(3)).as_slice(), 3).unwrap().lights, vec![true; 3]);
+        let error = Job::read(&mut bytes(&job(4)).as_slice(), 3).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tiles_on_the_canvas() {
+        // Tiles up to the edges of the 64 by 48 canvas, from -32 to 32 and -24 to 24
+        for tile in [Tile { x: -32, y: -24, width: 2, height: 2 }, Tile { x: 30, y: 22, width: 2, height: 2 }] {
+            assert!(Job::read(&mut bytes(&Job { tile, ..job(0) }).as_slice(), 0).is_ok());
+        }
+        let wrong = [
+            Job { tile: Tile { x: 31, y: 0, width: 2, height: 2 }, ..job(0) },
+            Job { tile: Tile { x: 0, y: -25, width: 2, height: 2 }, ..job(0) },
+            Job { tile: Tile { x: 0, y: 0, width: -2, height: 2 }, ..job(0) },
+            Job { tile: Tile { x: 0, y: 0, width: i32::MAX, height: 2 }, ..job(0) },
+            Job { width: 0, ..job(0) },
+            Job { height: i32::MAX, ..job(0) },
+            Job { block: 0, ..job(0) },
+            Job { block: 16, ..job(0) }
+        ];
+        for job in wrong {
+            let error = Job::read(&mut bytes(&job).as_slice(), 0).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn answers_only_from_the_tile() {
+        let job = job(0);
+        let mut bytes = Vec::new();
+        write_points(&mut bytes, &[(0, 0, Vec3::X), (1, 1, Vec3::Y)]).unwrap();
+        assert_eq!(read_answer(&mut bytes.as_slice(), &job).unwrap().len(), 2);
+        let mut bytes = Vec::new();
+        write_points(&mut bytes, &[(0, 0, Vec3::X), (2, 1, Vec3::Y)]).unwrap();
+        let error = read_answer(&mut bytes.as_slice(), &job).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn points() {
+        let points = vec![(1, 2, Vec3::X), (3, 4, Vec3::Y)];
+        let mut bytes = Vec::new();
+        write_points(&mut bytes, &points).unwrap();
+        assert_eq!(read_points(&mut bytes.as_slice(), 2).unwrap(), points);
+        let error = read_points(&mut bytes.as_slice(), 1).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,359 @@
+// Importer for Wavefront .obj models and the .mtl material libraries they use.
+//
+// Each object (`o`) or group (`g`) in the file becomes a mesh, with the positions, normals and
+// texture coordinates of its faces. Faces with more than three corners are split into a fan of
+// triangles, which is only right for convex ones. Materials become Phong materials, with the
+// diffuse color image (`map_Kd`) read when it's a .ppm file.
+
+use crate::image::Image;
+use crate::materials::{Material, MaterialId, Phong, Texture};
+use crate::shapes::Mesh;
+use glam::{Vec2, Vec3};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Reads the model at `path`, adding its materials to `materials`. Returns the name and mesh of
+// each object in it
+pub fn load(path: &str, materials: &mut Vec<Box<dyn Material>>) -> Result<Vec<(String, Mesh)>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&contents, path, materials)
+}
+
+// The model in `contents`, read from `path`, which names it and has the material libraries
+// next to it
+fn parse(contents: &str, path: &str, materials: &mut Vec<Box<dyn Material>>) -> Result<Vec<(String, Mesh)>, String> {
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut positions: Vec<Vec3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut uvs: Vec<Vec2> = vec![];
+    let mut library: HashMap<String, MaterialId> = HashMap::new();
+    let mut groups: Vec<Group> = vec![];
+    let mut current = Group::new(default_name(path));
+    let mut material: Option<MaterialId> = None;
+
+    for (number, line) in contents.lines().enumerate() {
+        let error = |message: &str| format!("{}:{}: {}", path, number + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue
+        };
+        let values: Vec<&str> = words.collect();
+        match keyword {
+            "v" => positions.push(vector(&values).ok_or_else(|| error("invalid vertex"))?),
+            "vn" => normals.push(vector(&values).ok_or_else(|| error("invalid normal"))?),
+            "vt" => {
+                // The optional third coordinate is for 3D textures
+                let u = values.first().and_then(|u| u.parse().ok()).ok_or_else(|| error("invalid texture coordinate"))?;
+                let v = values.get(1).and_then(|v| v.parse().ok()).unwrap_or(0.0);
+                uvs.push(Vec2::new(u, v));
+            },
+            "f" => {
+                let counts = (positions.len(), uvs.len(), normals.len());
+                let corners: Option<Vec<Corner>> = values.iter().map(|corner| parse_corner(corner, counts)).collect();
+                let corners = corners.ok_or_else(|| error("invalid face"))?;
+                if corners.len() < 3 {
+                    return Err(error("face with less than three corners"));
+                }
+                let indices: Vec<usize> = corners.into_iter().map(|corner| current.vertex(corner)).collect();
+                for k in 1..indices.len() - 1 {
+                    current.triangles.push([indices[0], indices[k], indices[k + 1]]);
+                    current.materials.push(material);
+                }
+            },
+            "o" | "g" => {
+                let name = values.join(" ");
+                let previous = std::mem::replace(&mut current, Group::new(name));
+                if !previous.triangles.is_empty() {
+                    groups.push(previous);
+                }
+            },
+            "usemtl" => {
+                let name = values.join(" ");
+                material = Some(*library.get(&name).ok_or_else(|| error(&format!("unknown material {}", name)))?);
+            },
+            "mtllib" => {
+                for file in &values {
+                    let mtl = directory.join(file);
+                    load_materials(&mtl.to_string_lossy(), materials, &mut library)?;
+                }
+            },
+            // Smoothing groups, lines, points and the rest aren't supported
+            _ => {}
+        }
+    }
+    if !current.triangles.is_empty() {
+        groups.push(current);
+    }
+
+    // Faces that come before any material get a plain white one
+    let needs_default = groups.iter().any(|group| group.materials.iter().any(Option::is_none));
+    let uses_materials = groups.iter().any(|group| group.materials.iter().any(Option::is_some));
+    let default = if needs_default && uses_materials {
+        materials.push(Box::new(Phong::default()));
+        Some(materials.len() - 1)
+    } else {
+        None
+    };
+
+    let meshes = groups
+        .into_iter()
+        .map(|group| {
+            let vertices = group.corners.iter().map(|corner| positions[corner.position]).collect();
+            // Materials only when the file has some, otherwise the one of the object is used
+            let face_materials = if uses_materials {
+                group.materials.iter().map(|material| material.or(default).unwrap()).collect()
+            } else {
+                vec![]
+            };
+            let mut mesh = Mesh::new(vertices, group.triangles, face_materials);
+            // Normals and texture coordinates are only used when every corner has them
+            if group.corners.iter().all(|corner| corner.normal.is_some()) {
+                mesh = mesh.with_normals(group.corners.iter().map(|corner| normals[corner.normal.unwrap()].normalize()).collect());
+            }
+            if group.corners.iter().all(|corner| corner.uv.is_some()) {
+                mesh = mesh.with_uvs(group.corners.iter().map(|corner| uvs[corner.uv.unwrap()]).collect());
+            }
+            (group.name, mesh)
+        })
+        .collect();
+    Ok(meshes)
+}
+
+// Indices into the positions, texture coordinates and normals read so far
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>
+}
+
+// An object or group of the file being read. Face corners that are the same become a single
+// vertex
+struct Group {
+    name: String,
+    corners: Vec<Corner>,
+    vertices: HashMap<Corner, usize>,
+    triangles: Vec<[usize; 3]>,
+    materials: Vec<Option<MaterialId>>
+}
+
+impl Group {
+    fn new(name: String) -> Group {
+        Group {
+            name,
+            corners: vec![],
+            vertices: HashMap::new(),
+            triangles: vec![],
+            materials: vec![]
+        }
+    }
+
+    // Index of the vertex for the corner, added if it's new
+    fn vertex(&mut self, corner: Corner) -> usize {
+        let corners = &mut self.corners;
+        *self.vertices.entry(corner).or_insert_with(|| {
+            corners.push(corner);
+            corners.len() - 1
+        })
+    }
+}
+
+// The file name without the extension
+fn default_name(path: &str) -> String {
+    Path::new(path).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn vector(values: &[&str]) -> Option<Vec3> {
+    let mut numbers = values.iter().map(|value| value.parse::<f32>().ok());
+    Some(Vec3::new(numbers.next()??, numbers.next()??, numbers.next()??))
+}
+
+// A face corner `position/uv/normal`, where the last two are optional. Indices start at 1, and
+// negative ones count back from the last element read
+fn parse_corner(corner: &str, (positions, uvs, normals): (usize, usize, usize)) -> Option<Corner> {
+    let resolve = |index: &str, count: usize| -> Option<usize> {
+        let index: i64 = index.parse().ok()?;
+        let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+        (0..count as i64).contains(&resolved).then_some(resolved as usize)
+    };
+    let mut parts = corner.split('/');
+    let position = resolve(parts.next()?, positions)?;
+    let uv = match parts.next() {
+        Some(uv) if !uv.is_empty() => Some(resolve(uv, uvs)?),
+        _ => None
+    };
+    let normal = match parts.next() {
+        Some(normal) if !normal.is_empty() => Some(resolve(normal, normals)?),
+        _ => None
+    };
+    Some(Corner { position, uv, normal })
+}
+
+// Reads the materials of a .mtl file into `materials`, remembering their ids by name
+fn load_materials(path: &str, materials: &mut Vec<Box<dyn Material>>, library: &mut HashMap<String, MaterialId>) -> Result<(), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let mut definitions: Vec<(String, Definition)> = vec![];
+    for (number, line) in contents.lines().enumerate() {
+        let error = |message: &str| format!("{}:{}: {}", path, number + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue
+        };
+        let values: Vec<&str> = words.collect();
+        if keyword == "newmtl" {
+            definitions.push((values.join(" "), Definition::default()));
+            continue;
+        }
+        let definition = match definitions.last_mut() {
+            Some((_, definition)) => definition,
+            None => return Err(error("material property before newmtl"))
+        };
+        let number = || values.first().and_then(|value| value.parse::<f32>().ok()).ok_or_else(|| error("invalid number"));
+        let color = || color(&values).ok_or_else(|| error("invalid color"));
+        match keyword {
+            "Kd" => definition.diffuse = color()?,
+            "Ks" => definition.specular = color()?,
+            "Ke" => definition.emission = color()?,
+            "Ns" => definition.shininess = number()?,
+            "Ni" => definition.ior = number()?,
+            "d" => definition.opacity = number()?,
+            "Tr" => definition.opacity = 1.0 - number()?,
+            "illum" => definition.illumination = number()? as u32,
+            // The file name is the last word, after any options
+            "map_Kd" => {
+                let file = values.last().ok_or_else(|| error("missing texture file"))?;
+                definition.diffuse_map = Some(directory.join(file).to_string_lossy().to_string());
+            },
+            _ => {}
+        }
+    }
+
+    for (name, definition) in definitions {
+        materials.push(Box::new(definition.to_phong()?));
+        library.insert(name, materials.len() - 1);
+    }
+    Ok(())
+}
+
+// Colors are either three values, or a single one for gray
+fn color(values: &[&str]) -> Option<Vec3> {
+    match values.len() {
+        1 => Some(Vec3::splat(values[0].parse().ok()?)),
+        _ => vector(values)
+    }
+}
+
+// The properties of a material in a .mtl file we make use of
+struct Definition {
+    diffuse: Vec3,
+    diffuse_map: Option<String>,
+    specular: Vec3,
+    shininess: f32,
+    emission: Vec3,
+    ior: f32,
+    opacity: f32,
+    // Illumination model, 3 and up add mirror reflections
+    illumination: u32
+}
+
+impl Default for Definition {
+    fn default() -> Self {
+        Definition {
+            diffuse: Vec3::splat(0.8),
+            diffuse_map: None,
+            specular: Vec3::ZERO,
+            shininess: 0.0,
+            emission: Vec3::ZERO,
+            ior: 1.0,
+            opacity: 1.0,
+            illumination: 2
+        }
+    }
+}
+
+impl Definition {
+    fn to_phong(&self) -> Result<Phong, String> {
+        let color = match &self.diffuse_map {
+            // Other image formats are left out, they fall back to the diffuse color
+            Some(file) if file.to_lowercase().ends_with(".ppm") => {
+                let image = Image::load(file)?;
+                let tint = self.diffuse;
+                Texture::Procedural(Box::new(move |hit| image.sample(hit.uv) * tint))
+            },
+            _ => self.diffuse.into()
+        };
+        let shiny = self.specular != Vec3::ZERO && self.shininess > 0.0;
+        let mirror = matches!(self.illumination, 3 | 5 | 7);
+        Ok(Phong {
+            color,
+            specular: shiny.then(|| self.shininess.into()),
+            reflective: if mirror { self.specular.max_element() } else { 0.0 },
+            transmission: Vec3::splat(1.0 - self.opacity.clamp(0.0, 1.0)),
+            ior: self.ior,
+            emission: self.emission,
+            ..Phong::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_text(text: &str) -> Result<Vec<(String, Mesh)>, String> {
+        parse(text, "test.obj", &mut vec![])
+    }
+
+    const SQUARE: &str = "
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+    ";
+
+    #[test]
+    fn positive_indices_start_at_one() {
+        let meshes = parse_text(&format!("{}f 1 2 3", SQUARE)).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].0, "test");
+        assert_eq!(meshes[0].1.triangle_corners(), vec![[Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0)]]);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let meshes = parse_text(&format!("{}f -4 -3 -1\nv 5 5 5\nf -1 -2 -3", SQUARE)).unwrap();
+        let corners = meshes[0].1.triangle_corners();
+        assert_eq!(corners[0], [Vec3::ZERO, Vec3::X, Vec3::Y]);
+        assert_eq!(corners[1], [Vec3::splat(5.0), Vec3::Y, Vec3::new(1.0, 1.0, 0.0)]);
+    }
+
+    #[test]
+    fn negative_indices_work_for_uvs_and_normals_too() {
+        let text = format!("{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 2\nf 1/-3/-1 2/-2/-1 3/-1/-1", SQUARE);
+        let mesh = &parse_text(&text).unwrap()[0].1;
+        assert_eq!(mesh.vertex_uvs(), &[Vec2::ZERO, Vec2::X, Vec2::ONE]);
+        assert_eq!(mesh.vertex_normals(), &[Vec3::Z; 3]);
+    }
+
+    #[test]
+    fn indices_out_of_range_are_errors() {
+        assert!(parse_text(&format!("{}f 1 2 5", SQUARE)).is_err());
+        assert!(parse_text(&format!("{}f -5 1 2", SQUARE)).is_err());
+        assert!(parse_text(&format!("{}f 0 1 2", SQUARE)).is_err());
+    }
+
+    #[test]
+    fn faces_are_split_into_fans_and_groups_into_meshes() {
+        let meshes = parse_text(&format!("{}o first\nf 1 2 3 4\ng second\nf 4 3 2", SQUARE)).unwrap();
+        let names: Vec<&str> = meshes.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(meshes[0].1.triangle_corners().len(), 2);
+        assert_eq!(meshes[1].1.triangle_corners().len(), 1);
+    }
+}
@@ -0,0 +1,371 @@
+// Importer for Stanford PLY files, what 3D scanners and point cloud tools usually write, in the
+// ASCII and both binary flavours.
+//
+// Files with faces are read as meshes, and any file can be read as a cloud of its vertices.
+//
+// Vertices bring their positions and, when they have them, their normals, texture coordinates
+// and colors. Faces with more than three corners are split into a fan of triangles. Other
+// elements, like edges or the materials some tools add, are skipped.
+
+use crate::shapes::{Mesh, PointCloud};
+use glam::{Vec2, Vec3};
+use std::fs;
+
+pub fn load(path: &str) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&bytes).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Loads just the vertices, for scans that are a cloud of points with no faces between them.
+// Every point is drawn as a ball of the given radius, or of its own when the vertices have one
+pub fn load_points(path: &str, radius: f32) -> Result<PointCloud, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_points(&bytes, radius).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Mesh, String> {
+    let body = read(bytes)?;
+    if body.faces.is_empty() {
+        return Err(String::from("the file has no faces"));
+    }
+    let positions = body.positions()?;
+    let mut triangles = vec![];
+    for face in &body.faces {
+        if face.len() < 3 || face.iter().any(|&index| index >= positions.len()) {
+            return Err(String::from("invalid face"));
+        }
+        for k in 1..face.len() - 1 {
+            triangles.push([face[0], face[k], face[k + 1]]);
+        }
+    }
+
+    let mut mesh = Mesh::new(positions, triangles, vec![]);
+    if let Some(normals) = body.columns(&["nx", "ny", "nz"]) {
+        mesh = mesh.with_normals(normals.into_iter().map(|n| Vec3::new(n[0], n[1], n[2]).normalize_or_zero()).collect());
+    }
+    // Texture coordinates go by several names
+    let uvs = [["u", "v"], ["s", "t"], ["texture_u", "texture_v"]].iter().find_map(|names| body.columns(names));
+    if let Some(uvs) = uvs {
+        mesh = mesh.with_uvs(uvs.into_iter().map(|uv| Vec2::new(uv[0], uv[1])).collect());
+    }
+    if let Some(colors) = body.colors() {
+        mesh = mesh.with_colors(colors);
+    }
+    Ok(mesh)
+}
+
+pub fn parse_points(bytes: &[u8], radius: f32) -> Result<PointCloud, String> {
+    let body = read(bytes)?;
+    let mut cloud = PointCloud::new(body.positions()?, radius);
+    // Splatting tools store the size of each point as its radius
+    if let Some(radii) = body.columns(&["radius"]) {
+        cloud = cloud.with_radii(radii.into_iter().map(|r| r[0]).collect());
+    }
+    if let Some(colors) = body.colors() {
+        cloud = cloud.with_colors(colors);
+    }
+    Ok(cloud)
+}
+
+// Everything in the file that's used: the vertices, with the values of their scalar
+// properties in order, and the corners of the faces
+struct Body {
+    header: Header,
+    vertices: Vec<Vec<f64>>,
+    vertex_properties: Vec<String>,
+    faces: Vec<Vec<usize>>
+}
+
+impl Body {
+    // Vertex properties by name, all of them or none
+    fn columns(&self, names: &[&str]) -> Option<Vec<Vec<f32>>> {
+        let columns: Vec<usize> = names.iter().map(|name| self.vertex_properties.iter().position(|property| property == name)).collect::<Option<_>>()?;
+        Some(self.vertices.iter().map(|vertex| columns.iter().map(|&column| vertex[column] as f32).collect()).collect())
+    }
+
+    fn positions(&self) -> Result<Vec<Vec3>, String> {
+        let positions = self.columns(&["x", "y", "z"]).ok_or("vertices without positions")?;
+        Ok(positions.into_iter().map(|p| Vec3::new(p[0], p[1], p[2])).collect())
+    }
+
+    // Colors are usually bytes, but can be floats in [0, 1] too
+    fn colors(&self) -> Option<Vec<Vec3>> {
+        let colors = self.columns(&["red", "green", "blue"])?;
+        let vertex = self.header.elements.iter().find(|element| element.name == "vertex").unwrap();
+        let red = vertex.properties.iter().find(|property| property.name == "red").map(|property| &property.kind);
+        let scale = match red {
+            Some(Kind::Scalar(Scalar::Float | Scalar::Double)) => 1.0,
+            Some(Kind::Scalar(Scalar::UShort)) => 1.0 / 65535.0,
+            _ => 1.0 / 255.0
+        };
+        Some(colors.into_iter().map(|c| Vec3::new(c[0], c[1], c[2]) * scale).collect())
+    }
+}
+
+fn read(bytes: &[u8]) -> Result<Body, String> {
+    let (header, body_start) = parse_header(bytes)?;
+    let mut reader = Reader { bytes, position: body_start, format: header.format };
+
+    let mut vertices: Vec<Vec<f64>> = vec![];
+    let mut vertex_properties: Vec<String> = vec![];
+    let mut faces: Vec<Vec<usize>> = vec![];
+    for element in &header.elements {
+        for _ in 0..element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            let mut indices = None;
+            for property in &element.properties {
+                match &property.kind {
+                    Kind::Scalar(scalar) => values.push(reader.value(*scalar)?),
+                    Kind::List { count, item } => {
+                        let count = reader.value(*count)?;
+                        if count < 0.0 {
+                            return Err(String::from("negative list length"));
+                        }
+                        let items: Result<Vec<f64>, String> = (0..count as usize).map(|_| reader.value(*item)).collect();
+                        if element.name == "face" && (property.name == "vertex_indices" || property.name == "vertex_index") {
+                            let items = items?;
+                            if items.iter().any(|&index| index < 0.0 || index.fract() != 0.0) {
+                                return Err(String::from("invalid face"));
+                            }
+                            indices = Some(items.into_iter().map(|index| index as usize).collect());
+                        } else {
+                            items?;
+                        }
+                    }
+                }
+            }
+            match element.name.as_str() {
+                "vertex" => vertices.push(values),
+                "face" => faces.push(indices.ok_or("face without vertex indices")?),
+                _ => {}
+            }
+        }
+        if element.name == "vertex" {
+            vertex_properties = element.properties.iter().filter(|property| matches!(property.kind, Kind::Scalar(_))).map(|property| property.name.clone()).collect();
+        }
+    }
+    Ok(Body { header, vertices, vertex_properties, faces })
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian
+}
+
+#[derive(Copy, Clone)]
+enum Scalar {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, String> {
+        match name {
+            "char" | "int8" => Ok(Scalar::Char),
+            "uchar" | "uint8" => Ok(Scalar::UChar),
+            "short" | "int16" => Ok(Scalar::Short),
+            "ushort" | "uint16" => Ok(Scalar::UShort),
+            "int" | "int32" => Ok(Scalar::Int),
+            "uint" | "uint32" => Ok(Scalar::UInt),
+            "float" | "float32" => Ok(Scalar::Float),
+            "double" | "float64" => Ok(Scalar::Double),
+            _ => Err(format!("unknown property type {}", name))
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Char | Scalar::UChar => 1,
+            Scalar::Short | Scalar::UShort => 2,
+            Scalar::Int | Scalar::UInt | Scalar::Float => 4,
+            Scalar::Double => 8
+        }
+    }
+}
+
+enum Kind {
+    Scalar(Scalar),
+    // A length followed by that many items
+    List { count: Scalar, item: Scalar }
+}
+
+struct Property {
+    name: String,
+    kind: Kind
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>
+}
+
+// The header, and where the data starts after it
+fn parse_header(bytes: &[u8]) -> Result<(Header, usize), String> {
+    let end = bytes.windows(10).position(|window| window == b"end_header").ok_or("not a PLY file")?;
+    let body_start = bytes[end..].iter().position(|&byte| byte == b'\n').map_or(bytes.len(), |newline| end + newline + 1);
+    let text = String::from_utf8_lossy(&bytes[..end]);
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(String::from("not a PLY file"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::LittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(Format::BigEndian),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| format!("invalid count of {}", name))?,
+                properties: vec![]
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                let kind = Kind::List { count: Scalar::parse(count)?, item: Scalar::parse(item)? };
+                element.properties.push(Property { name: name.to_string(), kind });
+            },
+            ["property", scalar, name] => {
+                let element = elements.last_mut().ok_or("property before any element")?;
+                element.properties.push(Property { name: name.to_string(), kind: Kind::Scalar(Scalar::parse(scalar)?) });
+            },
+            // Comments and object info
+            _ => {}
+        }
+    }
+    let format = format.ok_or("missing format")?;
+    Ok((Header { format, elements }, body_start))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    format: Format
+}
+
+impl<'a> Reader<'a> {
+    fn value(&mut self, scalar: Scalar) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            return self.word()?.parse().map_err(|_| String::from("invalid number"));
+        }
+        let size = scalar.size();
+        let bytes = self.bytes.get(self.position..self.position + size).ok_or("unexpected end of file")?;
+        self.position += size;
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            buffer[..size].reverse();
+        }
+        Ok(match scalar {
+            Scalar::Char => buffer[0] as i8 as f64,
+            Scalar::UChar => buffer[0] as f64,
+            Scalar::Short => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            Scalar::UShort => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            Scalar::Int => i32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::UInt => u32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::Float => f32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::Double => f64::from_le_bytes(buffer)
+        })
+    }
+
+    fn word(&mut self) -> Result<&str, String> {
+        while self.bytes.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(String::from("unexpected end of file"));
+        }
+        std::str::from_utf8(&self.bytes[start..self.position]).map_err(|_| String::from("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 3
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    const CORNERS: [Vec3; 3] = [Vec3::ZERO, Vec3::X, Vec3::Y];
+    const COLORS: [[u8; 3]; 3] = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+
+    // The triangle with a color per corner, in a binary file of the given byte order
+    fn binary(format: &str, indices: [i32; 3], to_bytes: fn(u32) -> [u8; 4]) -> Vec<u8> {
+        let mut bytes = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+        for (corner, color) in CORNERS.iter().zip(COLORS) {
+            for value in corner.to_array() {
+                bytes.extend(to_bytes(value.to_bits()));
+            }
+            bytes.extend(color);
+        }
+        bytes.push(3);
+        for index in indices {
+            bytes.extend(to_bytes(index as u32));
+        }
+        bytes
+    }
+
+    fn check_triangle(mesh: &Mesh) {
+        assert_eq!(mesh.triangle_corners(), vec![CORNERS]);
+        assert_eq!(mesh.vertex_colors(), &[Vec3::X, Vec3::Y, Vec3::Z]);
+    }
+
+    #[test]
+    fn reads_little_endian_files_with_colors() {
+        check_triangle(&parse(&binary("binary_little_endian", [0, 1, 2], u32::to_le_bytes)).unwrap());
+    }
+
+    #[test]
+    fn reads_big_endian_files_with_colors() {
+        check_triangle(&parse(&binary("binary_big_endian", [0, 1, 2], u32::to_be_bytes)).unwrap());
+    }
+
+    #[test]
+    fn reads_ascii_files_with_colors() {
+        let text = format!("ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 0 255 0\n0 1 0 0 0 255\n3 0 1 2\n", HEADER);
+        check_triangle(&parse(text.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn negative_and_fractional_indices_are_errors() {
+        assert!(parse(&binary("binary_little_endian", [0, -1, 2], u32::to_le_bytes)).is_err());
+        let text = format!("ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 0 255 0\n0 1 0 0 0 255\n3 0 1.5 2\n", HEADER);
+        assert!(parse(text.as_bytes()).is_err());
+        assert!(parse(&binary("binary_little_endian", [0, 1, 3], u32::to_le_bytes)).is_err());
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = binary("binary_big_endian", [0, 1, 2], u32::to_be_bytes);
+        assert!(parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+// Where the time of a render goes, for `--profile`. The parts of the pipeline mark what they're
+// doing with a span, and the time of each thread is charged to the innermost span it's in, so
+// the shading of a hit isn't counted again in the rays traced for it, nor those in the shading.
+// The times of all the threads are added up, so they're CPU time rather than time on the clock.
+//
+// Nothing is timed until it's turned on, spans only cost checking that when it's off.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone)]
+pub enum Phase {
+    // Sorting the objects into the acceleration structure
+    Build,
+    // Finding what rays hit
+    Traversal,
+    // Working out the light coming back from the hits
+    Shading,
+    // Getting the frame on the window
+    Present
+}
+
+const PHASES: [Phase; 4] = [Phase::Build, Phase::Traversal, Phase::Shading, Phase::Present];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// Nanoseconds spent in each phase, and how many spans of it there were
+static TIMES: [AtomicU64; 4] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+static COUNTS: [AtomicU64; 4] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+thread_local! {
+    // Phases of the spans the thread is in, innermost last, and when the time of the innermost
+    // one was last charged
+    static STACK: RefCell<(Vec<Phase>, Option<Instant>)> = const { RefCell::new((vec![], None)) };
+}
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Build => "scene build",
+            Phase::Traversal => "traversal",
+            Phase::Shading => "shading",
+            Phase::Present => "present"
+        }
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Marks the time until the span is dropped as spent in `phase`
+pub struct Span {
+    timed: bool
+}
+
+pub fn span(phase: Phase) -> Span {
+    if !enabled() {
+        return Span { timed: false };
+    }
+    STACK.with(|stack| {
+        let (phases, since) = &mut *stack.borrow_mut();
+        charge(phases.last().copied(), since);
+        phases.push(phase);
+    });
+    COUNTS[phase as usize].fetch_add(1, Ordering::Relaxed);
+    Span { timed: true }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if self.timed {
+            STACK.with(|stack| {
+                let (phases, since) = &mut *stack.borrow_mut();
+                charge(phases.pop(), since);
+            });
+        }
+    }
+}
+
+// Adds the time since the last charge to `phase`, if the thread was in a span
+fn charge(phase: Option<Phase>, since: &mut Option<Instant>) {
+    let now = Instant::now();
+    if let (Some(phase), Some(since)) = (phase, *since) {
+        TIMES[phase as usize].fetch_add((now - since).as_nanos() as u64, Ordering::Relaxed);
+    }
+    *since = Some(now);
+}
+
+// Prints how the time was split between the phases since the last report, and starts over.
+// Nothing unless profiling is on
+pub fn report() {
+    if !enabled() {
+        return;
+    }
+    let times = PHASES.map(|phase| Duration::from_nanos(TIMES[phase as usize].swap(0, Ordering::Relaxed)));
+    let counts = PHASES.map(|phase| COUNTS[phase as usize].swap(0, Ordering::Relaxed));
+    let total: Duration = times.iter().sum();
+    println!("Profile, CPU time over all threads:");
+    for (k, phase) in PHASES.iter().enumerate() {
+        let share = if total.is_zero() { 0.0 } else { 100.0 * times[k].as_secs_f64() / total.as_secs_f64() };
+        println!("  {:<12} {:>10.2?} {:>5.1}% in {} spans", phase.name(), times[k], share, counts[k]);
+    }
+}
@@ -0,0 +1,69 @@
+// Small random number utilities for sampling.
+//
+// Renders have to come out the same every time, so instead of being seeded from the clock the
+// generators are seeded from the shading point: a point always makes the same choices, and
+// neighbouring points make different ones.
+
+use glam::Vec3;
+use std::f32::consts::PI;
+
+// PCG32 by Melissa O'Neill, small and fast with good enough statistics for sampling
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    // Generator for the point, `stream` tells apart the different things sampled there
+    pub fn from_point(p: Vec3, stream: u32) -> Rng {
+        let seed = (point_hash(p, stream) as u64) << 32 | point_hash(p, !stream) as u64;
+        Rng::new(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+// Pseudo random number in [0, 1) that only depends on the point and `k`, so a point always
+// makes the same choices and the image doesn't change between renders
+pub fn hash_random(p: Vec3, k: u32) -> f32 {
+    (point_hash(p, k) >> 8) as f32 / (1 << 24) as f32
+}
+
+fn point_hash(p: Vec3, k: u32) -> u32 {
+    let mut h = p.x.to_bits() ^ p.y.to_bits().rotate_left(11) ^ p.z.to_bits().rotate_left(22) ^ k.wrapping_mul(0x9e3779b9);
+    // Integer hash by Chris Wellons
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    h
+}
+
+// Maps a point of the unit square to a direction in the hemisphere around the unit normal `n`,
+// with a density proportional to the cosine to the normal (Malley's method: uniform in the
+// disc, projected up onto the hemisphere). Averaging the light seen along such directions
+// gives the diffuse lighting without weighting each one
+pub fn cosine_hemisphere(n: Vec3, u1: f32, u2: f32) -> Vec3 {
+    let (tangent, bitangent) = n.any_orthonormal_pair();
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    r * phi.cos() * tangent + r * phi.sin() * bitangent + (1.0 - u1).max(0.0).sqrt() * n
+}
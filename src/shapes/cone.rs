@@ -0,0 +1,91 @@
+use super::{axis_frame, closest, disc_intersection, paired_crossings, Intersection, Shape, Span};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+// Part of the surface, in the intersections
+const SIDE: usize = 0;
+const BOTTOM: usize = 1;
+
+// Cone standing on the disc of `radius` around `base`, narrowing to a point `height` up along
+// `axis`. Without a cap the bottom is open
+pub struct Cone {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool
+}
+
+impl Shape for Cone {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let axis = self.axis.normalize();
+        let offset = origin - self.base;
+        // The side is where the distance to the axis is `slope` times the height left to the tip
+        let slope = self.radius / self.height;
+        let o = offset - axis * offset.dot(axis);
+        let d = direction - axis * direction.dot(axis);
+        let w = self.height - offset.dot(axis);
+        let dy = direction.dot(axis);
+        let k = slope * slope;
+        let a = d.dot(d) - k * dy * dy;
+        let b = 2.0 * (o.dot(d) + k * w * dy);
+        let c = o.dot(o) - k * w * w;
+        let (t1, t2) = if a.abs() > 1e-12 {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant >= 0.0 {
+                ((-b - discriminant.sqrt()) / (2.0 * a), (-b + discriminant.sqrt()) / (2.0 * a))
+            } else {
+                (f32::MAX, f32::MAX)
+            }
+        } else if b.abs() > 1e-12 {
+            // Rays parallel to the side only cross it once
+            (-c / b, f32::MAX)
+        } else {
+            (f32::MAX, f32::MAX)
+        };
+        // The equation describes a double cone, only the half between the base and the tip counts
+        let on_side = |t: f32| {
+            let y = (offset + t * direction).dot(axis);
+            (0.0..=self.height).contains(&y).then_some(t)
+        };
+
+        let mut candidates = vec![(on_side(t1), SIDE), (on_side(t2), SIDE)];
+        if self.capped {
+            candidates.push((disc_intersection(origin, direction, self.base, axis, self.radius), BOTTOM));
+        }
+        closest(candidates, min_t, max_t)
+    }
+
+    // On the side the tangent goes around the axis, and the texture wraps around it once from
+    // the bottom to the tip. The cap is mapped flat
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let axis = self.axis.normalize();
+        let (u, v) = axis_frame(axis);
+        let offset = point - self.base;
+        let across = offset - axis * offset.dot(axis);
+        let outwards = across.normalize_or_zero();
+        if part == BOTTOM {
+            let uv = Vec2::new(across.dot(u), across.dot(v)) / (2.0 * self.radius) + 0.5;
+            return SurfaceHit { point, normal: -axis, tangent: u, uv, color: None };
+        }
+        // Leans towards the tip as much as the side leans towards the axis
+        let normal = (outwards * self.height + axis * self.radius).normalize();
+        let angle = across.dot(v).atan2(across.dot(u));
+        let uv = Vec2::new(0.5 + angle / (2.0 * PI), offset.dot(axis) / self.height);
+        SurfaceHit { point, normal, tangent: axis.cross(outwards), uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let center = self.base + self.axis.normalize() * self.height / 2.0;
+        Some((center, (self.radius * self.radius + self.height * self.height / 4.0).sqrt()))
+    }
+
+    // Without caps there's no inside
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        if !self.capped {
+            return vec![];
+        }
+        paired_crossings(self, origin, direction)
+    }
+}
@@ -0,0 +1,177 @@
+use super::{enclosing, Intersection, Shape, Span};
+use crate::materials::{MaterialId, SurfaceHit};
+use glam::Vec3;
+
+// How the solids of the two shapes are put together
+#[derive(Copy, Clone)]
+pub enum Operation {
+    // Inside either of them
+    Union,
+    // Inside both of them
+    Intersection,
+    // Inside the first one but not the second, which carves it out
+    Difference
+}
+
+impl Operation {
+    fn inside(self, left: bool, right: bool) -> bool {
+        match self {
+            Operation::Union => left || right,
+            Operation::Intersection => left && right,
+            Operation::Difference => left && !right
+        }
+    }
+}
+
+// Solid made by combining two others, found by walking along the stretches of the ray inside
+// each of them. Both need to be closed surfaces, and can be combinations themselves.
+//
+// The parts of the combination are the parts of the shapes they come from, doubled, plus 1
+// for the right one
+pub struct Csg {
+    pub operation: Operation,
+    pub left: Box<dyn Shape>,
+    pub right: Box<dyn Shape>
+}
+
+impl Csg {
+    // The shape a part comes from, and its part in that shape
+    fn child(&self, part: usize) -> (&dyn Shape, usize) {
+        let shape = match part % 2 {
+            0 => self.left.as_ref(),
+            _ => self.right.as_ref()
+        };
+        (shape, part / 2)
+    }
+}
+
+impl Shape for Csg {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        self.spans(origin, direction)
+            .into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|crossing| min_t < crossing.t && crossing.t < max_t)
+    }
+
+    // The surface of the shape it comes from, turned inside out where the right one carves
+    // into the left one
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let (child, child_part) = self.child(part);
+        let mut hit = child.surface_hit(point, child_part);
+        if matches!(self.operation, Operation::Difference) && part % 2 == 1 {
+            hit.normal = -hit.normal;
+        }
+        hit
+    }
+
+    fn material(&self, part: usize) -> Option<MaterialId> {
+        let (child, child_part) = self.child(part);
+        child.material(child_part)
+    }
+
+    fn material_parts(&self) -> Vec<usize> {
+        let left = self.left.material_parts().into_iter().map(|part| 2 * part);
+        left.chain(self.right.material_parts().into_iter().map(|part| 2 * part + 1)).collect()
+    }
+
+    // Whatever is left of the first one can't be bigger than it, and what's inside both can't be
+    // bigger than the second one
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        match self.operation {
+            Operation::Union => Some(enclosing(self.left.bounding_sphere()?, self.right.bounding_sphere()?)),
+            Operation::Intersection => self.right.bounding_sphere().or_else(|| self.left.bounding_sphere()),
+            Operation::Difference => self.left.bounding_sphere()
+        }
+    }
+
+    // Goes through where the ray goes in and out of each shape in order, keeping track of
+    // whether it's inside each one, and so inside the combination
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        let tag = |crossing: Intersection, side: usize| Intersection { t: crossing.t, part: crossing.part * 2 + side };
+        let mut events = vec![];
+        for (side, shape) in [self.left.as_ref(), self.right.as_ref()].into_iter().enumerate() {
+            for span in shape.spans(origin, direction) {
+                events.push((tag(span.enter, side), side, true));
+                events.push((tag(span.exit, side), side, false));
+            }
+        }
+        events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+        let mut inside = [false, false];
+        let mut enter = None;
+        let mut spans = vec![];
+        for (crossing, side, entering) in events {
+            let was_inside = self.operation.inside(inside[0], inside[1]);
+            inside[side] = entering;
+            match (was_inside, self.operation.inside(inside[0], inside[1])) {
+                (false, true) => enter = Some(crossing),
+                (true, false) => spans.extend(enter.take().map(|enter| Span { enter, exit: crossing })),
+                _ => {}
+            }
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{Cuboid, Sphere};
+
+    // A unit sphere at the origin combined with a box
+    fn combined(operation: Operation, min: Vec3, max: Vec3) -> Csg {
+        Csg {
+            operation,
+            left: Box::new(Sphere { center: Vec3::ZERO, radius: 1.0 }),
+            right: Box::new(Cuboid { min, max })
+        }
+    }
+
+    // Where each stretch inside the solid goes in and comes out, along X from x = -5
+    fn along_x(csg: &Csg) -> Vec<(f32, f32)> {
+        csg.spans(Vec3::new(-5.0, 0.0, 0.0), Vec3::X).into_iter().map(|span| (span.enter.t, span.exit.t)).collect()
+    }
+
+    fn assert_spans(spans: Vec<(f32, f32)>, expected: &[(f32, f32)]) {
+        assert_eq!(spans.len(), expected.len(), "{:?}", spans);
+        for (&(enter, exit), &(expected_enter, expected_exit)) in spans.iter().zip(expected) {
+            assert!((enter - expected_enter).abs() < 1e-5 && (exit - expected_exit).abs() < 1e-5, "{:?}", spans);
+        }
+    }
+
+    #[test]
+    fn union() {
+        let csg = combined(Operation::Union, Vec3::new(0.0, -2.0, -2.0), Vec3::splat(2.0));
+        assert_spans(along_x(&csg), &[(4.0, 7.0)]);
+    }
+
+    #[test]
+    fn intersection() {
+        let csg = combined(Operation::Intersection, Vec3::new(0.0, -2.0, -2.0), Vec3::splat(2.0));
+        assert_spans(along_x(&csg), &[(5.0, 6.0)]);
+    }
+
+    #[test]
+    fn difference() {
+        let csg = combined(Operation::Difference, Vec3::splat(-0.5), Vec3::splat(0.5));
+        assert_spans(along_x(&csg), &[(4.0, 4.5), (5.5, 6.0)]);
+        // The walls of the hole are hit, and nothing is left in between
+        let origin = Vec3::new(0.0, 0.0, -5.0);
+        assert!(csg.intersect(origin, Vec3::Z, 4.1, 5.9).is_some_and(|hit| (hit.t - 4.5).abs() < 1e-5));
+        assert!(csg.intersect(origin, Vec3::Z, 4.6, 5.4).is_none());
+    }
+
+    #[test]
+    fn carved_surface_faces_into_the_hole() {
+        let csg = combined(Operation::Difference, Vec3::splat(-0.5), Vec3::splat(0.5));
+        let spans = csg.spans(Vec3::new(-5.0, 0.0, 0.0), Vec3::X);
+        // Going out of what's left into the hole, on the surface of the box
+        let exit = spans[0].exit;
+        assert_eq!(exit.part % 2, 1);
+        let hit = csg.surface_hit(Vec3::new(-0.5, 0.0, 0.0), exit.part);
+        assert!(hit.normal.distance(Vec3::X) < 1e-5, "{}", hit.normal);
+        // The sphere keeps its own normal
+        let hit = csg.surface_hit(Vec3::new(-1.0, 0.0, 0.0), spans[0].enter.part);
+        assert!(hit.normal.distance(-Vec3::X) < 1e-5, "{}", hit.normal);
+    }
+}
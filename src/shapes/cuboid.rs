@@ -0,0 +1,68 @@
+use super::{Intersection, Shape, Span};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+
+// Box between the corners `min` and `max`, with its faces along the axes. The parts are the
+// faces, 0 to 2 the ones facing -X, -Y and -Z and 3 to 5 the ones facing +X, +Y and +Z
+pub struct Cuboid {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Cuboid {
+    // Where the ray is between each pair of opposite faces, and the faces it goes in and out
+    // through, following the slab method. None when it misses the box
+    fn slabs(&self, origin: Vec3, direction: Vec3) -> Option<Span> {
+        let mut enter = Intersection { t: f32::NEG_INFINITY, part: 0 };
+        let mut exit = Intersection { t: f32::INFINITY, part: 0 };
+        for axis in 0..3 {
+            if direction[axis].abs() < 1e-12 {
+                // Parallel to the faces, either always between them or never
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let to_min = (self.min[axis] - origin[axis]) / direction[axis];
+            let to_max = (self.max[axis] - origin[axis]) / direction[axis];
+            let (near, far) = if to_min < to_max { ((to_min, axis), (to_max, axis + 3)) } else { ((to_max, axis + 3), (to_min, axis)) };
+            if near.0 > enter.t {
+                enter = Intersection { t: near.0, part: near.1 };
+            }
+            if far.0 < exit.t {
+                exit = Intersection { t: far.0, part: far.1 };
+            }
+        }
+        (enter.t <= exit.t).then_some(Span { enter, exit })
+    }
+}
+
+impl Shape for Cuboid {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let span = self.slabs(origin, direction)?;
+        [span.enter, span.exit].into_iter().find(|crossing| min_t < crossing.t && crossing.t < max_t)
+    }
+
+    // Each face is mapped flat, the whole image once across it
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let axis = part % 3;
+        let sign = if part < 3 { -1.0 } else { 1.0 };
+        let mut normal = Vec3::ZERO;
+        normal[axis] = sign;
+        // The next axis round goes along the face, and the one after that across it
+        let (along, across) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut tangent = Vec3::ZERO;
+        tangent[along] = 1.0;
+        let position = (point - self.min) / (self.max - self.min);
+        let uv = Vec2::new(position[along], position[across]);
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        Some(((self.min + self.max) / 2.0, self.min.distance(self.max) / 2.0))
+    }
+
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        self.slabs(origin, direction).into_iter().collect()
+    }
+}
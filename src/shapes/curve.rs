@@ -0,0 +1,115 @@
+// Thin curves like hair, blades of grass and wires: a cubic Bézier swept by a width that can
+// change along it.
+//
+// The curve is cut into straight pieces, and each one is a ribbon turned to face the ray, as
+// wide as the curve there. Hits are pushed back from the middle of the ribbon onto the round
+// tube around it, which is all a curve a few pixels wide needs to look like one.
+
+use super::{enclosing, Intersection, Shape, Span};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+
+pub struct Curve {
+    // Ends of the straight pieces, and how far the surface is from the curve at each
+    points: Vec<Vec3>,
+    radii: Vec<f32>,
+    bounds: (Vec3, f32)
+}
+
+impl Curve {
+    // Bézier curve through the first and last of the control points, cut into `segments`
+    // pieces. The widths are spread evenly from the start to the end of the curve, with
+    // straight changes between them, like a hair thinning to its tip
+    pub fn new(controls: [Vec3; 4], widths: &[f32], segments: usize) -> Curve {
+        assert!(!widths.is_empty(), "at least one width");
+        let segments = segments.max(1);
+        let width = |u: f32| {
+            let x = u * (widths.len() - 1) as f32;
+            let k = (x as usize).min(widths.len() - 1);
+            let next = widths[(k + 1).min(widths.len() - 1)];
+            widths[k] + (next - widths[k]) * (x - k as f32)
+        };
+        let (points, radii): (Vec<Vec3>, Vec<f32>) = (0..=segments)
+            .map(|k| {
+                let u = k as f32 / segments as f32;
+                let w = 1.0 - u;
+                let [a, b, c, d] = controls;
+                let point = a * (w * w * w) + b * (3.0 * w * w * u) + c * (3.0 * w * u * u) + d * (u * u * u);
+                (point, width(u) / 2.0)
+            })
+            .unzip();
+        let bounds = points.iter().copied().zip(radii.iter().copied()).reduce(enclosing).unwrap();
+        Curve { points, radii, bounds }
+    }
+
+    // Where along the piece the point is closest to the curve, from 0 at its start to 1 at
+    // its end
+    fn along(&self, piece: usize, point: Vec3) -> f32 {
+        let (a, b) = (self.points[piece], self.points[piece + 1]);
+        let ab = b - a;
+        ((point - a).dot(ab) / ab.length_squared().max(1e-12)).clamp(0.0, 1.0)
+    }
+}
+
+impl Shape for Curve {
+    // The ray crosses the ribbon of a piece where it comes closest to it, if that's within
+    // the width of the curve there
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let (center, radius) = self.bounds;
+        let co = origin - center;
+        let a = direction.dot(direction);
+        let b = co.dot(direction);
+        if b * b - a * (co.dot(co) - radius * radius) < 0.0 {
+            return None;
+        }
+
+        let mut closest: Option<Intersection> = None;
+        for piece in 0..self.points.len() - 1 {
+            let (start, end) = (self.points[piece], self.points[piece + 1]);
+            let e = end - start;
+            let w = origin - start;
+            let (ab, c) = (direction.dot(e), e.dot(e));
+            let determinant = a * c - ab * ab;
+            // Seen end on, the neighbouring pieces cover it
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+            let (d, f) = (direction.dot(w), e.dot(w));
+            let s = ((a * f - ab * d) / determinant).clamp(0.0, 1.0);
+            let t = (s * ab - d) / a;
+            let distance_squared = (w + t * direction - s * e).length_squared();
+            let r = self.radii[piece] + (self.radii[piece + 1] - self.radii[piece]) * s;
+            if distance_squared > r * r {
+                continue;
+            }
+            // Onto the near side of the tube
+            let t = t - (r * r - distance_squared).sqrt() / a.sqrt();
+            if min_t < t && t < closest.map_or(max_t, |hit| hit.t) {
+                closest = Some(Intersection { t, part: piece });
+            }
+        }
+        closest
+    }
+
+    // The normal points away from the curve, the tangent along it like the fibers of hair.
+    // The texture goes from 0 at the start to 1 at the end along `u`
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let s = self.along(part, point);
+        let (a, b) = (self.points[part], self.points[part + 1]);
+        let tangent = (b - a).normalize_or_zero();
+        let away = point - (a + (b - a) * s);
+        let across = away - tangent * away.dot(tangent);
+        let normal = if across.length_squared() > 1e-12 { across.normalize() } else { tangent.any_orthonormal_vector() };
+        let u = (part as f32 + s) / (self.points.len() - 1) as f32;
+        SurfaceHit { point, normal, tangent, uv: Vec2::new(u, 0.5), color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        Some(self.bounds)
+    }
+
+    // Too thin to have an inside worth cutting into
+    fn spans(&self, _origin: Vec3, _direction: Vec3) -> Vec<Span> {
+        vec![]
+    }
+}
@@ -0,0 +1,86 @@
+use super::{axis_frame, closest, disc_intersection, paired_crossings, Intersection, Shape, Span};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+// Part of the surface, in the intersections
+const SIDE: usize = 0;
+const BOTTOM: usize = 1;
+const TOP: usize = 2;
+
+// Cylinder standing on the disc around `base`, going up `height` along `axis`. Without caps
+// it's an open tube
+pub struct Cylinder {
+    pub base: Vec3,
+    pub axis: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub capped: bool
+}
+
+impl Shape for Cylinder {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let axis = self.axis.normalize();
+        let offset = origin - self.base;
+        // The side is where the distance to the axis is the radius, only looking across it
+        let o = offset - axis * offset.dot(axis);
+        let d = direction - axis * direction.dot(axis);
+        let a = d.dot(d);
+        let b = 2.0 * o.dot(d);
+        let c = o.dot(o) - self.radius * self.radius;
+        let discriminant = b * b - 4.0 * a * c;
+        let (t1, t2) = if a > 1e-12 && discriminant >= 0.0 {
+            ((-b - discriminant.sqrt()) / (2.0 * a), (-b + discriminant.sqrt()) / (2.0 * a))
+        } else {
+            (f32::MAX, f32::MAX)
+        };
+        // Only between the ends
+        let on_side = |t: f32| {
+            let y = (offset + t * direction).dot(axis);
+            (0.0..=self.height).contains(&y).then_some(t)
+        };
+
+        let mut candidates = vec![(on_side(t1), SIDE), (on_side(t2), SIDE)];
+        if self.capped {
+            let top = self.base + axis * self.height;
+            candidates.push((disc_intersection(origin, direction, self.base, axis, self.radius), BOTTOM));
+            candidates.push((disc_intersection(origin, direction, top, axis, self.radius), TOP));
+        }
+        closest(candidates, min_t, max_t)
+    }
+
+    // On the side the tangent goes around the axis, and the texture wraps around it once from
+    // the bottom to the top. Caps are mapped flat
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let axis = self.axis.normalize();
+        let (u, v) = axis_frame(axis);
+        let offset = point - self.base;
+        let across = offset - axis * offset.dot(axis);
+        let normal = match part {
+            BOTTOM => -axis,
+            TOP => axis,
+            _ => across.normalize_or_zero()
+        };
+        let (tangent, uv) = match part {
+            SIDE => {
+                let angle = across.dot(v).atan2(across.dot(u));
+                (axis.cross(normal), Vec2::new(0.5 + angle / (2.0 * PI), offset.dot(axis) / self.height))
+            },
+            _ => (u, Vec2::new(across.dot(u), across.dot(v)) / (2.0 * self.radius) + 0.5)
+        };
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let center = self.base + self.axis.normalize() * self.height / 2.0;
+        Some((center, (self.radius * self.radius + self.height * self.height / 4.0).sqrt()))
+    }
+
+    // Without caps there's no inside
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        if !self.capped {
+            return vec![];
+        }
+        paired_crossings(self, origin, direction)
+    }
+}
@@ -0,0 +1,47 @@
+use super::{axis_frame, Intersection, Shape, Span};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+
+// Flat disc of `outer_radius` around `center`, facing `normal`. With an inner radius it's a
+// ring with a hole in the middle, like a washer
+pub struct Disc {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub inner_radius: f32,
+    pub outer_radius: f32
+}
+
+impl Shape for Disc {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let denominator = self.normal.dot(direction);
+        // Rays parallel to the disc never reach it
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(self.center - origin) / denominator;
+        if !(min_t < t && t < max_t) {
+            return None;
+        }
+        let distance_squared = (origin + t * direction).distance_squared(self.center);
+        let inside = self.inner_radius * self.inner_radius <= distance_squared && distance_squared <= self.outer_radius * self.outer_radius;
+        inside.then_some(Intersection { t, part: 0 })
+    }
+
+    // Mapped flat like the caps of a cylinder, the texture filling the square around the disc
+    fn surface_hit(&self, point: Vec3, _part: usize) -> SurfaceHit {
+        let normal = self.normal.normalize();
+        let (u, v) = axis_frame(normal);
+        let offset = point - self.center;
+        let uv = Vec2::new(offset.dot(u), offset.dot(v)) / (2.0 * self.outer_radius) + 0.5;
+        SurfaceHit { point, normal, tangent: u, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        Some((self.center, self.outer_radius))
+    }
+
+    // Flat, so there's no inside
+    fn spans(&self, _origin: Vec3, _direction: Vec3) -> Vec<Span> {
+        vec![]
+    }
+}
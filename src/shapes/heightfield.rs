@@ -0,0 +1,177 @@
+use super::mesh::{barycentric, triangle_intersection};
+use super::{Intersection, Shape};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+
+// Terrain over a grid of heights, stretching `size.x` along X and `size.z` along Z from the
+// corner at `corner`, rising up to `size.y` above it. Each cell of the grid is split into two
+// triangles, and the parts are the triangles, two per cell row by row
+pub struct Heightfield {
+    corner: Vec3,
+    size: Vec3,
+    columns: usize,
+    rows: usize,
+    // Height above the corner at each grid point, row by row, and the normal there
+    heights: Vec<f32>,
+    normals: Vec<Vec3>,
+    // Lowest and highest point, to skip rays passing over or under it
+    min_height: f32,
+    max_height: f32
+}
+
+impl Heightfield {
+    // Grid of `columns` by `rows` cells with the heights given by the function, in [0, 1], at
+    // the texture coordinates of each grid point. An image or a noise function make good ones
+    pub fn from_fn(corner: Vec3, size: Vec3, columns: usize, rows: usize, height: impl Fn(Vec2) -> f32) -> Heightfield {
+        assert!(columns > 0 && rows > 0, "at least one cell");
+        let mut heights = Vec::with_capacity((columns + 1) * (rows + 1));
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let uv = Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+                heights.push(height(uv) * size.y);
+            }
+        }
+        let min_height = heights.iter().copied().fold(f32::MAX, f32::min);
+        let max_height = heights.iter().copied().fold(f32::MIN, f32::max);
+        let mut heightfield = Heightfield { corner, size, columns, rows, heights, normals: vec![], min_height, max_height };
+
+        // Smooth normals from the slope between the neighbours of each point
+        let (width, depth) = heightfield.cell_size();
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let height = |column: usize, row: usize| heightfield.heights[row * (columns + 1) + column];
+                let (left, right) = (column.saturating_sub(1), (column + 1).min(columns));
+                let (back, front) = (row.saturating_sub(1), (row + 1).min(rows));
+                let dx = (height(right, row) - height(left, row)) / ((right - left) as f32 * width);
+                let dz = (height(column, front) - height(column, back)) / ((front - back) as f32 * depth);
+                heightfield.normals.push(Vec3::new(-dx, 1.0, -dz).normalize());
+            }
+        }
+        heightfield
+    }
+
+    fn cell_size(&self) -> (f32, f32) {
+        (self.size.x / self.columns as f32, self.size.z / self.rows as f32)
+    }
+
+    fn index(&self, column: usize, row: usize) -> usize {
+        row * (self.columns + 1) + column
+    }
+
+    fn point(&self, column: usize, row: usize) -> Vec3 {
+        let (width, depth) = self.cell_size();
+        self.corner + Vec3::new(column as f32 * width, self.heights[self.index(column, row)], row as f32 * depth)
+    }
+
+    // Grid points at the corners of each triangle, facing up
+    fn triangle(&self, part: usize) -> [(usize, usize); 3] {
+        let cell = part / 2;
+        let (column, row) = (cell % self.columns, cell / self.columns);
+        if part % 2 == 1 {
+            [(column, row), (column, row + 1), (column + 1, row + 1)]
+        } else {
+            [(column, row), (column + 1, row + 1), (column + 1, row)]
+        }
+    }
+
+    // Where the ray is inside the box around the terrain, by the slab method
+    fn box_span(&self, origin: Vec3, direction: Vec3) -> Option<(f32, f32)> {
+        let min = self.corner + Vec3::new(0.0, self.min_height, 0.0);
+        let max = self.corner + Vec3::new(self.size.x, self.max_height, self.size.z);
+        let (mut enter, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+        for axis in 0..3 {
+            if direction[axis].abs() < 1e-12 {
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let to_min = (min[axis] - origin[axis]) / direction[axis];
+            let to_max = (max[axis] - origin[axis]) / direction[axis];
+            enter = enter.max(to_min.min(to_max));
+            exit = exit.min(to_min.max(to_max));
+        }
+        (enter <= exit).then_some((enter, exit))
+    }
+}
+
+impl Shape for Heightfield {
+    // Walks the cells under the ray in the order it crosses them, like drawing a line on the
+    // grid with a 2D DDA, and tries the two triangles of each. The first cell with a hit has
+    // the closest one, since the triangles don't stick out of their cells
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let (enter, exit) = self.box_span(origin, direction)?;
+        let (start, end) = (enter.max(min_t), exit.min(max_t));
+        if start > end {
+            return None;
+        }
+
+        let (width, depth) = self.cell_size();
+        let p = origin + start * direction - self.corner;
+        let mut column = ((p.x / width).floor().max(0.0) as usize).min(self.columns - 1);
+        let mut row = ((p.z / depth).floor().max(0.0) as usize).min(self.rows - 1);
+
+        // Along each axis: which way the ray goes through the cells, the `t` where it crosses
+        // into the next one, and how much `t` it takes to cross a whole cell
+        let axis = |position: f32, direction: f32, cell: usize, size: f32| -> (isize, f32, f32) {
+            if direction > 0.0 {
+                (1, start + ((cell + 1) as f32 * size - position) / direction, size / direction)
+            } else if direction < 0.0 {
+                (-1, start + (cell as f32 * size - position) / direction, -size / direction)
+            } else {
+                (0, f32::INFINITY, f32::INFINITY)
+            }
+        };
+        let (step_x, mut next_x, delta_x) = axis(p.x, direction.x, column, width);
+        let (step_z, mut next_z, delta_z) = axis(p.z, direction.z, row, depth);
+
+        loop {
+            let cell = row * self.columns + column;
+            let hit = [cell * 2, cell * 2 + 1]
+                .into_iter()
+                .filter_map(|part| {
+                    let [a, b, c] = self.triangle(part).map(|(column, row)| self.point(column, row));
+                    let t = triangle_intersection(origin, direction, a, b, c)?;
+                    (min_t < t && t < max_t).then_some(Intersection { t, part })
+                })
+                .min_by(|a, b| a.t.total_cmp(&b.t));
+            if hit.is_some() {
+                return hit;
+            }
+
+            if next_x < next_z {
+                if next_x > end {
+                    return None;
+                }
+                next_x += delta_x;
+                column = column.checked_add_signed(step_x).filter(|&column| column < self.columns)?;
+            } else {
+                if next_z > end {
+                    return None;
+                }
+                next_z += delta_z;
+                row = row.checked_add_signed(step_z).filter(|&row| row < self.rows)?;
+            }
+        }
+    }
+
+    // Smooth shaded with the normals of the grid points. The tangent goes along X, and the
+    // texture is stretched once over the whole terrain
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let corners = self.triangle(part);
+        let [a, b, c] = corners.map(|(column, row)| self.point(column, row));
+        let weights = barycentric(point, a, b, c);
+        let [na, nb, nc] = corners.map(|(column, row)| self.normals[self.index(column, row)]);
+        let normal = (na * weights.x + nb * weights.y + nc * weights.z).normalize();
+        let tangent = (Vec3::X - normal * normal.x).normalize();
+        let offset = point - self.corner;
+        let uv = Vec2::new(offset.x / self.size.x, offset.z / self.size.z);
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let min = self.corner + Vec3::new(0.0, self.min_height, 0.0);
+        let max = self.corner + Vec3::new(self.size.x, self.max_height, self.size.z);
+        Some(((min + max) / 2.0, min.distance(max) / 2.0))
+    }
+}
@@ -0,0 +1,529 @@
+use super::{first_of_each, Intersection, Shape};
+use crate::bvh::{Aabb, Bvh, BvhOptions};
+#[cfg(feature = "embree")]
+use crate::embree::TriangleScene;
+use crate::materials::{MaterialId, ScalarTexture, SurfaceHit};
+use glam::{Mat4, Vec2, Vec3};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Surface made of triangles sharing their corners. Each triangle is three indices `a`, `b`, `c`
+// into the vertices, and its front is the side (b - a) x (c - a) points to
+pub struct Mesh {
+    vertices: Vec<Vec3>,
+    // Normal, texture coordinates and color of each vertex, or empty when the mesh doesn't
+    // have them
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    colors: Vec<Vec3>,
+    triangles: Vec<[usize; 3]>,
+    // Material of each triangle, or empty for the material of the object everywhere
+    materials: Vec<MaterialId>,
+    bounds: (Vec3, f32),
+    // Tree of boxes around the triangles, so rays don't try them all, and how it's built. It's
+    // built for the first ray, so the steps making a mesh out of another don't each build one
+    bvh: OnceLock<Bvh>,
+    bvh_options: BvhOptions,
+    // The same triangles in Embree, which answers the rays looking ahead of their origin in
+    // place of the BVH, built for the first ray too
+    #[cfg(feature = "embree")]
+    embree: OnceLock<TriangleScene>
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vec3>, triangles: Vec<[usize; 3]>, materials: Vec<MaterialId>) -> Mesh {
+        assert!(materials.is_empty() || materials.len() == triangles.len(), "one material per triangle");
+        assert!(triangles.iter().flatten().all(|&index| index < vertices.len()), "vertex index out of range");
+        let mut mesh = Mesh {
+            vertices,
+            normals: vec![],
+            uvs: vec![],
+            colors: vec![],
+            triangles,
+            materials,
+            bounds: (Vec3::ZERO, 0.0),
+            bvh: OnceLock::new(),
+            bvh_options: BvhOptions::default(),
+            #[cfg(feature = "embree")]
+            embree: OnceLock::new()
+        };
+        mesh.update_bounds();
+        mesh
+    }
+
+    // Shades the mesh smoothly by interpolating these normals, one per vertex, across the
+    // triangles instead of using the flat normal of each triangle
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Mesh {
+        assert_eq!(normals.len(), self.vertices.len(), "one normal per vertex");
+        self.normals = normals;
+        self
+    }
+
+    // Shades every triangle with its own flat normal, dropping the vertex normals
+    pub fn flat(mut self) -> Mesh {
+        self.normals = vec![];
+        self
+    }
+
+    // Works out vertex normals from the triangles around each vertex, so the mesh looks smooth.
+    // Triangles meeting at a sharper angle than `max_angle`, in radians, keep a crease between
+    // them. Corners in the same place count as the same vertex, even when each triangle has
+    // its own like in STL files. Vertices on a crease are split, one for each side of it
+    pub fn smoothed(self, max_angle: f32) -> Mesh {
+        // Normals of the triangles, longer the bigger they are so big ones weigh more
+        let faces: Vec<Vec3> = (0..self.triangles.len()).map(|triangle| {
+            let [a, b, c] = self.corners(triangle);
+            (b - a).cross(c - a)
+        }).collect();
+        let key = |v: Vec3| v.to_array().map(f32::to_bits);
+        let mut around: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+        for (triangle, indices) in self.triangles.iter().enumerate() {
+            for &index in indices {
+                around.entry(key(self.vertices[index])).or_default().push(triangle);
+            }
+        }
+
+        let min_cosine = max_angle.cos();
+        let mut mesh = Mesh { vertices: vec![], normals: vec![], uvs: vec![], colors: vec![], triangles: vec![], ..self };
+        // The new vertex for each old vertex and normal
+        let mut split: HashMap<(usize, [u32; 3]), usize> = HashMap::new();
+        for (triangle, indices) in self.triangles.iter().enumerate() {
+            let own = faces[triangle].normalize_or_zero();
+            let corners = indices.map(|index| {
+                let sum: Vec3 = around[&key(self.vertices[index])]
+                    .iter()
+                    .map(|&other| faces[other])
+                    .filter(|face| face.normalize_or_zero().dot(own) >= min_cosine)
+                    .sum();
+                let normal = if sum.length_squared() > 1e-20 { sum.normalize() } else { own };
+                *split.entry((index, key(normal))).or_insert_with(|| {
+                    mesh.vertices.push(self.vertices[index]);
+                    mesh.normals.push(normal);
+                    mesh.uvs.extend(self.uvs.get(index));
+                    mesh.colors.extend(self.colors.get(index));
+                    mesh.vertices.len() - 1
+                })
+            });
+            mesh.triangles.push(corners);
+        }
+        mesh
+    }
+
+    // Rounds off a coarse mesh by Loop subdivision, each level splitting every triangle into
+    // four and moving the corners towards the smooth surface the mesh is a cage for. Open edges
+    // stay on the curve through the border. Corners in the same place count as the same vertex,
+    // so seams in the texture coordinates don't tear apart. The result is shaded smooth, as the
+    // normals the mesh came with no longer fit it
+    pub fn subdivided(self, levels: u32) -> Mesh {
+        if levels == 0 {
+            return self;
+        }
+        let mut mesh = self;
+        for _ in 0..levels {
+            mesh = mesh.split(true);
+        }
+        mesh.smoothed(std::f32::consts::PI)
+    }
+
+    // Splits every triangle into four, as many times as it takes for no edge to be longer than
+    // `max_edge`, leaving the surface as it was. Gives displacement enough vertices to work with
+    pub fn tessellated(self, max_edge: f32) -> Mesh {
+        let longest = (0..self.triangles.len())
+            .map(|triangle| {
+                let [a, b, c] = self.corners(triangle);
+                a.distance(b).max(b.distance(c)).max(c.distance(a))
+            })
+            .fold(0.0, f32::max);
+        let levels = (longest / max_edge).log2().ceil().max(0.0) as u32;
+        let mut mesh = self;
+        for _ in 0..levels {
+            mesh = mesh.split(false);
+        }
+        mesh
+    }
+
+    // Pushes every vertex out along its normal by `scale` times the height the texture gives
+    // there, and shades the result smooth. The normals are worked out first when the mesh
+    // doesn't have them, so it's best tessellated finely beforehand
+    pub fn displaced(self, height: &ScalarTexture, scale: f32) -> Mesh {
+        let mesh = if self.normals.is_empty() { self.smoothed(std::f32::consts::PI) } else { self };
+        let vertices = (0..mesh.vertices.len())
+            .map(|index| {
+                let (point, normal) = (mesh.vertices[index], mesh.normals[index]);
+                let hit = SurfaceHit {
+                    point,
+                    normal,
+                    tangent: normal.any_orthonormal_vector(),
+                    uv: mesh.uvs.get(index).copied().unwrap_or(Vec2::ZERO),
+                    color: mesh.colors.get(index).copied()
+                };
+                point + normal * height.sample(&hit) * scale
+            })
+            .collect();
+        let mut mesh = Mesh { vertices, normals: vec![], ..mesh };
+        mesh.update_bounds();
+        mesh.smoothed(std::f32::consts::PI)
+    }
+
+    // Splits every triangle into four at the middle of its edges. When `smooth` the corners and
+    // the new vertices move by the rules of Loop subdivision, otherwise the vertex normals are
+    // blended onto the new vertices
+    fn split(self, smooth: bool) -> Mesh {
+        // The same position is the same vertex, so the surface is followed across seams
+        let key = |v: Vec3| v.to_array().map(f32::to_bits);
+        let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut positions: Vec<Vec3> = vec![];
+        let ids: Vec<usize> = self.vertices.iter().map(|&vertex| {
+            *welded.entry(key(vertex)).or_insert_with(|| {
+                positions.push(vertex);
+                positions.len() - 1
+            })
+        }).collect();
+
+        // The corner across from each edge, one for each triangle it's on
+        let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+        let mut opposite: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for &[a, b, c] in &self.triangles {
+            let [a, b, c] = [ids[a], ids[b], ids[c]];
+            opposite.entry(edge(a, b)).or_default().push(c);
+            opposite.entry(edge(b, c)).or_default().push(a);
+            opposite.entry(edge(c, a)).or_default().push(b);
+        }
+        let mut neighbours: Vec<Vec<usize>> = vec![vec![]; positions.len()];
+        let mut border: Vec<Vec<usize>> = vec![vec![]; positions.len()];
+        for (&(a, b), corners) in &opposite {
+            neighbours[a].push(b);
+            neighbours[b].push(a);
+            if corners.len() != 2 {
+                border[a].push(b);
+                border[b].push(a);
+            }
+        }
+
+        // Corners move towards their neighbours, with the weights of Warren. Those on an open
+        // edge only move along it, and where more than two open edges meet they stay put
+        let corner_positions: Vec<Vec3> = (0..positions.len()).map(|v| {
+            let p = positions[v];
+            if !smooth {
+                return p;
+            }
+            match border[v].len() {
+                0 => {
+                    let n = neighbours[v].len() as f32;
+                    let beta = if neighbours[v].len() > 3 { 3.0 / (8.0 * n) } else { 3.0 / 16.0 };
+                    p * (1.0 - n * beta) + neighbours[v].iter().map(|&w| positions[w]).sum::<Vec3>() * beta
+                },
+                2 => p * 0.75 + (positions[border[v][0]] + positions[border[v][1]]) * 0.125,
+                _ => p
+            }
+        }).collect();
+        // New vertices on the edges lean towards the corners across from them
+        let edge_position = |a: usize, b: usize| match opposite[&edge(a, b)][..] {
+            [c, d] if smooth => (positions[a] + positions[b]) * 0.375 + (positions[c] + positions[d]) * 0.125,
+            _ => (positions[a] + positions[b]) * 0.5
+        };
+
+        let mut mesh = Mesh {
+            vertices: ids.iter().map(|&id| corner_positions[id]).collect(),
+            normals: if smooth { vec![] } else { self.normals.clone() },
+            uvs: self.uvs.clone(),
+            colors: self.colors.clone(),
+            triangles: vec![],
+            materials: self.materials.iter().flat_map(|&material| [material; 4]).collect(),
+            ..self
+        };
+        // One new vertex per edge of the original vertices, not the welded ones, so each side of
+        // a seam keeps its own texture coordinates
+        let mut middles: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut middle = |mesh: &mut Mesh, a: usize, b: usize| {
+            *middles.entry(edge(a, b)).or_insert_with(|| {
+                mesh.vertices.push(edge_position(ids[a], ids[b]));
+                if !mesh.normals.is_empty() {
+                    mesh.normals.push((mesh.normals[a] + mesh.normals[b]).normalize_or_zero());
+                }
+                if !mesh.uvs.is_empty() {
+                    mesh.uvs.push((mesh.uvs[a] + mesh.uvs[b]) / 2.0);
+                }
+                if !mesh.colors.is_empty() {
+                    mesh.colors.push((mesh.colors[a] + mesh.colors[b]) / 2.0);
+                }
+                mesh.vertices.len() - 1
+            })
+        };
+        for &[a, b, c] in &self.triangles {
+            let (ab, bc, ca) = (middle(&mut mesh, a, b), middle(&mut mesh, b, c), middle(&mut mesh, c, a));
+            mesh.triangles.extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+        }
+        mesh.update_bounds();
+        mesh
+    }
+
+    // Texture coordinates of each vertex, interpolated across the triangles
+    pub fn with_uvs(mut self, uvs: Vec<Vec2>) -> Mesh {
+        assert_eq!(uvs.len(), self.vertices.len(), "one texture coordinate per vertex");
+        self.uvs = uvs;
+        self
+    }
+
+    // Linear RGB color of each vertex, blended across the triangles
+    pub fn with_colors(mut self, colors: Vec<Vec3>) -> Mesh {
+        assert_eq!(colors.len(), self.vertices.len(), "one color per vertex");
+        self.colors = colors;
+        self
+    }
+
+    // Moves, turns and scales the mesh by the matrix, for models made at whatever size and
+    // place. Mirroring turns the triangles around so their fronts stay on the same side
+    pub fn transformed(mut self, matrix: Mat4) -> Mesh {
+        for vertex in &mut self.vertices {
+            *vertex = matrix.transform_point3(*vertex);
+        }
+        // Normals only stay perpendicular to the surface with the inverse transpose
+        let normal_matrix = matrix.inverse().transpose();
+        for normal in &mut self.normals {
+            *normal = normal_matrix.transform_vector3(*normal).normalize_or_zero();
+        }
+        if matrix.determinant() < 0.0 {
+            for triangle in &mut self.triangles {
+                triangle.swap(1, 2);
+            }
+        }
+        self.update_bounds();
+        self
+    }
+
+    // Builds the tree of boxes around the triangles this way, from now on
+    pub fn with_bvh(mut self, options: BvhOptions) -> Mesh {
+        self.bvh_options = options;
+        self.update_bounds();
+        self
+    }
+
+    // After the vertices have moved, or the way the BVH is built has changed. The trees are
+    // built again when they're next needed
+    fn update_bounds(&mut self) {
+        self.bounds = bounding_sphere(&self.vertices);
+        self.bvh = OnceLock::new();
+        #[cfg(feature = "embree")]
+        {
+            self.embree = OnceLock::new();
+        }
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            let boxes: Vec<Aabb> = (0..self.triangles.len()).map(|triangle| Aabb::around_points(&self.corners(triangle))).collect();
+            Bvh::new(&boxes, self.bvh_options)
+        })
+    }
+
+    #[cfg(feature = "embree")]
+    fn embree(&self) -> &TriangleScene {
+        self.embree.get_or_init(|| TriangleScene::new(&self.vertices, &self.triangles))
+    }
+
+    fn corners(&self, triangle: usize) -> [Vec3; 3] {
+        self.triangles[triangle].map(|index| self.vertices[index])
+    }
+}
+
+// What the importers read, for their tests to look at
+#[cfg(test)]
+impl Mesh {
+    pub fn triangle_corners(&self) -> Vec<[Vec3; 3]> {
+        (0..self.triangles.len()).map(|triangle| self.corners(triangle)).collect()
+    }
+
+    pub fn vertex_normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn vertex_uvs(&self) -> &[Vec2] {
+        &self.uvs
+    }
+
+    pub fn vertex_colors(&self) -> &[Vec3] {
+        &self.colors
+    }
+}
+
+impl Shape for Mesh {
+    // Tries the triangles in the boxes the ray goes through, keeping the closest hit
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        #[cfg(feature = "embree")]
+        if min_t >= 0.0 {
+            return self.embree().intersect(origin, direction, min_t, max_t).map(|(triangle, t)| Intersection { t, part: triangle });
+        }
+        let mut closest = None;
+        self.bvh().traverse(origin, direction, min_t, max_t, |triangle, max_t| {
+            let [a, b, c] = self.corners(triangle);
+            let t = triangle_intersection(origin, direction, a, b, c).filter(|&t| min_t < t && t < max_t)?;
+            closest = Some(Intersection { t, part: triangle });
+            Some(t)
+        });
+        closest
+    }
+
+    // Any triangle in the way will do
+    fn occludes(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> bool {
+        #[cfg(feature = "embree")]
+        if min_t >= 0.0 {
+            return self.embree().occluded(origin, direction, min_t, max_t);
+        }
+        self.bvh().any_hit(origin, direction, min_t, max_t, |triangle| {
+            let [a, b, c] = self.corners(triangle);
+            triangle_intersection(origin, direction, a, b, c).is_some_and(|t| min_t < t && t < max_t)
+        })
+    }
+
+    // Flat shaded unless the mesh has vertex normals. The tangent follows the first edge of
+    // the triangle
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let [a, b, c] = self.corners(part);
+        let face_normal = (b - a).cross(c - a).normalize();
+        let weights = barycentric(point, a, b, c);
+        let [i, j, k] = self.triangles[part];
+
+        let normal = if self.normals.is_empty() {
+            face_normal
+        } else {
+            let interpolated = self.normals[i] * weights.x + self.normals[j] * weights.y + self.normals[k] * weights.z;
+            // Normals pointing all different ways can cancel out
+            if interpolated.length_squared() > 1e-12 { interpolated.normalize() } else { face_normal }
+        };
+        let edge = b - a;
+        let along = edge - normal * normal.dot(edge);
+        let tangent = if along.length_squared() > 1e-12 { along.normalize() } else { normal.any_orthonormal_vector() };
+        let uv = if self.uvs.is_empty() {
+            Vec2::new(weights.y, weights.z)
+        } else {
+            self.uvs[i] * weights.x + self.uvs[j] * weights.y + self.uvs[k] * weights.z
+        };
+        let color = (!self.colors.is_empty())
+            .then(|| self.colors[i] * weights.x + self.colors[j] * weights.y + self.colors[k] * weights.z);
+        SurfaceHit { point, normal, tangent, uv, color }
+    }
+
+    fn material(&self, part: usize) -> Option<MaterialId> {
+        self.materials.get(part).copied()
+    }
+
+    fn material_parts(&self) -> Vec<usize> {
+        first_of_each(&self.materials)
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        Some(self.bounds)
+    }
+}
+
+// Sphere around the box the points fit in, which is close enough to the smallest one
+fn bounding_sphere(points: &[Vec3]) -> (Vec3, f32) {
+    let min = points.iter().copied().reduce(Vec3::min).unwrap_or(Vec3::ZERO);
+    let max = points.iter().copied().reduce(Vec3::max).unwrap_or(Vec3::ZERO);
+    let center = (min + max) / 2.0;
+    let radius = points.iter().map(|&point| point.distance(center)).fold(0.0, f32::max);
+    (center, radius)
+}
+
+// Where the ray crosses the triangle `a`, `b`, `c` from either side, following Möller and
+// Trumbore. None when it misses it or runs parallel to it
+pub(super) fn triangle_intersection(origin: Vec3, direction: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = direction.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    // Barycentric coordinates of the point where the ray crosses the plane of the triangle
+    let ao = origin - a;
+    let u = ao.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = ao.cross(ab);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(ac.dot(q) * inverse)
+}
+
+// How much each corner of the triangle weighs in the point `p` on it
+pub(super) fn barycentric(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d00, d01, d11) = (ab.dot(ab), ab.dot(ac), ac.dot(ac));
+    let (d20, d21) = (ap.dot(ab), ap.dot(ac));
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator.abs() < 1e-20 {
+        return Vec3::new(1.0, 0.0, 0.0);
+    }
+    let v = (d11 * d20 - d01 * d21) / denominator;
+    let w = (d00 * d21 - d01 * d20) / denominator;
+    Vec3::new(1.0 - v - w, v, w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_part_for_each_material() {
+        let vertices = vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+        let triangles = vec![[0, 1, 2], [0, 2, 3], [0, 3, 1], [1, 3, 2]];
+        assert_eq!(Mesh::new(vertices.clone(), triangles.clone(), vec![]).material_parts(), vec![0]);
+        assert_eq!(Mesh::new(vertices, triangles, vec![4, 7, 4, 2]).material_parts(), vec![0, 1, 3]);
+    }
+
+    // Distance from the origin of the points of the mesh closest to it and furthest from it,
+    // the corners and the middles of the triangles
+    fn closest_and_furthest(mesh: &Mesh) -> (f32, f32) {
+        let distances: Vec<f32> = mesh
+            .triangle_corners()
+            .into_iter()
+            .flat_map(|[a, b, c]| [a.length(), b.length(), c.length(), ((a + b + c) / 3.0).length()])
+            .collect();
+        (distances.iter().copied().fold(f32::MAX, f32::min), distances.iter().copied().fold(0.0, f32::max))
+    }
+
+    #[test]
+    fn subdivision_rounds_off_closed_meshes() {
+        let vertices = vec![Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+        let triangles = vec![[0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4], [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5]];
+        let octahedron = Mesh::new(vertices, triangles, vec![1; 8]);
+        let (closest, furthest) = closest_and_furthest(&octahedron);
+        let subdivided = Mesh::new(octahedron.vertices.clone(), octahedron.triangles.clone(), vec![1; 8]).subdivided(2);
+        assert_eq!(subdivided.triangle_corners().len(), 8 * 16);
+        assert_eq!(subdivided.material(127), Some(1));
+        // Closer to a sphere, the corners pulled in a lot more than the middles of the faces
+        let (subdivided_closest, subdivided_furthest) = closest_and_furthest(&subdivided);
+        assert!(subdivided_furthest < furthest);
+        assert!(subdivided_closest / subdivided_furthest > 0.75 && closest / furthest < 0.6);
+    }
+
+    #[test]
+    fn subdivision_keeps_flat_open_meshes_flat() {
+        let vertices = vec![Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, 1.0)];
+        let square = Mesh::new(vertices, vec![[0, 2, 1], [0, 3, 2]], vec![]).subdivided(3);
+        assert_eq!(square.triangle_corners().len(), 2 * 64);
+        assert!(square.triangle_corners().into_iter().flatten().all(|corner| corner.y.abs() < 1e-6));
+        assert!(square.vertex_normals().iter().all(|normal| normal.distance(Vec3::Y) < 1e-5 || normal.distance(-Vec3::Y) < 1e-5));
+    }
+
+    // Embree answers this ray when it's there, rather than the BVH
+    #[cfg(not(feature = "embree"))]
+    #[test]
+    fn bvh_is_built_once_for_the_first_ray() {
+        let vertices = vec![Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)];
+        let mesh = Mesh::new(vertices, vec![[0, 1, 2]], vec![])
+            .tessellated(0.5)
+            .transformed(Mat4::from_translation(Vec3::Z))
+            .with_bvh(BvhOptions::default());
+        assert!(mesh.bvh.get().is_none());
+        let hit = mesh.intersect(Vec3::ZERO, Vec3::Z, 0.0, f32::MAX);
+        assert!(hit.is_some_and(|hit| (hit.t - 1.0).abs() < 1e-6));
+        assert!(mesh.bvh.get().is_some());
+    }
+}
@@ -0,0 +1,233 @@
+// The geometry of the objects in the scene.
+//
+// A shape answers where a ray first crosses its surface and what the surface looks like at that
+// point. What the surface is made of is up to the material of the object holding the shape.
+
+mod cone;
+mod csg;
+mod cuboid;
+mod curve;
+mod cylinder;
+mod disc;
+mod heightfield;
+mod mesh;
+mod plane;
+mod points;
+mod polynomial;
+mod quadric;
+pub mod sdf;
+mod sphere;
+mod spheres;
+mod torus;
+mod transformed;
+
+pub use cone::Cone;
+pub use csg::{Csg, Operation};
+pub use cuboid::Cuboid;
+pub use curve::Curve;
+pub use cylinder::Cylinder;
+pub use disc::Disc;
+pub use heightfield::Heightfield;
+pub use mesh::Mesh;
+pub use plane::Plane;
+pub use points::PointCloud;
+pub use quadric::Quadric;
+pub use sphere::Sphere;
+pub use spheres::Spheres;
+pub use torus::Torus;
+pub use transformed::Transformed;
+
+use crate::materials::{MaterialId, SurfaceHit};
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+// Float type spheres and quadrics solve where rays cross them in. Far from the origin, or on
+// huge surfaces like a ground made of a sphere thousands of units across, the terms of the
+// quadratic are so big next to their difference that f32 loses the hit by more than the shadow
+// bias, and the surface speckles with holes and acne. The `f64` feature works them out in
+// double precision, at some cost in speed
+#[cfg(not(feature = "f64"))]
+type Real = f32;
+#[cfg(feature = "f64")]
+type Real = f64;
+
+// Back from `Real`, for the `t` of a hit
+#[cfg(not(feature = "f64"))]
+fn narrow(t: Real) -> f32 {
+    t
+}
+
+#[cfg(feature = "f64")]
+fn narrow(t: Real) -> f32 {
+    t as f32
+}
+
+// Vectors and matrices in the precision of `Real`
+trait Widen {
+    type Wide;
+    fn widen(self) -> Self::Wide;
+}
+
+#[cfg(not(feature = "f64"))]
+impl<T> Widen for T {
+    type Wide = T;
+    fn widen(self) -> T {
+        self
+    }
+}
+
+#[cfg(feature = "f64")]
+impl Widen for Vec3 {
+    type Wide = glam::DVec3;
+    fn widen(self) -> glam::DVec3 {
+        self.as_dvec3()
+    }
+}
+
+#[cfg(feature = "f64")]
+impl Widen for glam::Vec4 {
+    type Wide = glam::DVec4;
+    fn widen(self) -> glam::DVec4 {
+        self.as_dvec4()
+    }
+}
+
+#[cfg(feature = "f64")]
+impl Widen for glam::Mat4 {
+    type Wide = glam::DMat4;
+    fn widen(self) -> glam::DMat4 {
+        self.as_dmat4()
+    }
+}
+
+// Where a ray crosses a shape
+#[derive(Copy, Clone)]
+pub struct Intersection {
+    // The point is at `origin + t * direction` along the ray
+    pub t: f32,
+    // Which piece of the shape was hit, like a triangle of a mesh. Always 0 for shapes made of
+    // a single surface
+    pub part: usize
+}
+
+// Stretch of a ray inside a solid, from where it goes in to where it comes out. Either end
+// can be infinitely far when the solid goes on forever
+#[derive(Copy, Clone)]
+pub struct Span {
+    pub enter: Intersection,
+    pub exit: Intersection
+}
+
+pub trait Shape: Send + Sync {
+    // Closest crossing between `min_t` and `max_t` of the ray `origin + t * direction` with the
+    // surface
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection>;
+
+    // Whether the ray crosses the surface anywhere between `min_t` and `max_t`, for shadow
+    // rays. Shapes made of many pieces can stop at the first piece they find
+    fn occludes(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> bool {
+        self.intersect(origin, direction, min_t, max_t).is_some()
+    }
+
+    // Shading frame at a point on the surface, on the given part
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit;
+
+    // Material of a part, when it's not the one of the whole object
+    fn material(&self, _part: usize) -> Option<MaterialId> {
+        None
+    }
+
+    // A part for each of the materials the parts are given, to tell what the shape is made of
+    // without going through every part. Just part 0 for shapes all made of one
+    fn material_parts(&self) -> Vec<usize> {
+        vec![0]
+    }
+
+    // Center and radius of a sphere around the whole shape, None for shapes that go on forever
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)>;
+
+    // Stretches of the whole line `origin + t * direction` inside the solid the surface
+    // encloses, in order. By default every crossing of the surface goes in or comes out in
+    // turn, which holds for closed surfaces. Open ones, like a tube, have no inside
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        paired_crossings(self, origin, direction)
+    }
+}
+
+// The first part with each of the materials, one per part, or part 0 when there are none
+fn first_of_each(materials: &[MaterialId]) -> Vec<usize> {
+    let mut parts: Vec<usize> = vec![];
+    for (part, material) in materials.iter().enumerate() {
+        if !parts.iter().any(|&seen| materials[seen] == *material) {
+            parts.push(part);
+        }
+    }
+    if parts.is_empty() {
+        parts.push(0);
+    }
+    parts
+}
+
+// Two unit vectors perpendicular to the unit vector `axis` and to each other, to measure angles
+// around it
+fn axis_frame(axis: Vec3) -> (Vec3, Vec3) {
+    let u = axis.any_orthonormal_vector();
+    (u, axis.cross(u))
+}
+
+// Tangent and texture coordinates where the unit vector `normal` points on a sphere. The
+// tangent goes around the vertical axis, along the lines of latitude, and the texture
+// coordinates are the longitude and latitude, like an equirectangular map
+fn sphere_tangent_uv(normal: Vec3) -> (Vec3, Vec2) {
+    let around = Vec3::Y.cross(normal);
+    // At the poles any direction on the surface will do
+    let tangent = if around.length_squared() > 1e-12 { around.normalize() } else { normal.any_orthonormal_vector() };
+    let uv = Vec2::new(0.5 + normal.x.atan2(normal.z) / (2.0 * PI), 0.5 + normal.y.clamp(-1.0, 1.0).asin() / PI);
+    (tangent, uv)
+}
+
+// Where the ray crosses, from either side, the disc of the given radius around `center` that
+// faces `normal`
+fn disc_intersection(origin: Vec3, direction: Vec3, center: Vec3, normal: Vec3, radius: f32) -> Option<f32> {
+    let denominator = normal.dot(direction);
+    if denominator.abs() < 1e-8 {
+        return None;
+    }
+    let t = normal.dot(center - origin) / denominator;
+    let p = origin + t * direction;
+    (p.distance_squared(center) <= radius * radius).then_some(t)
+}
+
+// Closest of the candidate crossings between `min_t` and `max_t`, each with the part it's on
+fn closest(candidates: impl IntoIterator<Item = (Option<f32>, usize)>, min_t: f32, max_t: f32) -> Option<Intersection> {
+    candidates
+        .into_iter()
+        .filter_map(|(t, part)| t.filter(|&t| min_t < t && t < max_t).map(|t| Intersection { t, part }))
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+// Smallest sphere around the two spheres, each a center and a radius
+fn enclosing((a, ra): (Vec3, f32), (b, rb): (Vec3, f32)) -> (Vec3, f32) {
+    let distance = a.distance(b);
+    if distance + rb <= ra {
+        (a, ra)
+    } else if distance + ra <= rb {
+        (b, rb)
+    } else {
+        let radius = (distance + ra + rb) / 2.0;
+        (a + (b - a) * ((radius - ra) / distance), radius)
+    }
+}
+
+// Every crossing of the surface along the whole line, taken two at a time as going in and
+// coming out
+fn paired_crossings<S: Shape + ?Sized>(shape: &S, origin: Vec3, direction: Vec3) -> Vec<Span> {
+    let mut crossings = vec![];
+    let mut min_t = f32::MIN;
+    while let Some(crossing) = shape.intersect(origin, direction, min_t, f32::MAX) {
+        crossings.push(crossing);
+        // A little past it, or the same crossing could be found again
+        min_t = crossing.t + (crossing.t.abs() * 1e-6).max(1e-4);
+    }
+    crossings.chunks_exact(2).map(|pair| Span { enter: pair[0], exit: pair[1] }).collect()
+}
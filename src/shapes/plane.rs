@@ -0,0 +1,53 @@
+use super::{Intersection, Shape, Span};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+
+// Flat surface going on forever through `point`, facing the side `normal` points to
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3
+}
+
+impl Shape for Plane {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let denominator = self.normal.dot(direction);
+        // Rays parallel to the plane never reach it
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(self.point - origin) / denominator;
+        (min_t < t && t < max_t).then_some(Intersection { t, part: 0 })
+    }
+
+    // The tangent goes along the X axis when the plane allows it, so textures and anisotropic
+    // highlights line up with the world on floors and walls. Texture coordinates are the
+    // distances from `point` along the tangent and the bitangent, so images repeat every unit
+    fn surface_hit(&self, point: Vec3, _part: usize) -> SurfaceHit {
+        let normal = self.normal.normalize();
+        let along = Vec3::X - normal * normal.x;
+        let tangent = if along.length_squared() > 1e-12 { along.normalize() } else { normal.any_orthonormal_vector() };
+        let offset = point - self.point;
+        let uv = Vec2::new(offset.dot(tangent), offset.dot(normal.cross(tangent)));
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        None
+    }
+
+    // The solid is everything behind the plane
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        let denominator = self.normal.dot(direction);
+        let behind = self.normal.dot(origin - self.point) < 0.0;
+        let forever = |t: f32| Intersection { t, part: 0 };
+        if denominator.abs() < 1e-8 {
+            return if behind { vec![Span { enter: forever(f32::NEG_INFINITY), exit: forever(f32::INFINITY) }] } else { vec![] };
+        }
+        let crossing = forever(self.normal.dot(self.point - origin) / denominator);
+        if denominator > 0.0 {
+            vec![Span { enter: forever(f32::NEG_INFINITY), exit: crossing }]
+        } else {
+            vec![Span { enter: crossing, exit: forever(f32::INFINITY) }]
+        }
+    }
+}
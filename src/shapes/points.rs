@@ -0,0 +1,110 @@
+// Point clouds, like the ones LiDAR and 3D scanners make, drawn as a little ball around each
+// point.
+//
+// The balls are sorted into a grid of cells, and rays walk through the cells they cross in
+// order, only trying the balls in each, so clouds of millions of points stay quick.
+
+use super::{sphere_tangent_uv, Intersection, Shape};
+use crate::accel::AccelStructure;
+use crate::bvh::Aabb;
+use crate::grid::Grid;
+use crate::materials::SurfaceHit;
+use glam::{Mat4, Vec3};
+
+// Cells per ball, about two balls to a cell, and the most cells along each side of the grid
+const CELLS_PER_BALL: f32 = 0.5;
+const MAX_RESOLUTION: usize = 512;
+
+pub struct PointCloud {
+    points: Vec<Vec3>,
+    // Radius and linear RGB color of each ball, the color empty when the points have none
+    radii: Vec<f32>,
+    colors: Vec<Vec3>,
+    grid: Grid
+}
+
+impl PointCloud {
+    // Every point drawn as a ball of the same radius
+    pub fn new(points: Vec<Vec3>, radius: f32) -> PointCloud {
+        let radii = vec![radius; points.len()];
+        let grid = ball_grid(&points, &radii);
+        PointCloud { points, radii, colors: vec![], grid }
+    }
+
+    // Radius of each ball, for clouds where the points stand for patches of different sizes
+    pub fn with_radii(mut self, radii: Vec<f32>) -> PointCloud {
+        assert_eq!(radii.len(), self.points.len(), "one radius per point");
+        self.radii = radii;
+        self.grid = ball_grid(&self.points, &self.radii);
+        self
+    }
+
+    // Linear RGB color of each point, which materials can pick up from the surface hit
+    pub fn with_colors(mut self, colors: Vec<Vec3>) -> PointCloud {
+        assert_eq!(colors.len(), self.points.len(), "one color per point");
+        self.colors = colors;
+        self
+    }
+
+    // Moves, turns and scales the cloud by the matrix. The balls stay round, growing with the
+    // largest of the scales
+    pub fn transformed(mut self, matrix: Mat4) -> PointCloud {
+        for point in &mut self.points {
+            *point = matrix.transform_point3(*point);
+        }
+        let scale = [matrix.x_axis, matrix.y_axis, matrix.z_axis].map(|axis| axis.truncate().length()).into_iter().fold(0.0, f32::max);
+        for radius in &mut self.radii {
+            *radius *= scale;
+        }
+        self.grid = ball_grid(&self.points, &self.radii);
+        self
+    }
+
+    // Closest crossing of the ray with one ball between `min_t` and `max_t`
+    fn ball_intersection(&self, ball: usize, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<f32> {
+        let co = origin - self.points[ball];
+        let a = direction.dot(direction);
+        let b = co.dot(direction);
+        let c = co.dot(co) - self.radii[ball] * self.radii[ball];
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        [(-b - root) / a, (-b + root) / a].into_iter().find(|&t| min_t < t && t < max_t)
+    }
+}
+
+// The balls sorted into a grid of cells, a ball in every cell its box overlaps
+fn ball_grid(points: &[Vec3], radii: &[f32]) -> Grid {
+    let boxes: Vec<Aabb> = points.iter().zip(radii).map(|(&point, &radius)| Aabb::around_sphere(point, radius)).collect();
+    Grid::with_cells(&boxes, CELLS_PER_BALL, MAX_RESOLUTION)
+}
+
+impl Shape for PointCloud {
+    // Only tries the balls in the cells of the grid the ray goes through
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let mut closest = None;
+        self.grid.traverse(origin, direction, min_t, max_t, &mut |ball, closest_t| {
+            let t = self.ball_intersection(ball, origin, direction, min_t, closest_t)?;
+            closest = Some(Intersection { t, part: ball });
+            Some(t)
+        });
+        closest
+    }
+
+    // Like on a sphere, with the color of the point
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let normal = (point - self.points[part]).normalize_or_zero();
+        let (tangent, uv) = sphere_tangent_uv(normal);
+        SurfaceHit { point, normal, tangent, uv, color: self.colors.get(part).copied() }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        if self.points.is_empty() {
+            return Some((Vec3::ZERO, 0.0));
+        }
+        let bounds = self.grid.bounds();
+        Some((bounds.center(), bounds.max.distance(bounds.center())))
+    }
+}
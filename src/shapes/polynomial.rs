@@ -0,0 +1,106 @@
+// Real roots of polynomials, for the shapes whose intersections don't have a simple formula.
+//
+// The roots of the derivative split the interval into pieces where the polynomial only goes up
+// or only goes down, so each piece holds at most one root, which bisection then finds. This is
+// slower than the closed form solutions for cubics and quartics but doesn't lose roots to
+// rounding errors. Roots where the polynomial only touches zero without crossing it are missed,
+// which for intersections means rays that only graze a surface.
+
+// Roots between `lo` and `hi`, in increasing order, of the polynomial with these coefficients
+// from the highest power down
+pub fn roots(coefficients: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    // A zero leading coefficient lowers the degree
+    let start = coefficients.iter().position(|&c| c != 0.0).unwrap_or(coefficients.len());
+    let coefficients = &coefficients[start..];
+    match coefficients.len() {
+        0 | 1 => return vec![],
+        2 => {
+            let root = -coefficients[1] / coefficients[0];
+            return if (lo..=hi).contains(&root) { vec![root] } else { vec![] };
+        },
+        _ => {}
+    }
+
+    let degree = coefficients.len() - 1;
+    let derivative: Vec<f64> = coefficients[..degree].iter().enumerate().map(|(k, c)| c * (degree - k) as f64).collect();
+    let mut bounds = vec![lo];
+    bounds.extend(roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut found = vec![];
+    for pair in bounds.windows(2) {
+        if let Some(root) = bisect(coefficients, pair[0], pair[1]) {
+            if found.last() != Some(&root) {
+                found.push(root);
+            }
+        }
+    }
+    found
+}
+
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().fold(0.0, |value, c| value * x + c)
+}
+
+// The root between `a` and `b`, if the polynomial changes sign in between
+fn bisect(coefficients: &[f64], mut a: f64, mut b: f64) -> Option<f64> {
+    let mut fa = evaluate(coefficients, a);
+    let fb = evaluate(coefficients, b);
+    if fa == 0.0 {
+        return Some(a);
+    }
+    if fb == 0.0 {
+        return Some(b);
+    }
+    if fa.signum() == fb.signum() {
+        return None;
+    }
+    for _ in 0..64 {
+        let middle = 0.5 * (a + b);
+        let fm = evaluate(coefficients, middle);
+        if fm == 0.0 || b - a < 1e-12 * (1.0 + middle.abs()) {
+            return Some(middle);
+        }
+        if fm.signum() == fa.signum() {
+            a = middle;
+            fa = fm;
+        } else {
+            b = middle;
+        }
+    }
+    Some(0.5 * (a + b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(found: &[f64], expected: &[f64]) {
+        assert_eq!(found.len(), expected.len(), "{:?} isn't {:?}", found, expected);
+        for (found, expected) in found.iter().zip(expected) {
+            assert!((found - expected).abs() < 1e-9, "{:?} isn't {:?}", found, expected);
+        }
+    }
+
+    // (x - 1)(x - 2)(x - 3)(x - 4)
+    const QUARTIC: [f64; 5] = [1.0, -10.0, 35.0, -50.0, 24.0];
+
+    #[test]
+    fn finds_the_four_roots_of_a_quartic_in_order() {
+        assert_close(&roots(&QUARTIC, 0.0, 5.0), &[1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn only_finds_roots_in_the_interval() {
+        assert_close(&roots(&QUARTIC, 1.5, 3.5), &[2.0, 3.0]);
+        assert_close(&roots(&QUARTIC, 4.5, 10.0), &[]);
+    }
+
+    #[test]
+    fn leading_zero_coefficients_lower_the_degree() {
+        // (x - 1)(x - 2)
+        assert_close(&roots(&[0.0, 0.0, 1.0, -3.0, 2.0], 0.0, 5.0), &[1.0, 2.0]);
+        assert_close(&roots(&[0.0, 2.0, -4.0], 0.0, 5.0), &[2.0]);
+        assert_close(&roots(&[0.0, 0.0, 3.0], 0.0, 5.0), &[]);
+    }
+}
@@ -0,0 +1,152 @@
+use super::{narrow, sphere_tangent_uv, Intersection, Real, Shape, Span, Widen};
+use crate::materials::SurfaceHit;
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use std::f32::consts::PI;
+
+// Surface of the points `p` where p^T Q p = 0, with `p` in homogeneous coordinates (x, y, z, 1)
+// and Q a symmetric 4x4 matrix. That covers ellipsoids, paraboloids, hyperboloids, cones and
+// cylinders of any shape, at any place and turned any way. Inside the solid p^T Q p is negative.
+//
+// Most quadrics go on forever, so they can be clipped to the part inside a sphere
+pub struct Quadric {
+    matrix: Mat4,
+    clip: Option<(Vec3, f32)>
+}
+
+impl Quadric {
+    pub fn new(matrix: Mat4) -> Quadric {
+        // Only the symmetric part of the matrix changes the surface
+        Quadric { matrix: (matrix + matrix.transpose()) * 0.5, clip: None }
+    }
+
+    // Sphere of radius 1 around the origin, to be scaled into an ellipsoid
+    pub fn ellipsoid(center: Vec3, radii: Vec3) -> Quadric {
+        let unit = Quadric::new(Mat4::from_diagonal(Vec4::new(1.0, 1.0, 1.0, -1.0)));
+        let quadric = unit.transformed(Mat4::from_scale_rotation_translation(radii, Quat::IDENTITY, center));
+        quadric.clipped(center, radii.max_element())
+    }
+
+    // Bowl y = x² + z² opening upwards from the origin
+    pub fn paraboloid() -> Quadric {
+        Quadric::new(Mat4::from_cols(
+            Vec4::new(1.0, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, 0.0, -0.5),
+            Vec4::new(0.0, 0.0, 1.0, 0.0),
+            Vec4::new(0.0, -0.5, 0.0, 0.0)
+        ))
+    }
+
+    // x² + z² - y² = 1, a single surface narrowing to a waist of radius 1 around the Y axis
+    pub fn hyperboloid() -> Quadric {
+        Quadric::new(Mat4::from_diagonal(Vec4::new(1.0, -1.0, 1.0, -1.0)))
+    }
+
+    // Moves, turns and scales the surface by the matrix, the clipping sphere with it
+    pub fn transformed(mut self, matrix: Mat4) -> Quadric {
+        let inverse = matrix.inverse();
+        self.matrix = inverse.transpose() * self.matrix * inverse;
+        self.clip = self.clip.map(|(center, radius)| {
+            let (scale, _, _) = matrix.to_scale_rotation_translation();
+            (matrix.transform_point3(center), radius * scale.abs().max_element())
+        });
+        self
+    }
+
+    // Keeps only the part of the surface inside the sphere
+    pub fn clipped(mut self, center: Vec3, radius: f32) -> Quadric {
+        self.clip = Some((center, radius));
+        self
+    }
+
+    fn inside_clip(&self, point: Vec3) -> bool {
+        self.clip.is_none_or(|(center, radius)| point.distance_squared(center) <= radius * radius)
+    }
+}
+
+impl Shape for Quadric {
+    // Along the ray p^T Q p is a quadratic in `t`, which is linear when the ray runs along the
+    // way the surface opens, like up a paraboloid
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let matrix = self.matrix.widen();
+        let o = origin.extend(1.0).widen();
+        let d = direction.extend(0.0).widen();
+        let qo = matrix * o;
+        let a = d.dot(matrix * d);
+        let b = 2.0 * d.dot(qo);
+        let c = o.dot(qo);
+
+        let roots = if a.abs() < 1e-9 {
+            if b.abs() < 1e-12 {
+                return None;
+            }
+            [-c / b, Real::MAX]
+        } else {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            [(-b - discriminant.sqrt()) / (2.0 * a), (-b + discriminant.sqrt()) / (2.0 * a)]
+        };
+        roots
+            .map(narrow)
+            .into_iter()
+            .filter(|&t| min_t < t && t < max_t && self.inside_clip(origin + t * direction))
+            .reduce(f32::min)
+            .map(|t| Intersection { t, part: 0 })
+    }
+
+    // The normal is the gradient of p^T Q p, pointing out of the solid. The tangent and the
+    // texture coordinates go around the vertical axis like on a sphere, centered on the
+    // clipping sphere
+    fn surface_hit(&self, point: Vec3, _part: usize) -> SurfaceHit {
+        let gradient = (self.matrix * point.extend(1.0)).truncate();
+        let normal = gradient.normalize_or_zero();
+        let (tangent, _) = sphere_tangent_uv(normal);
+        let uv = match self.clip {
+            Some((center, radius)) => {
+                let direction = (point - center).normalize_or_zero();
+                Vec2::new(0.5 + direction.x.atan2(direction.z) / (2.0 * PI), 0.5 + (point.y - center.y) / (2.0 * radius))
+            },
+            None => Vec2::new(point.x, point.z)
+        };
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        self.clip
+    }
+
+    // Where p^T Q p is negative along the ray, on the whole solid whether it's clipped or not.
+    // When the quadratic opens downwards the ray is inside before and after the roots, like
+    // along the axis of a hyperboloid
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        let o = origin.extend(1.0);
+        let d = direction.extend(0.0);
+        let qo = self.matrix * o;
+        let a = d.dot(self.matrix * d);
+        let b = 2.0 * d.dot(qo);
+        let c = o.dot(qo);
+        let at = |t: f32| Intersection { t, part: 0 };
+        let (before, after) = (at(f32::NEG_INFINITY), at(f32::INFINITY));
+
+        if a.abs() < 1e-9 {
+            if b.abs() < 1e-12 {
+                return if c < 0.0 { vec![Span { enter: before, exit: after }] } else { vec![] };
+            }
+            let root = at(-c / b);
+            return if b > 0.0 { vec![Span { enter: before, exit: root }] } else { vec![Span { enter: root, exit: after }] };
+        }
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return if a < 0.0 { vec![Span { enter: before, exit: after }] } else { vec![] };
+        }
+        let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
+        let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
+        let (near, far) = (at(t1.min(t2)), at(t1.max(t2)));
+        if a > 0.0 {
+            vec![Span { enter: near, exit: far }]
+        } else {
+            vec![Span { enter: before, exit: near }, Span { enter: far, exit: after }]
+        }
+    }
+}
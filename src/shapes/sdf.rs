@@ -0,0 +1,300 @@
+// Shapes given by a signed distance field: a function telling, for any point, how far it is
+// from the surface, negative inside. They're found by sphere tracing, stepping along the ray
+// by the distance to the surface, which can't overshoot it. That makes it easy to melt shapes
+// together or to describe ones that have no formula for where a ray crosses them.
+
+use super::{enclosing, sphere_tangent_uv, Intersection, Shape};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+
+// How close to the surface counts as on it, and the most steps to take before giving up,
+// which only happens grazing it
+const EPSILON: f32 = 1e-4;
+const MAX_STEPS: usize = 512;
+
+pub trait Sdf: Send + Sync {
+    // Distance from the point to the surface, negative inside. It can be less than the
+    // distance, but never more
+    fn distance(&self, p: Vec3) -> f32;
+
+    // Center and radius of a sphere the whole surface is in
+    fn bounding_sphere(&self) -> (Vec3, f32);
+}
+
+// Ray marched object made of the field
+pub struct Marched {
+    pub sdf: Box<dyn Sdf>
+}
+
+impl Shape for Marched {
+    // Starts where the ray enters the bounding sphere. When the ray starts inside it, maybe
+    // right on the surface like a shadow ray, it marches by the absolute distance until it's
+    // away from the surface, so it finds the next crossing and not the one it started from
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let (center, radius) = self.sdf.bounding_sphere();
+        let co = origin - center;
+        let a = direction.dot(direction);
+        let b = co.dot(direction);
+        let discriminant = b * b - a * (co.dot(co) - radius * radius);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let mut t = ((-b - discriminant.sqrt()) / a).max(min_t);
+        let exit = ((-b + discriminant.sqrt()) / a).min(max_t);
+
+        let speed = a.sqrt();
+        let mut left_surface = t > min_t;
+        for _ in 0..MAX_STEPS {
+            if t >= exit {
+                return None;
+            }
+            let distance = self.sdf.distance(origin + t * direction).abs();
+            if distance < EPSILON {
+                if left_surface && t > min_t {
+                    return Some(Intersection { t, part: 0 });
+                }
+            } else if distance > 2.0 * EPSILON {
+                left_surface = true;
+            }
+            t += distance.max(EPSILON) / speed;
+        }
+        None
+    }
+
+    // The normal is the gradient of the field, by central differences. The tangent and the
+    // texture coordinates go around the vertical axis like on a sphere
+    fn surface_hit(&self, point: Vec3, _part: usize) -> SurfaceHit {
+        let h = EPSILON;
+        let gradient = Vec3::new(
+            self.sdf.distance(point + Vec3::X * h) - self.sdf.distance(point - Vec3::X * h),
+            self.sdf.distance(point + Vec3::Y * h) - self.sdf.distance(point - Vec3::Y * h),
+            self.sdf.distance(point + Vec3::Z * h) - self.sdf.distance(point - Vec3::Z * h)
+        );
+        let normal = gradient.normalize_or_zero();
+        let (center, _) = self.sdf.bounding_sphere();
+        let (tangent, _) = sphere_tangent_uv(normal);
+        let (_, uv) = sphere_tangent_uv((point - center).normalize_or_zero());
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        Some(self.sdf.bounding_sphere())
+    }
+}
+
+pub struct Ball {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+impl Sdf for Ball {
+    fn distance(&self, p: Vec3) -> f32 {
+        p.distance(self.center) - self.radius
+    }
+
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.center, self.radius)
+    }
+}
+
+// Box going `half_size` each way from `center`, with its edges and corners rounded off by
+// `radius` beyond that
+pub struct RoundedBox {
+    pub center: Vec3,
+    pub half_size: Vec3,
+    pub radius: f32
+}
+
+impl Sdf for RoundedBox {
+    fn distance(&self, p: Vec3) -> f32 {
+        let q = (p - self.center).abs() - self.half_size;
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0) - self.radius
+    }
+
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.center, self.half_size.length() + self.radius)
+    }
+}
+
+// Torus lying flat around `center`
+pub struct Ring {
+    pub center: Vec3,
+    pub major_radius: f32,
+    pub minor_radius: f32
+}
+
+impl Sdf for Ring {
+    fn distance(&self, p: Vec3) -> f32 {
+        let offset = p - self.center;
+        let q = Vec2::new(Vec2::new(offset.x, offset.z).length() - self.major_radius, offset.y);
+        q.length() - self.minor_radius
+    }
+
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.center, self.major_radius + self.minor_radius)
+    }
+}
+
+// Rod from `a` to `b` with round ends
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f32
+}
+
+impl Sdf for Capsule {
+    fn distance(&self, p: Vec3) -> f32 {
+        let ab = self.b - self.a;
+        let along = ((p - self.a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0);
+        p.distance(self.a + ab * along) - self.radius
+    }
+
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        ((self.a + self.b) / 2.0, self.a.distance(self.b) / 2.0 + self.radius)
+    }
+}
+
+// Both fields melted together, filling in where they come within `smoothness` of each other.
+// With no smoothness it's a plain union, with a sharp crease
+pub struct SmoothUnion {
+    pub left: Box<dyn Sdf>,
+    pub right: Box<dyn Sdf>,
+    pub smoothness: f32
+}
+
+impl Sdf for SmoothUnion {
+    // The polynomial smooth minimum
+    fn distance(&self, p: Vec3) -> f32 {
+        let (a, b) = (self.left.distance(p), self.right.distance(p));
+        let k = self.smoothness;
+        if k <= 0.0 {
+            return a.min(b);
+        }
+        let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+        b + (a - b) * h - k * h * (1.0 - h)
+    }
+
+    // The blend can't swell the surface by more than a quarter of the smoothness
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        let (center, radius) = enclosing(self.left.bounding_sphere(), self.right.bounding_sphere());
+        (center, radius + self.smoothness.max(0.0) / 4.0)
+    }
+}
+
+// Source of the field of a blobby object, `weight` at its center fading smoothly to nothing
+// `radius` away from it. A negative weight carves into the blobs around it
+pub struct Charge {
+    pub center: Vec3,
+    pub radius: f32,
+    pub weight: f32
+}
+
+impl Charge {
+    // The (1 - r^2 / R^2)^2 falloff of Wyvill, which is flat at the center and at the radius
+    fn field(&self, p: Vec3) -> f32 {
+        let falloff = 1.0 - p.distance_squared(self.center) / (self.radius * self.radius);
+        if falloff <= 0.0 { 0.0 } else { self.weight * falloff * falloff }
+    }
+
+    // Steepest the field gets, at a third of the way out in r^2
+    fn max_slope(&self) -> f32 {
+        self.weight.abs() * 8.0 / (3.0 * 3.0_f32.sqrt() * self.radius)
+    }
+}
+
+// Blobby object, the surface where the fields of the charges add up to `threshold`. Charges
+// coming close to each other merge like drops of liquid
+pub struct Metaballs {
+    pub charges: Vec<Charge>,
+    pub threshold: f32
+}
+
+impl Sdf for Metaballs {
+    // The field isn't a distance, but it can't change faster than the slopes of the charges
+    // added up, so dividing by that never overshoots the surface
+    fn distance(&self, p: Vec3) -> f32 {
+        let field: f32 = self.charges.iter().map(|charge| charge.field(p)).sum();
+        let slope: f32 = self.charges.iter().map(Charge::max_slope).sum();
+        (self.threshold - field) / slope.max(1e-6)
+    }
+
+    // The field is zero outside of all the charges
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        self.charges
+            .iter()
+            .map(|charge| (charge.center, charge.radius))
+            .reduce(enclosing)
+            .unwrap_or((Vec3::ZERO, 0.0))
+    }
+}
+
+// The Mandelbulb, the 3D take on the Mandelbrot set raising points to `power` in spherical
+// coordinates, about `scale` across from `center`. More iterations bring out finer detail
+pub struct Mandelbulb {
+    pub center: Vec3,
+    pub scale: f32,
+    pub power: f32,
+    pub iterations: u32
+}
+
+impl Sdf for Mandelbulb {
+    // Distance estimate from how fast the point escapes, 0.5 * ln(r) * r / dr, with dr the
+    // running derivative of the iteration
+    fn distance(&self, p: Vec3) -> f32 {
+        let c = (p - self.center) / self.scale;
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+            let theta = (z.z / r.max(1e-12)).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            z = r.powf(self.power) * Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) + c;
+            r = z.length();
+        }
+        if r <= 1e-12 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+
+    // Points further than 2 away escape right away, the bulb itself stays well within that
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.center, 1.5 * self.scale)
+    }
+}
+
+// The Menger sponge, a cube going `half_size` each way from `center` with a cross punched
+// through it, and through each of the cubes left around it, `iterations` times over
+pub struct MengerSponge {
+    pub center: Vec3,
+    pub half_size: f32,
+    pub iterations: u32
+}
+
+impl Sdf for MengerSponge {
+    // The cube, with the crosses of each level cut out of it in turn
+    fn distance(&self, p: Vec3) -> f32 {
+        let q = (p - self.center) / self.half_size;
+        let outside = q.abs() - Vec3::ONE;
+        let mut d = outside.max(Vec3::ZERO).length() + outside.max_element().min(0.0);
+        let mut scale = 1.0;
+        for _ in 0..self.iterations {
+            // Where the point falls in its cell of this level, from -1 to 1 each way
+            let cell = q * scale;
+            let a = Vec3::new(cell.x.rem_euclid(2.0), cell.y.rem_euclid(2.0), cell.z.rem_euclid(2.0)) - Vec3::ONE;
+            scale *= 3.0;
+            let r = (Vec3::ONE - 3.0 * a.abs()).abs();
+            let cross = r.x.max(r.y).min(r.y.max(r.z)).min(r.z.max(r.x));
+            d = d.max((cross - 1.0) / scale);
+        }
+        d * self.half_size
+    }
+
+    fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.center, self.half_size * 3.0_f32.sqrt())
+    }
+}
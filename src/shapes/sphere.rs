@@ -0,0 +1,51 @@
+use super::{narrow, sphere_tangent_uv, Intersection, Real, Shape, Widen};
+use crate::materials::SurfaceHit;
+use glam::Vec3;
+
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+impl Sphere {
+    // Both values of `t` where the ray crosses the sphere, infinite when it misses it
+    fn ray_intersection(&self, origin: Vec3, distance: Vec3) -> (f32, f32) {
+        let r = self.radius as Real;
+        let co = origin.widen() - self.center.widen();
+        let distance = distance.widen();
+
+        let a = distance.dot(distance);
+        let b = 2.0 * co.dot(distance);
+        let c = co.dot(co) - r * r;
+
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return (f32::MAX, f32::MAX);
+        }
+
+        let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
+        let t2 = (-b - discriminant.sqrt()) / (2.0 * a);
+
+        (narrow(t1), narrow(t2))
+    }
+}
+
+impl Shape for Sphere {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let (t1, t2) = self.ray_intersection(origin, direction);
+        let t = [t1, t2].into_iter().filter(|&t| min_t < t && t < max_t).reduce(f32::min)?;
+        Some(Intersection { t, part: 0 })
+    }
+
+    // The tangent goes around the vertical axis, along the lines of latitude. Texture
+    // coordinates are the longitude and latitude, like an equirectangular map
+    fn surface_hit(&self, point: Vec3, _part: usize) -> SurfaceHit {
+        let normal = (point - self.center).normalize();
+        let (tangent, uv) = sphere_tangent_uv(normal);
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        Some((self.center, self.radius))
+    }
+}
@@ -0,0 +1,139 @@
+// Lots of spheres in one shape, for scenes made of thousands of them, like a ball pit or
+// particles.
+//
+// Spheres near each other are packed four at a time, centers and radii laid out per lane, and
+// a ray is tried against the four at once with SIMD. The packs are sorted into a BVH, so a ray
+// only tries the packs it passes near.
+
+use super::{first_of_each, sphere_tangent_uv, Intersection, Shape};
+use crate::bvh::{Aabb, Bvh, BvhOptions};
+use crate::materials::{MaterialId, SurfaceHit};
+use glam::{Vec3, Vec4};
+
+// Four spheres, the unused lanes of the last pack with a radius that never hits
+struct Pack {
+    x: Vec4,
+    y: Vec4,
+    z: Vec4,
+    radius_squared: Vec4,
+    // Which sphere each lane is
+    spheres: [usize; 4]
+}
+
+pub struct Spheres {
+    centers: Vec<Vec3>,
+    radii: Vec<f32>,
+    materials: Vec<MaterialId>,
+    packs: Vec<Pack>,
+    bvh: Bvh
+}
+
+impl Spheres {
+    pub fn new(centers: Vec<Vec3>, radii: Vec<f32>) -> Spheres {
+        assert_eq!(radii.len(), centers.len(), "one radius per sphere");
+        let bounds = Aabb::around_points(&centers);
+        let extent = (bounds.max - bounds.min).max(Vec3::splat(1e-6));
+
+        // Along a Z curve, so the spheres packed together are close to each other
+        let mut order: Vec<usize> = (0..centers.len()).collect();
+        order.sort_by_cached_key(|&sphere| morton_code((centers[sphere] - bounds.min) / extent));
+
+        let packs: Vec<Pack> = order
+            .chunks(4)
+            .map(|chunk| {
+                let lanes = |f: &dyn Fn(usize) -> f32, unused: f32| Vec4::from_array([0, 1, 2, 3].map(|k| chunk.get(k).map_or(unused, |&sphere| f(sphere))));
+                Pack {
+                    x: lanes(&|sphere| centers[sphere].x, 0.0),
+                    y: lanes(&|sphere| centers[sphere].y, 0.0),
+                    z: lanes(&|sphere| centers[sphere].z, 0.0),
+                    radius_squared: lanes(&|sphere| radii[sphere] * radii[sphere], f32::NEG_INFINITY),
+                    spheres: [0, 1, 2, 3].map(|k| chunk.get(k).copied().unwrap_or(chunk[0]))
+                }
+            })
+            .collect();
+        let boxes: Vec<Aabb> = order
+            .chunks(4)
+            .map(|chunk| chunk.iter().fold(Aabb::EMPTY, |bounds, &sphere| bounds.union(Aabb::around_sphere(centers[sphere], radii[sphere]))))
+            .collect();
+        let bvh = Bvh::new(&boxes, BvhOptions { leaf_size: 1, ..BvhOptions::default() });
+        Spheres { centers, radii, materials: vec![], packs, bvh }
+    }
+
+    // Material of each sphere, in place of the one of the object
+    pub fn with_materials(mut self, materials: Vec<MaterialId>) -> Spheres {
+        assert_eq!(materials.len(), self.centers.len(), "one material per sphere");
+        self.materials = materials;
+        self
+    }
+
+    // Closest crossing of the ray with the four spheres of the pack between `min_t` and
+    // `max_t`, all worked out side by side
+    fn pack_intersection(pack: &Pack, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let (cx, cy, cz) = (Vec4::splat(origin.x) - pack.x, Vec4::splat(origin.y) - pack.y, Vec4::splat(origin.z) - pack.z);
+        let a = direction.dot(direction);
+        let b = cx * direction.x + cy * direction.y + cz * direction.z;
+        let c = cx * cx + cy * cy + cz * cz - pack.radius_squared;
+        let discriminant = b * b - c * a;
+        // glam has no square root over all the lanes, so it's taken lane by lane
+        let root = Vec4::from_array(discriminant.max(Vec4::ZERO).to_array().map(f32::sqrt));
+        let (near, far) = ((-b - root) / a, (-b + root) / a);
+
+        let (min_t, max_t) = (Vec4::splat(min_t), Vec4::splat(max_t));
+        let crosses = discriminant.cmpge(Vec4::ZERO);
+        let near_hit = crosses & near.cmpgt(min_t) & near.cmplt(max_t);
+        let far_hit = crosses & far.cmpgt(min_t) & far.cmplt(max_t);
+        let t = Vec4::select(near_hit, near, Vec4::select(far_hit, far, Vec4::splat(f32::INFINITY)));
+
+        let closest = t.min_element();
+        if closest == f32::INFINITY {
+            return None;
+        }
+        let lane = (0..4).find(|&k| t[k] == closest)?;
+        Some(Intersection { t: closest, part: pack.spheres[lane] })
+    }
+}
+
+// Position along a Z curve through the unit cube, from 10 bits of each coordinate
+fn morton_code(p: Vec3) -> u32 {
+    let spread = |v: f32| {
+        let mut x = (v.clamp(0.0, 1.0) * 1023.0) as u32;
+        x = (x | (x << 16)) & 0x030000ff;
+        x = (x | (x << 8)) & 0x0300f00f;
+        x = (x | (x << 4)) & 0x030c30c3;
+        (x | (x << 2)) & 0x09249249
+    };
+    spread(p.x) | (spread(p.y) << 1) | (spread(p.z) << 2)
+}
+
+impl Shape for Spheres {
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let mut closest = None;
+        self.bvh.traverse(origin, direction, min_t, max_t, |pack, max_t| {
+            let intersection = Spheres::pack_intersection(&self.packs[pack], origin, direction, min_t, max_t)?;
+            closest = Some(intersection);
+            Some(intersection.t)
+        });
+        closest
+    }
+
+    // Like on a single sphere
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let normal = (point - self.centers[part]).normalize();
+        let (tangent, uv) = sphere_tangent_uv(normal);
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn material(&self, part: usize) -> Option<MaterialId> {
+        self.materials.get(part).copied()
+    }
+
+    fn material_parts(&self) -> Vec<usize> {
+        first_of_each(&self.materials)
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let bounds = self.centers.iter().zip(&self.radii).fold(Aabb::EMPTY, |bounds, (&center, &radius)| bounds.union(Aabb::around_sphere(center, radius)));
+        let center = bounds.center();
+        Some((center, bounds.max.distance(center)))
+    }
+}
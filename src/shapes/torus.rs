@@ -0,0 +1,122 @@
+use super::{axis_frame, polynomial, Intersection, Shape};
+use crate::materials::SurfaceHit;
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+// Ring around `center` in the plane perpendicular to `axis`, with a tube of `minor_radius`
+// going around a circle of `major_radius`
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: f32,
+    pub minor_radius: f32
+}
+
+impl Torus {
+    // Coordinates of `p` in the space of the torus, centered on it with the axis along Y
+    fn to_local(&self, p: Vec3) -> Vec3 {
+        let axis = self.axis.normalize();
+        let (u, w) = axis_frame(axis);
+        Vec3::new(p.dot(u), p.dot(axis), p.dot(w))
+    }
+}
+
+impl Shape for Torus {
+    // The points of the torus are the roots of a quartic along the ray. It's only solved where
+    // the ray is inside the bounding sphere, starting from where the ray enters it, which keeps
+    // the numbers small
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let (center, radius) = self.bounding_sphere()?;
+        let co = origin - center;
+        let a = direction.dot(direction);
+        let b = co.dot(direction);
+        let discriminant = b * b - a * (co.dot(co) - radius * radius);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let enter = ((-b - discriminant.sqrt()) / a).max(min_t);
+        let exit = ((-b + discriminant.sqrt()) / a).min(max_t);
+        if enter >= exit {
+            return None;
+        }
+
+        let o = self.to_local(origin + enter * direction - self.center).as_dvec3();
+        let d = self.to_local(direction).as_dvec3();
+        let (major, minor) = (self.major_radius as f64, self.minor_radius as f64);
+        let (dd, od, oo) = (d.dot(d), o.dot(d), o.dot(o));
+        let k = oo + major * major - minor * minor;
+        let four_r2 = 4.0 * major * major;
+        let coefficients = [
+            dd * dd,
+            4.0 * dd * od,
+            4.0 * od * od + 2.0 * dd * k - four_r2 * (d.x * d.x + d.z * d.z),
+            4.0 * od * k - 2.0 * four_r2 * (o.x * d.x + o.z * d.z),
+            k * k - four_r2 * (o.x * o.x + o.z * o.z)
+        ];
+        polynomial::roots(&coefficients, 0.0, (exit - enter) as f64)
+            .into_iter()
+            .map(|s| enter + s as f32)
+            .find(|&t| min_t < t && t < max_t)
+            .map(|t| Intersection { t, part: 0 })
+    }
+
+    // The normal points away from the closest point on the circle inside the tube. The tangent
+    // goes around the axis, and the texture wraps once around it and once around the tube
+    fn surface_hit(&self, point: Vec3, _part: usize) -> SurfaceHit {
+        let axis = self.axis.normalize();
+        let (u, w) = axis_frame(axis);
+        let offset = point - self.center;
+        let height = offset.dot(axis);
+        let around = (offset - axis * height).normalize_or_zero();
+        let normal = (offset - around * self.major_radius).normalize();
+        let tangent = axis.cross(around);
+        let distance = offset.dot(around) - self.major_radius;
+        let uv = Vec2::new(
+            0.5 + around.dot(w).atan2(around.dot(u)) / (2.0 * PI),
+            0.5 + height.atan2(distance) / (2.0 * PI)
+        );
+        SurfaceHit { point, normal, tangent, uv, color: None }
+    }
+
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        Some((self.center, self.major_radius + self.minor_radius))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lying flat, its tube going from 1.5 to 2.5 away from the axis
+    const TORUS: Torus = Torus { center: Vec3::ZERO, axis: Vec3::Y, major_radius: 2.0, minor_radius: 0.5 };
+
+    fn hit(origin: Vec3, direction: Vec3) -> Option<f32> {
+        TORUS.intersect(origin, direction, 1e-4, f32::MAX).map(|intersection| intersection.t)
+    }
+
+    fn assert_hit(t: Option<f32>, expected: f32) {
+        let t = t.expect("the ray misses the torus");
+        assert!((t - expected).abs() < 1e-4, "hit at {} rather than {}", t, expected);
+    }
+
+    #[test]
+    fn rays_from_outside_hit_the_outer_side_first() {
+        assert_hit(hit(Vec3::new(0.0, 0.0, -5.0), Vec3::Z), 2.5);
+    }
+
+    #[test]
+    fn rays_from_the_hole_hit_the_inner_side() {
+        assert_hit(hit(Vec3::ZERO, Vec3::Z), 1.5);
+        assert_hit(hit(Vec3::ZERO, -Vec3::X), 1.5);
+    }
+
+    #[test]
+    fn rays_along_the_axis_go_through_the_hole() {
+        assert_eq!(hit(Vec3::new(0.0, 5.0, 0.0), -Vec3::Y), None);
+    }
+
+    #[test]
+    fn rays_inside_the_tube_hit_its_wall() {
+        assert_hit(hit(Vec3::new(0.0, 0.0, -2.0), Vec3::Z), 0.5);
+    }
+}
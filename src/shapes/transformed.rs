@@ -0,0 +1,122 @@
+use super::{Intersection, Shape, Span};
+use crate::materials::{MaterialId, SurfaceHit};
+use glam::{Mat3, Mat4, Vec3};
+use std::sync::Arc;
+
+// Shape moved, turned and scaled by a matrix, without touching its geometry. Rays are taken
+// into the space of the shape instead, where it sits untransformed, and the surface found
+// there is brought back out.
+//
+// The shape can be shared, so the same mesh can be placed many times over as instances
+// without copying its triangles
+pub struct Transformed {
+    shape: Arc<dyn Shape>,
+    matrix: Mat4,
+    inverse: Mat4,
+    // Normals only stay perpendicular to the surface with the inverse transpose
+    normal_matrix: Mat4,
+    // Material used all over in place of the ones of the shape, if any
+    material: Option<MaterialId>
+}
+
+impl Transformed {
+    pub fn new(shape: impl Into<Arc<dyn Shape>>, matrix: Mat4) -> Transformed {
+        let inverse = matrix.inverse();
+        Transformed { shape: shape.into(), matrix, inverse, normal_matrix: inverse.transpose(), material: None }
+    }
+
+    // Paints this instance with the material, even the parts the shape gives their own
+    pub fn with_material(mut self, material: MaterialId) -> Transformed {
+        self.material = Some(material);
+        self
+    }
+}
+
+impl Shape for Transformed {
+    // The direction isn't normalized in the space of the shape, so `t` is the same in both
+    fn intersect(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> Option<Intersection> {
+        let local_origin = self.inverse.transform_point3(origin);
+        let local_direction = self.inverse.transform_vector3(direction);
+        self.shape.intersect(local_origin, local_direction, min_t, max_t)
+    }
+
+    fn occludes(&self, origin: Vec3, direction: Vec3, min_t: f32, max_t: f32) -> bool {
+        self.shape.occludes(self.inverse.transform_point3(origin), self.inverse.transform_vector3(direction), min_t, max_t)
+    }
+
+    // Scaling unevenly skews the tangent away from the normal, it's straightened back
+    fn surface_hit(&self, point: Vec3, part: usize) -> SurfaceHit {
+        let mut hit = self.shape.surface_hit(self.inverse.transform_point3(point), part);
+        hit.point = point;
+        hit.normal = self.normal_matrix.transform_vector3(hit.normal).normalize_or_zero();
+        let tangent = self.matrix.transform_vector3(hit.tangent);
+        let along = tangent - hit.normal * hit.normal.dot(tangent);
+        hit.tangent = if along.length_squared() > 1e-12 { along.normalize() } else { hit.normal.any_orthonormal_vector() };
+        hit
+    }
+
+    fn material(&self, part: usize) -> Option<MaterialId> {
+        self.material.or_else(|| self.shape.material(part))
+    }
+
+    fn material_parts(&self) -> Vec<usize> {
+        match self.material {
+            Some(_) => vec![0],
+            None => self.shape.material_parts()
+        }
+    }
+
+    // Grown by the most the matrix stretches any direction, whose square is the largest
+    // eigenvalue of MᵀM. That's no more than the largest sum along a row of it, which is exact
+    // for turning and scaling evenly, and no more than its trace, the square of the Frobenius
+    // norm, which can be closer for shears
+    fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let (center, radius) = self.shape.bounding_sphere()?;
+        let m = Mat3::from_mat4(self.matrix);
+        let squared = m.transpose() * m;
+        let rows = (0..3).map(|k| squared.row(k).abs().dot(Vec3::ONE)).fold(0.0, f32::max);
+        let trace = squared.x_axis.x + squared.y_axis.y + squared.z_axis.z;
+        Some((self.matrix.transform_point3(center), radius * rows.min(trace).sqrt()))
+    }
+
+    fn spans(&self, origin: Vec3, direction: Vec3) -> Vec<Span> {
+        self.shape.spans(self.inverse.transform_point3(origin), self.inverse.transform_vector3(direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::Sphere;
+
+    // Points all over the unit sphere, moved by the matrix
+    fn moved_points(matrix: Mat4) -> Vec<Vec3> {
+        let mut points = vec![];
+        for latitude in 0..=20 {
+            for longitude in 0..40 {
+                let (above, around) = (latitude as f32 / 20.0 * std::f32::consts::PI, longitude as f32 / 40.0 * std::f32::consts::TAU);
+                let point = Vec3::new(above.sin() * around.cos(), above.cos(), above.sin() * around.sin());
+                points.push(matrix.transform_point3(point));
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn bounding_sphere_holds_sheared_shapes() {
+        let shear = Mat4::from_cols_array(&[1.0, 0.0, 0.0, 0.0, 3.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.5, -1.0, 2.0, 1.0]);
+        let sheared = Transformed::new(Arc::new(Sphere { center: Vec3::ZERO, radius: 1.0 }) as Arc<dyn Shape>, shear);
+        let (center, radius) = sheared.bounding_sphere().unwrap();
+        let furthest = moved_points(shear).into_iter().map(|point| point.distance(center)).fold(0.0, f32::max);
+        assert!(furthest <= radius * 1.0001, "{} outside {}", furthest, radius);
+    }
+
+    #[test]
+    fn bounding_sphere_is_tight_for_turns_and_even_scales() {
+        let placement = Mat4::from_scale_rotation_translation(Vec3::splat(2.5), glam::Quat::from_rotation_y(0.7), Vec3::new(1.0, 2.0, 3.0));
+        let placed = Transformed::new(Arc::new(Sphere { center: Vec3::ZERO, radius: 1.0 }) as Arc<dyn Shape>, placement);
+        let (center, radius) = placed.bounding_sphere().unwrap();
+        assert!(center.distance(Vec3::new(1.0, 2.0, 3.0)) < 1e-5);
+        assert!((radius - 2.5).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,148 @@
+// Procedural daylight using the Preetham, Shirley and Smits analytic sky model
+// ("A Practical Analytic Model for Daylight", 1999).
+//
+// The model gives the luminance and chromaticity of the sky in any direction from the position
+// of the sun and the turbidity of the atmosphere (2 is a very clear day, 10 is hazy).
+
+use glam::Vec3;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+// Sky luminance comes out in kcd/m^2, this brings a clear noon sky to roughly 0.5
+const SKY_SCALE: f32 = 0.05;
+
+// Converts the sky radiance back to nits, for physical light units
+pub const NITS_PER_UNIT: f32 = 1000.0 / SKY_SCALE;
+
+// Illuminance of direct sunlight, in lux, before going through the atmosphere
+pub const SUN_ILLUMINANCE: f32 = 100_000.0;
+
+pub struct Sky {
+    sun_direction: Vec3,
+    turbidity: f32,
+    // Perez distribution coefficients for luminance and the two chromaticity coordinates
+    perez_y: [f32; 5],
+    perez_x_chroma: [f32; 5],
+    perez_y_chroma: [f32; 5],
+    // Luminance and chromaticity at the zenith
+    zenith: Vec3,
+    // Average light arriving from the whole sky dome onto an upward facing surface
+    ambient: Vec3
+}
+
+impl Sky {
+    // Elevation above the horizon and azimuth (0 looks down +Z, 90 looks down +X) in degrees
+    pub fn new(sun_elevation: f32, sun_azimuth: f32, turbidity: f32) -> Sky {
+        let elevation = sun_elevation.to_radians();
+        let azimuth = sun_azimuth.to_radians();
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            elevation.cos() * azimuth.cos()
+        );
+
+        let t = turbidity;
+        let perez_y = [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+        let perez_x_chroma = [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+        let perez_y_chroma = [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+
+        // Angle between the sun and the zenith
+        let theta_s = FRAC_PI_2 - elevation.max(0.0);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (t2, th, th2, th3) = (t * t, theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let mut sky = Sky {
+            sun_direction,
+            turbidity,
+            perez_y,
+            perez_x_chroma,
+            perez_y_chroma,
+            zenith: Vec3::new(zenith_luminance, zenith_x, zenith_y),
+            ambient: Vec3::ZERO
+        };
+        sky.ambient = sky.integrate_ambient();
+        sky
+    }
+
+    // Unit vector pointing towards the sun
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    // Light of the sun after going through the atmosphere, reddened when it's low on the horizon
+    pub fn sun_color(&self) -> Vec3 {
+        if self.sun_direction.y <= 0.0 {
+            return Vec3::ZERO;
+        }
+        // Relative optical mass of the air the sunlight goes through (Kasten and Young)
+        let zenith_angle = self.sun_direction.y.acos().to_degrees();
+        let air_mass = 1.0 / (self.sun_direction.y + 0.50572 * (96.07995 - zenith_angle).powf(-1.6364));
+        // Shorter wavelengths get scattered away first
+        let extinction = Vec3::new(0.012, 0.025, 0.055) * self.turbidity;
+        Vec3::new(
+            (-air_mass * extinction.x).exp(),
+            (-air_mass * extinction.y).exp(),
+            (-air_mass * extinction.z).exp()
+        )
+    }
+
+    pub fn ambient(&self) -> Vec3 {
+        self.ambient
+    }
+
+    // Linear RGB radiance of the sky seen along `direction`
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let d = direction.normalize();
+        // The model is only defined above the horizon, keep the horizon color below it
+        let cos_theta = d.y.max(0.01);
+        let gamma = d.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_s = self.sun_direction.y.max(0.0).acos();
+
+        let relative = |coefficients: &[f32; 5], zenith: f32| {
+            zenith * perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, theta_s)
+        };
+        let luminance = relative(&self.perez_y, self.zenith.x);
+        let x = relative(&self.perez_x_chroma, self.zenith.y);
+        let y = relative(&self.perez_y_chroma, self.zenith.z);
+
+        xyy_to_rgb(x, y, luminance * SKY_SCALE).max(Vec3::ZERO)
+    }
+
+    // Cosine weighted average of the sky over the upper hemisphere, using directions
+    // spread along a Fibonacci spiral
+    fn integrate_ambient(&self) -> Vec3 {
+        let samples = 128;
+        let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+        let mut total = Vec3::ZERO;
+        for k in 0..samples {
+            // Uniform in the projected disc, which makes the directions cosine distributed
+            let r = ((k as f32 + 0.5) / samples as f32).sqrt();
+            let phi = k as f32 * golden_angle;
+            let direction = Vec3::new(r * phi.cos(), (1.0 - r * r).sqrt(), r * phi.sin());
+            total += self.radiance(direction);
+        }
+        total / samples as f32
+    }
+}
+
+// Perez et al. sky luminance distribution
+fn perez(c: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Vec3::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z
+    )
+}
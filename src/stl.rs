@@ -0,0 +1,142 @@
+// Importer for STL files, the triangle soups 3D printing software exchanges, in both the binary
+// and the ASCII flavours.
+//
+// Only the triangles are read, colors and units aren't part of the format. The normal stored
+// with each triangle is only used to fix its winding, the mesh works out its own flat normals.
+
+use crate::shapes::Mesh;
+use glam::Vec3;
+use std::fs;
+
+pub fn load(path: &str) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&bytes).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Mesh, String> {
+    // Binary files can start with "solid" too, but their size always matches the triangle count
+    let binary_size = bytes.get(80..84).map(|count| 84 + 50 * u32::from_le_bytes(count.try_into().unwrap()) as usize);
+    let facets = if bytes.starts_with(b"solid") && binary_size != Some(bytes.len()) {
+        parse_ascii(&String::from_utf8_lossy(bytes))?
+    } else {
+        parse_binary(bytes)?
+    };
+
+    let mut vertices = Vec::with_capacity(facets.len() * 3);
+    let mut triangles = Vec::with_capacity(facets.len());
+    for (normal, [a, b, c]) in facets {
+        // Turned around when the corners go the other way from the stored normal
+        let corners = if (b - a).cross(c - a).dot(normal) < 0.0 { [a, c, b] } else { [a, b, c] };
+        let first = vertices.len();
+        vertices.extend(corners);
+        triangles.push([first, first + 1, first + 2]);
+    }
+    Ok(Mesh::new(vertices, triangles, vec![]))
+}
+
+// 80 bytes of header, the number of triangles, and 50 bytes per triangle: the normal, the three
+// corners and two bytes of attributes
+fn parse_binary(bytes: &[u8]) -> Result<Vec<(Vec3, [Vec3; 3])>, String> {
+    let count = bytes.get(80..84).ok_or("unexpected end of file")?;
+    let count = u32::from_le_bytes(count.try_into().unwrap()) as usize;
+    let data = bytes.get(84..84 + count * 50).ok_or("unexpected end of file")?;
+    let vector = |bytes: &[u8]| {
+        let float = |k: usize| f32::from_le_bytes(bytes[k * 4..k * 4 + 4].try_into().unwrap());
+        Vec3::new(float(0), float(1), float(2))
+    };
+    Ok(data
+        .chunks(50)
+        .map(|facet| (vector(&facet[0..12]), [vector(&facet[12..24]), vector(&facet[24..36]), vector(&facet[36..48])]))
+        .collect())
+}
+
+// solid name, then facet normal / outer loop / three vertex lines / endloop / endfacet for each
+// triangle, and endsolid
+fn parse_ascii(text: &str) -> Result<Vec<(Vec3, [Vec3; 3])>, String> {
+    let mut facets = vec![];
+    let mut normal = Vec3::ZERO;
+    let mut corners = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let vector = |values: &[&str]| -> Result<Vec3, String> {
+            let numbers: Result<Vec<f32>, _> = values.iter().map(|value| value.parse::<f32>()).collect();
+            match numbers.map_err(|_| error("invalid number"))?[..] {
+                [x, y, z] => Ok(Vec3::new(x, y, z)),
+                _ => Err(error("expected three numbers"))
+            }
+        };
+        match words.first().copied() {
+            Some("facet") => {
+                normal = if words.get(1) == Some(&"normal") { vector(&words[2..])? } else { Vec3::ZERO };
+                corners.clear();
+            },
+            Some("vertex") => corners.push(vector(&words[1..])?),
+            Some("endfacet") => match corners[..] {
+                [a, b, c] => facets.push((normal, [a, b, c])),
+                _ => return Err(error("facet without three vertices"))
+            },
+            _ => {}
+        }
+    }
+    Ok(facets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORNERS: [Vec3; 3] = [Vec3::ZERO, Vec3::X, Vec3::Y];
+
+    // A binary file with the triangle, stored with `normal`, after the header
+    fn binary(header: &[u8], normal: Vec3) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, 0);
+        bytes.extend(1u32.to_le_bytes());
+        for vector in [normal].iter().chain(&CORNERS) {
+            bytes.extend(vector.to_array().iter().flat_map(|value| value.to_le_bytes()));
+        }
+        bytes.extend([0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn reads_binary_files() {
+        let mesh = parse(&binary(b"exported", Vec3::Z)).unwrap();
+        assert_eq!(mesh.triangle_corners(), vec![CORNERS]);
+    }
+
+    #[test]
+    fn binary_files_can_start_with_solid() {
+        let mesh = parse(&binary(b"solid exported by a tool that shouldn't", Vec3::Z)).unwrap();
+        assert_eq!(mesh.triangle_corners(), vec![CORNERS]);
+    }
+
+    #[test]
+    fn reads_ascii_files() {
+        let text = "solid square
+            facet normal 0 0 1
+                outer loop
+                    vertex 0 0 0
+                    vertex 1 0 0
+                    vertex 0 1 0
+                endloop
+            endfacet
+        endsolid square";
+        let mesh = parse(text.as_bytes()).unwrap();
+        assert_eq!(mesh.triangle_corners(), vec![CORNERS]);
+    }
+
+    #[test]
+    fn triangles_are_turned_to_face_their_normal() {
+        let mesh = parse(&binary(b"", -Vec3::Z)).unwrap();
+        assert_eq!(mesh.triangle_corners(), vec![[Vec3::ZERO, Vec3::Y, Vec3::X]]);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = binary(b"", Vec3::Z);
+        assert!(parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(parse(b"solid\nfacet normal 0 0 1\nvertex 0 0 0\nendfacet\n").is_err());
+    }
+}
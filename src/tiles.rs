@@ -0,0 +1,137 @@
+// Splitting a frame into tiles and handing them out to the threads rendering it.
+//
+// Each thread has a queue of its own, dealt from the tiles in the order they should come out,
+// the middle of the picture first, and works through it from the front. A thread that runs out
+// steals from the back of the longest other queue, so they all finish about together however
+// uneven the tiles are.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// Square of canvas points, `x` and `y` being its bottom left corner, cut short along the right
+// and top edges of the canvas
+#[derive(Copy, Clone)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32
+}
+
+impl Tile {
+    pub fn contains(self, x: i32, y: i32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    // Calls `f` with the points of each square of `size` by `size` points of the tile in
+    // turn. Only every `step`th point along each side is taken, for a coarser look
+    pub fn each_square(self, size: i32, step: i32, mut f: impl FnMut(&[(i32, i32)])) {
+        let mut points = vec![];
+        let span = size * step;
+        for square_x in (self.x..self.x + self.width).step_by(span as usize) {
+            for square_y in (self.y..self.y + self.height).step_by(span as usize) {
+                points.clear();
+                for cx in (square_x..(square_x + span).min(self.x + self.width)).step_by(step as usize) {
+                    for cy in (square_y..(square_y + span).min(self.y + self.height)).step_by(step as usize) {
+                        points.push((cx, cy));
+                    }
+                }
+                f(&points);
+            }
+        }
+    }
+}
+
+// Tiles of `size` by `size` points covering a canvas centered on the origin, going around in a
+// spiral from the middle out
+pub fn spiral(width: i32, height: i32, size: i32) -> Vec<Tile> {
+    let (left, bottom) = (-width / 2, -height / 2);
+    let mut tiles = vec![];
+    for x in (left..width / 2).step_by(size as usize) {
+        for y in (bottom..height / 2).step_by(size as usize) {
+            tiles.push(Tile { x, y, width: size.min(width / 2 - x), height: size.min(height / 2 - y) });
+        }
+    }
+    // By the ring of tiles around the middle each one is in, then by the angle around it
+    let place = |tile: &Tile| {
+        let (dx, dy) = ((tile.x as f32 + tile.width as f32 / 2.0) / size as f32, (tile.y as f32 + tile.height as f32 / 2.0) / size as f32);
+        (dx.abs().max(dy.abs()).round(), dy.atan2(dx))
+    };
+    tiles.sort_by(|a, b| {
+        let ((ring_a, angle_a), (ring_b, angle_b)) = (place(a), place(b));
+        ring_a.total_cmp(&ring_b).then(angle_a.total_cmp(&angle_b))
+    });
+    tiles
+}
+
+pub struct TileQueue {
+    queues: Vec<Mutex<VecDeque<Tile>>>
+}
+
+impl TileQueue {
+    // Deals the tiles out to the workers one at a time, so every one starts near the middle
+    pub fn new(tiles: &[Tile], workers: usize) -> TileQueue {
+        let workers = workers.max(1);
+        let mut queues = vec![VecDeque::new(); workers];
+        for (k, &tile) in tiles.iter().enumerate() {
+            queues[k % workers].push_back(tile);
+        }
+        TileQueue { queues: queues.into_iter().map(Mutex::new).collect() }
+    }
+
+    // Next tile for the worker, None once they're all taken
+    pub fn next(&self, worker: usize) -> Option<Tile> {
+        if let Some(tile) = self.queues[worker].lock().unwrap().pop_front() {
+            return Some(tile);
+        }
+        // The tiles at the back of the longest queue are the ones its worker would get to last
+        loop {
+            let victim = (0..self.queues.len()).max_by_key(|&k| self.queues[k].lock().unwrap().len())?;
+            if let Some(tile) = self.queues[victim].lock().unwrap().pop_back() {
+                return Some(tile);
+            }
+            // Someone else got there first
+            if self.queues.iter().all(|queue| queue.lock().unwrap().is_empty()) {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn spiral_covers_the_canvas_once_from_the_middle() {
+        let (width, height) = (70, 45);
+        let tiles = spiral(width, height, 16);
+        let mut points = HashSet::new();
+        for tile in &tiles {
+            for cx in tile.x..tile.x + tile.width {
+                for cy in tile.y..tile.y + tile.height {
+                    assert!(points.insert((cx, cy)), "({}, {}) is in two tiles", cx, cy);
+                }
+            }
+        }
+        assert_eq!(points.len(), ((width / 2 * 2) * (height / 2 * 2)) as usize);
+        // The first tile is one of the ones around the middle
+        let first = tiles[0];
+        assert!(first.x <= 0 && first.x + first.width >= 0 && first.y <= 0 && first.y + first.height >= 0);
+    }
+
+    #[test]
+    fn queue_hands_every_tile_out_once() {
+        let tiles = spiral(200, 150, 16);
+        let queue = TileQueue::new(&tiles, 3);
+        // The first worker takes everything, stealing from the others once its own run out
+        let mut taken = vec![];
+        while let Some(tile) = queue.next(0) {
+            taken.push((tile.x, tile.y));
+        }
+        assert_eq!(taken.len(), tiles.len());
+        assert_eq!(taken.iter().collect::<HashSet<_>>().len(), tiles.len());
+        assert!(queue.next(1).is_none() && queue.next(2).is_none());
+    }
+}
@@ -0,0 +1,397 @@
+// The interactive window, through SDL2, only built with the `window` feature. Everything else
+// renders into frames of bytes without knowing about it, which are only put on the screen here,
+// so the renderer also builds where there's no SDL2.
+
+use crate::rng::Rng;
+use crate::tiles::{self, TileQueue};
+use crate::camera::Camera;
+use crate::{profile, to_color, Canvas, Framebuffer, LightType, Phase, Point, Renderer, Scene, PREVIEW_BLOCK, TILE_SIZE};
+use glam::{Quat, Vec2, Vec3};
+use sdl2::event::Event;
+use sdl2::keyboard::{KeyboardState, Keycode, Scancode};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, WindowCanvas};
+use std::f32::consts::PI;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// Longest frames can take while the scene is being changed, to keep up 30 frames a second
+const FRAME_BUDGET: Duration = Duration::from_millis(33);
+// How long the scene has to stay the same before it's rendered at full resolution again
+const IDLE_DELAY: Duration = Duration::from_millis(300);
+// Most frames averaged together while the scene stays the same, past which the picture hardly
+// gets any cleaner
+const MAX_FRAMES: u32 = 64;
+// The camera flies around with WASD, forward, left, back and right, and Q and E, down and up,
+// and turns by dragging with the left mouse button. Units a second it flies, and radians it
+// turns for every pixel dragged
+const FLY_SPEED: f32 = 2.0;
+const TURN_SPEED: f32 = 0.005;
+// How much closer the camera gets to what it looks at for every step of the mouse wheel, when
+// orbiting
+const DOLLY_STEP: f32 = 0.9;
+
+// What dragging with the mouse does, switched with Tab. Flying turns the camera where it is.
+// Orbiting swings it around what it looks at instead, the mouse wheel takes it closer or further
+// and dragging with the middle button moves both sideways, which is handier for looking at a
+// single model
+#[derive(Copy, Clone, PartialEq)]
+enum Controls {
+    Fly,
+    Orbit
+}
+
+// While the scene keeps changing, frames are rendered at a lower resolution, in blocks of
+// `block` by `block` pixels, so the window keeps up. The blocks grow when frames take too long
+// and shrink when there's time to spare. Once nothing has changed for a moment, the frame is
+// rendered again at full resolution
+struct DynamicResolution {
+    block: i32,
+    // When the scene last changed, None once it's been rendered at full resolution since
+    changed: Option<Instant>
+}
+
+impl DynamicResolution {
+    // After a quick frame that took `elapsed`. Halving the blocks makes frames take about four
+    // times as long
+    fn adapt(&mut self, elapsed: Duration) {
+        if elapsed > FRAME_BUDGET && self.block < PREVIEW_BLOCK {
+            self.block *= 2;
+        } else if elapsed < FRAME_BUDGET / 4 && self.block > 1 {
+            self.block /= 2;
+        }
+        self.changed = Some(Instant::now());
+    }
+}
+
+// Index of the light toggled by a number key
+fn light_index(keycode: Keycode) -> Option<usize> {
+    let keys = [
+        Keycode::Num1, Keycode::Num2, Keycode::Num3,
+        Keycode::Num4, Keycode::Num5, Keycode::Num6,
+        Keycode::Num7, Keycode::Num8, Keycode::Num9
+    ];
+    keys.iter().position(|&key| key == keycode)
+}
+
+// Draws a rendered frame to the window, with the light gizmos on top if asked to. The frame is
+// uploaded to the texture in one go and drawn over the whole window, which is much quicker than
+// drawing it point by point
+fn present(
+    frame: &Framebuffer,
+    scene: &Scene,
+    canvas: Canvas,
+    camera: Camera,
+    show_gizmos: bool,
+    sdl_canvas: &mut WindowCanvas,
+    texture: &mut Texture
+) {
+    let _span = profile::span(Phase::Present);
+    texture.update(None, &frame.pixels, frame.pitch()).unwrap();
+    sdl_canvas.copy(texture, None, None).unwrap();
+    if show_gizmos {
+        draw_light_gizmos(scene, canvas, camera, sdl_canvas);
+    }
+    sdl_canvas.present();
+}
+
+// Marks where the lights are: a cross for point lights, plus a line along the direction for
+// spot lights, and the outline of area lights. Lights without a position aren't drawn
+fn draw_light_gizmos(scene: &Scene, canvas: Canvas, camera: Camera, sdl_canvas: &mut WindowCanvas) {
+    let project = |p: Vec3| camera.project(canvas, p).map(|(x, y)| canvas.to_screen(x, y));
+    let draw_outline = |sdl_canvas: &mut WindowCanvas, points: &[Vec3]| {
+        for k in 0..points.len() {
+            if let (Some(a), Some(b)) = (project(points[k]), project(points[(k + 1) % points.len()])) {
+                sdl_canvas.draw_line(to_sdl(a), to_sdl(b)).unwrap();
+            }
+        }
+    };
+
+    for light in scene.lighting.iter().filter(|light| light.enabled) {
+        let position = match light.position {
+            Some(position) => position,
+            None => continue
+        };
+        // Full brightness version of the light color, so dim lights are still visible
+        let max_channel = light.color.max_element().max(f32::EPSILON);
+        let color = to_color(light.color / max_channel);
+        sdl_canvas.set_draw_color(sdl2::pixels::Color::RGB(color.r, color.g, color.b));
+
+        match light.kind {
+            LightType::Rect { u, v } | LightType::Portal { u, v } => {
+                let corners = [
+                    position - 0.5 * u - 0.5 * v,
+                    position + 0.5 * u - 0.5 * v,
+                    position + 0.5 * u + 0.5 * v,
+                    position - 0.5 * u + 0.5 * v
+                ];
+                draw_outline(sdl_canvas, &corners);
+            },
+            LightType::Disc { radius } => {
+                let (tangent, bitangent) = light.direction.unwrap().normalize().any_orthonormal_pair();
+                let segments = 24;
+                let circle: Vec<Vec3> = (0..segments)
+                    .map(|k| {
+                        let angle = k as f32 / segments as f32 * 2.0 * std::f32::consts::PI;
+                        position + radius * (angle.cos() * tangent + angle.sin() * bitangent)
+                    })
+                    .collect();
+                draw_outline(sdl_canvas, &circle);
+            },
+            _ => {
+                if let Some(center) = project(position) {
+                    let size = 5;
+                    let center = to_sdl(center);
+                    sdl_canvas.draw_line(center.offset(-size, 0), center.offset(size, 0)).unwrap();
+                    sdl_canvas.draw_line(center.offset(0, -size), center.offset(0, size)).unwrap();
+                }
+                if let (LightType::Spot { .. }, Some(direction)) = (light.kind, light.direction) {
+                    draw_outline(sdl_canvas, &[position, position + 0.5 * direction.normalize()]);
+                }
+            }
+        }
+    }
+}
+
+// How far the keys held down fly the camera in `elapsed` seconds, across, up and forward in
+// the picture
+fn fly_step(keys: &KeyboardState, elapsed: f32) -> Vec3 {
+    let axis = |positive, negative| {
+        let held = |key| if keys.is_scancode_pressed(key) { 1.0 } else { 0.0 };
+        held(positive) - held(negative)
+    };
+    Vec3::new(axis(Scancode::D, Scancode::A), axis(Scancode::E, Scancode::Q), axis(Scancode::W, Scancode::S)) * FLY_SPEED * elapsed
+}
+
+fn to_sdl(point: Point) -> sdl2::rect::Point {
+    sdl2::rect::Point::new(point.x, point.y)
+}
+
+// Opens the window and shows the scene in it, rendered again whenever it changes, until it's
+// closed. `output` is where to save the first full frame, if anywhere
+pub fn run(scene: &mut Scene, mut renderer: Renderer, mut camera: Camera, output: Option<&str>) {
+    let canvas = renderer.canvas;
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window("Raytracer", canvas.width as u32, canvas.height as u32)
+        .build()
+        .unwrap();
+    let mut sdl_canvas = window.into_canvas().present_vsync().build().unwrap();
+    // Made once for the whole time the window is open, every frame is uploaded to the same one
+    let texture_creator = sdl_canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, canvas.width as u32, canvas.height as u32).unwrap();
+
+    let mut show_gizmos = false;
+    let mut frame = renderer.render(scene, camera, PREVIEW_BLOCK, 1, |preview| {
+        present(preview, scene, canvas, camera, show_gizmos, &mut sdl_canvas, &mut texture)
+    });
+    present(&frame, scene, canvas, camera, show_gizmos, &mut sdl_canvas, &mut texture);
+    profile::report();
+    if let Some(output) = output {
+        frame.save(output).expect("couldn't save the frame");
+        println!("Saved {}", output);
+    }
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut resolution = DynamicResolution { block: 2, changed: None };
+    let mut last = Instant::now();
+    let mut controls = Controls::Fly;
+
+    'running: loop {
+        // Whether the scene or the camera changed since the last frame
+        let mut changed = false;
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                // Show or hide where the lights are
+                Event::KeyDown {
+                    keycode: Some(Keycode::G),
+                    ..
+                } => {
+                    show_gizmos = !show_gizmos;
+                    present(&frame, scene, canvas, camera, show_gizmos, &mut sdl_canvas, &mut texture);
+                },
+                // Turn the first nine lights on and off
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if light_index(keycode).is_some_and(|index| index < scene.lighting.len()) => {
+                    let index = light_index(keycode).unwrap();
+                    let light = &mut scene.lighting[index];
+                    light.enabled = !light.enabled;
+                    println!("Light {} {}", index + 1, if light.enabled { "on" } else { "off" });
+                    changed = true;
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => {
+                    controls = if controls == Controls::Fly { Controls::Orbit } else { Controls::Fly };
+                    println!("{} controls", if controls == Controls::Fly { "Fly" } else { "Orbit" });
+                },
+                // Look around, or swing around what the camera looks at. The picture follows the
+                // mouse when orbiting, so the camera goes the other way
+                Event::MouseMotion { mousestate, xrel, yrel, .. } if mousestate.left() => {
+                    let (x, y) = (xrel as f32 * TURN_SPEED, yrel as f32 * TURN_SPEED);
+                    match controls {
+                        Controls::Fly => camera.turn(x, -y),
+                        Controls::Orbit => camera.orbit(-x, y)
+                    }
+                    changed = true;
+                },
+                Event::MouseMotion { mousestate, xrel, yrel, .. } if mousestate.middle() && controls == Controls::Orbit => {
+                    let distance = camera.position.distance(camera.target);
+                    camera.pan(-xrel as f32 * TURN_SPEED * distance, yrel as f32 * TURN_SPEED * distance);
+                    changed = true;
+                },
+                Event::MouseWheel { y, .. } if controls == Controls::Orbit => {
+                    camera.dolly(DOLLY_STEP.powi(y));
+                    changed = true;
+                },
+                _ => {}
+            }
+        }
+        let step = fly_step(&event_pump.keyboard_state(), last.elapsed().as_secs_f32().min(0.1));
+        last = Instant::now();
+        if step != Vec3::ZERO {
+            camera.fly(step);
+            changed = true;
+        }
+        if changed {
+            let start = Instant::now();
+            frame = renderer.render(scene, camera, resolution.block, resolution.block, |_| {});
+            present(&frame, scene, canvas, camera, show_gizmos, &mut sdl_canvas, &mut texture);
+            resolution.adapt(start.elapsed());
+        }
+        if resolution.changed.is_some_and(|changed| changed.elapsed() > IDLE_DELAY) {
+            resolution.changed = None;
+            frame = renderer.render(scene, camera, PREVIEW_BLOCK, 1, |preview| {
+                present(preview, scene, canvas, camera, show_gizmos, &mut sdl_canvas, &mut texture)
+            });
+            present(&frame, scene, canvas, camera, show_gizmos, &mut sdl_canvas, &mut texture);
+            profile::report();
+        } else if resolution.changed.is_none() {
+            // Nothing has changed since the last frame of every pixel, keep refining it
+            if let Some(refined) = renderer.accumulate(scene, camera) {
+                frame = refined;
+                present(&frame, scene, canvas, camera, show_gizmos, &mut sdl_canvas, &mut texture);
+            }
+        }
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+    }
+}
+
+impl Camera {
+    // Moves the camera and what it looks at together, `step` across, up and forward in the
+    // picture. Up is the up of the camera rather than of the picture, so looking down doesn't
+    // make flying up go forward
+    fn fly(&mut self, step: Vec3) {
+        let (right, _, forward) = self.axes();
+        let offset = step.x * right + step.y * self.up.normalize() + step.z * forward;
+        self.position += offset;
+        self.target += offset;
+    }
+
+    // Turns the camera `yaw` radians to the right and `pitch` up, short of looking straight up
+    // or down, where which way is right would flip
+    fn turn(&mut self, yaw: f32, pitch: f32) {
+        let (right, _, forward) = self.axes();
+        let up = self.up.normalize();
+        let from_up = forward.angle_between(up);
+        let pitch = pitch.clamp(from_up - PI + 0.01, from_up - 0.01);
+        let rotation = Quat::from_axis_angle(up, yaw) * Quat::from_axis_angle(right, -pitch);
+        self.target = self.position + rotation * (self.target - self.position);
+    }
+
+    // Swings the camera `yaw` radians to the right around what it looks at and `pitch` up over
+    // it, short of straight over or under it
+    fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let (right, _, forward) = self.axes();
+        let up = self.up.normalize();
+        let from_up = forward.angle_between(up);
+        // Going up over the target turns the camera down to keep looking at it
+        let pitch = pitch.clamp(0.01 - from_up, PI - 0.01 - from_up);
+        let rotation = Quat::from_axis_angle(up, -yaw) * Quat::from_axis_angle(right, pitch);
+        self.position = self.target + rotation * (self.position - self.target);
+    }
+
+    // Moves the camera and what it looks at `x` to the right and `y` up in the picture
+    fn pan(&mut self, x: f32, y: f32) {
+        let (right, up, _) = self.axes();
+        let offset = x * right + y * up;
+        self.position += offset;
+        self.target += offset;
+    }
+
+    // Takes the camera `factor` times as far from what it looks at
+    fn dolly(&mut self, factor: f32) {
+        let offset = (self.position - self.target) * factor;
+        if offset.length() > 1e-3 {
+            self.position = self.target + offset;
+        }
+    }
+}
+
+impl Renderer {
+    // Adds another frame to the average of the ones since the scene last changed, with a ray
+    // through a different random point of each pixel, and returns the average. A still
+    // picture gets less noisy and smoother along the edges the longer it's left alone. None
+    // until a frame of every pixel has been rendered, and once MAX_FRAMES have been added
+    fn accumulate(&mut self, scene: &Scene, camera: Camera) -> Option<Framebuffer> {
+        if self.frames == 0 || self.frames >= MAX_FRAMES {
+            return None;
+        }
+        let canvas = self.canvas;
+        let tiles = tiles::spiral(canvas.width, canvas.height, TILE_SIZE);
+        let workers = std::thread::available_parallelism().map_or(1, |count| count.get());
+        let queue = TileQueue::new(&tiles, workers);
+        let frame_seed = (self.frames as u64).wrapping_mul(0x9e3779b97f4a7c15);
+
+        std::thread::scope(|threads| {
+            let (sender, receiver) = mpsc::channel();
+            for worker in 0..workers {
+                let (queue, sender) = (&queue, sender.clone());
+                threads.spawn(move || {
+                    while let Some(tile) = queue.next(worker) {
+                        let mut traced = vec![];
+                        tile.each_square(TILE_SIZE, 1, |points| {
+                            for &(cx, cy) in points {
+                                let mut rng = Rng::new(((cx as u32 as u64) << 32 | cy as u32 as u64) ^ frame_seed);
+                                let point = Vec2::new(cx as f32 + rng.next_f32() - 0.5, cy as f32 + rng.next_f32() - 0.5);
+                                let sample = Vec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32());
+                                let radiance = match camera.sample_ray(canvas, point, sample) {
+                                    Some((origin, direction)) => scene.shade_ray(origin, direction, scene.closest_intersection(origin, direction, camera.near, camera.far), camera.far),
+                                    None => Vec3::ZERO
+                                };
+                                traced.push((cx, cy, radiance));
+                            }
+                        });
+                        if sender.send(traced).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            for traced in receiver {
+                for (cx, cy, radiance) in traced {
+                    let screen = canvas.to_screen(cx, cy);
+                    if screen.y < canvas.height {
+                        self.accumulated[(screen.y * canvas.width + screen.x) as usize] += radiance;
+                    }
+                }
+            }
+        });
+        self.frames += 1;
+
+        let mut frame = Framebuffer::new(canvas);
+        for (index, &sum) in self.accumulated.iter().enumerate() {
+            frame.set(index, to_color(sum / self.frames as f32));
+        }
+        Some(frame)
+    }
+}